@@ -1,12 +1,55 @@
 //! Simple Soracom Harvest Data API client to get data entries and delete data entry.
 
-use crate::{endpoint::Endpoint, error::SoracomHarvestClientError};
-use chrono::{Duration, TimeZone, Utc};
-use reqwest::{blocking::Client, header::USER_AGENT};
+use crate::{
+    content_type::ContentType,
+    decoder::{decode, DecodeOptions},
+    endpoint::Endpoint,
+    error::SoracomHarvestClientError,
+    event::ApiEvent,
+};
+use chrono::{DateTime, Duration, SecondsFormat, TimeZone, Utc};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::{
+    blocking::Client,
+    header::{HeaderMap, DATE, USER_AGENT},
+};
 use serde::{Deserialize, Serialize};
-use std::fmt::{Display, Formatter};
+use soracom_harvest_client::{
+    dns::{DnsRetry, ResolvedHosts},
+    sender::{new_idempotency_key, Sender},
+};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::{Display, Formatter},
+    io::{BufRead, BufReader, Read},
+    net::SocketAddr,
+    sync::Mutex,
+    thread,
+    time::Instant,
+};
 use typed_builder::TypedBuilder;
 
+/// Characters to percent-encode in a path segment, i.e. everything except unreserved characters.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Number of latest data entries to probe in `estimate_query_cost`.
+const ESTIMATE_SAMPLE_SIZE: u32 = 10;
+
+/// Default interval between polls of [`SoracomHarvestClient::send_and_verify`].
+const SEND_AND_VERIFY_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Maximum number of polls in `measure_ingest_latency`, i.e. 1 minute with the default poll interval.
+const INGEST_LATENCY_MAX_ATTEMPTS: u32 = 120;
+
+/// Lifetime of a token assumed if the auth response doesn't tell it, conservatively shorter than the API
+/// default of 24 hours, so that a token is never considered valid after it has actually expired.
+pub const DEFAULT_TOKEN_TIMEOUT_SECONDS: i64 = 60 * 60;
+
 #[derive(Serialize, Deserialize, Debug)]
 struct AuthRequest {
     #[serde(rename = "authKeyId")]
@@ -25,6 +68,8 @@ struct AuthResponse {
     pub user_name: Option<String>,
     #[serde(rename = "operatorId")]
     pub operator_id: Option<String>,
+    #[serde(rename = "tokenTimeoutSeconds")]
+    pub token_timeout_seconds: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -33,6 +78,11 @@ struct HarvestDataResponse {
     pub data: Vec<Data>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+struct SubscriberResponse {
+    pub imsi: String,
+}
+
 /// Single entity of Soracom Harvest Data.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Data {
@@ -47,9 +97,82 @@ pub struct Data {
     /// it could be base64-encoded data. If value of the `payload` property can be decoded as base64,
     /// and can be represented as UTF-8 string, and the decoded string has only ASCII printable characters,
     /// return `{"value": "<decoded string>"}` as the content. Otherwise return original content as is.
+    /// The decoders to try, and the representation of binary payloads can be configured, see
+    /// [`decoder`](crate::decoder).
     pub content: String,
 }
 
+impl Data {
+    /// Returns the content type of the entity as [`ContentType`], ignoring casing and parameters.
+    pub fn content_type_enum(&self) -> ContentType {
+        ContentType::from(self.content_type.as_str())
+    }
+
+    /// Returns the time of the entity in the timezone for display, e.g. `chrono_tz::Asia::Tokyo`, or `None` if
+    /// it is out of range. The underlying `time` is kept as is. Requires `chrono-tz` feature.
+    #[cfg(feature = "chrono-tz")]
+    pub fn datetime_in(&self, tz: chrono_tz::Tz) -> Option<DateTime<chrono_tz::Tz>> {
+        tz.timestamp_millis_opt(self.time).single()
+    }
+
+    /// Returns the entity as a flat map of strings, e.g. to interpolate into a message template.
+    ///
+    /// - `time`: epoch time in milliseconds, e.g. `1669024325202`
+    /// - `time_iso`: RFC 3339 time in UTC, e.g. `2022-11-21T09:52:05.202Z`, unless `time` is out of range
+    /// - `content_type`: the content type as is
+    /// - each key of `content` if it's a JSON object, with nested keys joined by dots e.g. `sensor.temp`, and
+    ///   array elements by index e.g. `readings.0`. Strings are kept as is, and other values are written as
+    ///   JSON e.g. `true` or `null`. Otherwise `content` is the content as is.
+    ///
+    /// The keys above take precedence over keys of `content` with the same name.
+    pub fn to_flat_map(&self) -> HashMap<String, String> {
+        let mut map = HashMap::new();
+        match serde_json::from_str::<serde_json::Value>(&self.content) {
+            Ok(value @ serde_json::Value::Object(_)) => flatten_into(&mut map, None, &value),
+            _ => {
+                map.insert("content".to_string(), self.content.clone());
+            }
+        }
+
+        map.insert("time".to_string(), self.time.to_string());
+        if let Some(time) = Utc.timestamp_millis_opt(self.time).single() {
+            map.insert(
+                "time_iso".to_string(),
+                time.to_rfc3339_opts(SecondsFormat::Millis, true),
+            );
+        }
+        map.insert("content_type".to_string(), self.content_type.clone());
+
+        map
+    }
+}
+
+/// Inserts `value` into `map` with `key`, flattening objects and arrays into dotted keys.
+fn flatten_into(map: &mut HashMap<String, String>, key: Option<String>, value: &serde_json::Value) {
+    let join = |k: &dyn Display| match &key {
+        Some(key) => format!("{key}.{k}"),
+        None => k.to_string(),
+    };
+    match value {
+        serde_json::Value::Object(object) => {
+            for (k, v) in object {
+                flatten_into(map, Some(join(k)), v);
+            }
+        }
+        serde_json::Value::Array(array) => {
+            for (i, v) in array.iter().enumerate() {
+                flatten_into(map, Some(join(&i)), v);
+            }
+        }
+        serde_json::Value::String(s) => {
+            map.insert(key.unwrap_or_default(), s.clone());
+        }
+        v => {
+            map.insert(key.unwrap_or_default(), v.to_string());
+        }
+    }
+}
+
 impl Display for Data {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -63,9 +186,162 @@ impl Display for Data {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Base64EncodedPayload {
-    pub payload: String,
+/// Coverage of the expected keys in data entries, see [`SoracomHarvestClient::check_schema`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SchemaReport {
+    /// Number of data entries examined.
+    pub entries: usize,
+    /// Number of data entries containing each expected key.
+    pub counts: BTreeMap<String, usize>,
+}
+
+impl SchemaReport {
+    /// Returns the expected keys missing from some data entries, in ascending order.
+    pub fn missing_keys(&self) -> Vec<&str> {
+        self.counts
+            .iter()
+            .filter(|(_, count)| **count < self.entries)
+            .map(|(key, _)| key.as_str())
+            .collect()
+    }
+}
+
+/// Counts of lines imported with [`SoracomHarvestClient::import_ndjson`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Number of data entries sent successfully.
+    pub sent: usize,
+    /// Number of data entries which failed to send.
+    pub failed: usize,
+    /// Number of lines skipped as they aren't a data entry.
+    pub malformed: usize,
+}
+
+/// Rate limit of the SORACOM API, from the `x-ratelimit-*` headers of a response. Each field is `None` if
+/// the header is missing or malformed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitInfo {
+    /// Maximum number of requests allowed in the current window, from `x-ratelimit-limit`.
+    pub limit: Option<u64>,
+    /// Number of requests remaining in the current window, from `x-ratelimit-remaining`.
+    pub remaining: Option<u64>,
+    /// When the current window resets, from `x-ratelimit-reset`, as is.
+    pub reset: Option<u64>,
+}
+
+impl RateLimitInfo {
+    /// Returns the rate limit from the headers, or `None` if none of the headers is present.
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let get = |name: &str| headers.get(name)?.to_str().ok()?.trim().parse().ok();
+        let info = RateLimitInfo {
+            limit: get("x-ratelimit-limit"),
+            remaining: get("x-ratelimit-remaining"),
+            reset: get("x-ratelimit-reset"),
+        };
+
+        (info != RateLimitInfo::default()).then_some(info)
+    }
+}
+
+/// Estimate of the data entries a query would transfer, see
+/// [`estimate_query_cost`](SoracomHarvestClient::estimate_query_cost).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryEstimate {
+    /// Estimated number of data entries, up to the limit.
+    pub entries: u32,
+    /// Estimated number of bytes of the data entries as JSON, excluding HTTP overhead.
+    pub bytes: u64,
+    /// Whether the estimate is exact, i.e. the probe fetched all the entries.
+    pub exact: bool,
+}
+
+/// Data entry with the endpoint it is fetched from, see
+/// [`get_data_entries_across_sourced`](SoracomHarvestClient::get_data_entries_across_sourced).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourcedData {
+    /// Data entry.
+    pub data: Data,
+    /// Endpoint the data entry is fetched from, i.e. where it is stored.
+    pub endpoint: Endpoint,
+}
+
+/// Data entries of a paginated pull capped by bytes, see
+/// [`get_all_data_entries_capped`](SoracomHarvestClient::get_all_data_entries_capped).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CappedData {
+    /// Data entries fetched, latest first.
+    pub data: Vec<Data>,
+    /// Whether the pagination stopped at the cap, leaving older entries within the search range unfetched.
+    pub truncated: bool,
+}
+
+/// Returns an HTTP client with the DNS resolution overrides.
+fn http_client(resolve: &[(String, SocketAddr)]) -> Client {
+    resolve
+        .iter()
+        .fold(Client::builder(), |builder, (host, addr)| {
+            builder.resolve(host, *addr)
+        })
+        .build()
+        .expect("Failed to build HTTP client")
+}
+
+/// What to do when the API returns more data entries than the requested `limit`, see
+/// [`SoracomHarvestClient`].
+#[derive(Debug, Default, Eq, PartialEq, Clone, Copy)]
+pub enum OverLimit {
+    /// Keep the first `limit` entries, i.e. the latest ones, and log a warning.
+    #[default]
+    Truncate,
+
+    /// Return [`SoracomHarvestClientError::TooManyEntries`].
+    Error,
+}
+
+/// Returns timestamps shared by multiple entries, in ascending order. As
+/// [`delete_data_entry`](SoracomHarvestClient::delete_data_entry) identifies an entry by IMSI and timestamp,
+/// check this before deleting entries by their timestamps, as deleting one of them could be ambiguous.
+pub fn find_duplicate_timestamps(entries: &[Data]) -> Vec<i64> {
+    let mut counts: BTreeMap<i64, usize> = BTreeMap::new();
+    for d in entries {
+        *counts.entry(d.time).or_default() += 1;
+    }
+
+    counts
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(time, _)| time)
+        .collect()
+}
+
+/// Returns the union of data entries from a local cache and a fresh fetch, e.g. to reconcile an offline
+/// cache after going online, in descending order (latest data entry first) like the API. Entries identical
+/// in time, content type and content are deduped, while distinct entries sharing a timestamp are all kept,
+/// as Harvest may store multiple entries at the same timestamp. Among the entries sharing a timestamp, ones
+/// from `fresh` come first, then ones only in `cached`, each in their original order.
+pub fn merge_data(cached: Vec<Data>, fresh: Vec<Data>) -> Vec<Data> {
+    let mut merged = fresh;
+    merged.extend(cached);
+    // stable, so that entries from `fresh` stay before ones from `cached` for the same timestamp
+    merged.sort_by_key(|d| Reverse(d.time));
+
+    let mut seen = HashSet::new();
+    merged.retain(|d| seen.insert((d.time, d.content_type.clone(), d.content.clone())));
+
+    merged
+}
+
+/// Returns pairs of the time and the number at `json_pointer` e.g. `/sensor/temp` (RFC 6901) in the content of
+/// each entry, in the order of `entries`, for plotting. Entries are skipped if the content isn't JSON, or the
+/// value at the pointer is missing or isn't a number; a numeric string e.g. `"20.5"` isn't a number.
+pub fn to_series(entries: &[Data], json_pointer: &str) -> Vec<(i64, f64)> {
+    entries
+        .iter()
+        .filter_map(|d| {
+            let content: serde_json::Value = serde_json::from_str(&d.content).ok()?;
+            Some((d.time, content.pointer(json_pointer)?.as_f64()?))
+        })
+        .collect()
 }
 
 /// Client for Soracom Harvest Data.
@@ -73,7 +349,41 @@ struct Base64EncodedPayload {
 /// Use `.builder()` to construct a new, with following methods.
 ///
 /// - Required: `auth_key_id` and `auth_key_secret`
-/// - Optional: `endpoint`
+/// - Optional: `endpoint`, `https_only`, `strict_defaults`, `sender`, `decode_options`, `dns_retry`,
+///   `resolve`, `over_limit`, `auth_endpoint`
+///
+/// The scheme of [`Endpoint::Custom`] e.g. `http://localhost:8080` is honored as is, for local testing or a
+/// proxy terminating TLS. With `https_only(true)`, requests to a non-`https` endpoint fail with
+/// [`SoracomHarvestClientError::InsecureEndpoint`] instead, so that credentials never go over plain HTTP.
+///
+/// With `strict_defaults(true)`, `from`, `to` and `limit` must be given explicitly to
+/// [`get_data_entries`](Self::get_data_entries) and friends, otherwise
+/// [`SoracomHarvestClientError::MissingParameter`] is returned, instead of silently querying the last 24
+/// hours or up to 100 entries.
+///
+/// `sender` is the [`Sender`] used by [`send_and_verify`](Self::send_and_verify) to send a message, which
+/// defaults to the one with default endpoints.
+///
+/// `decode_options` configures how the payload of data entries is decoded, e.g. the order of decoders to
+/// try, which defaults to base64 only, or the key of the decoded value. See [`decoder`](crate::decoder) for
+/// details.
+///
+/// `dns_retry` retries resolving the host of the endpoint with backoff before the first request to it, as DNS
+/// may not be ready for a few seconds after the modem connects. Returns [`SoracomHarvestClientError::Resolve`]
+/// if it still fails. Defaults to [`DnsRetry::default`].
+///
+/// `resolve` overrides DNS resolution of hostnames to socket addresses e.g.
+/// `[("g.api.soracom.io".to_string(), "10.0.0.1:443".parse().unwrap())]`, to point the client at a mock or a
+/// private deployment without DNS changes. The port of the address is ignored as with
+/// [`ClientBuilder::resolve`](reqwest::blocking::ClientBuilder::resolve), so give the port in the endpoint
+/// instead, if it isn't the default for the scheme. These hosts are never resolved with `dns_retry`.
+///
+/// `auth_endpoint` overrides the endpoint to authenticate with, for private deployments serving `/v1/auth`
+/// from another host than `/v1/data`. Defaults to `endpoint`. It's subject to `https_only` as well.
+///
+/// `over_limit` guards against the API returning more data entries than the requested `limit` for a page,
+/// which it shouldn't. Defaults to [`OverLimit::Truncate`], which keeps the first `limit` entries with a
+/// warning; [`OverLimit::Error`] fails with [`SoracomHarvestClientError::TooManyEntries`] instead.
 ///
 /// Then call `.auth()` to authenticate.
 /// The call will setup `api_key`, `token`, `user_name`, `operator_id` for following `.get_data_entries()` calls.
@@ -108,6 +418,18 @@ pub struct SoracomHarvestClient {
     /// Endpoint for this client.
     #[builder(setter(into), default = Endpoint::Global)]
     pub endpoint: Endpoint,
+    #[builder(default, setter(strip_option, into))]
+    auth_endpoint: Option<Endpoint>,
+    #[builder(default)]
+    https_only: bool,
+    #[builder(default)]
+    strict_defaults: bool,
+    #[builder(default = Sender::builder().build())]
+    sender: Sender,
+    #[builder(default)]
+    decode_options: DecodeOptions,
+    #[builder(default)]
+    dns_retry: DnsRetry,
     #[builder(default)]
     api_key: String,
     #[builder(default)]
@@ -118,8 +440,18 @@ pub struct SoracomHarvestClient {
     #[builder(default)]
     /// Operator ID for the authentication information.
     pub operator_id: Option<String>,
+    #[builder(default, setter(into))]
+    resolve: Vec<(String, SocketAddr)>,
     #[builder(default)]
+    over_limit: OverLimit,
+    #[builder(default = http_client(&resolve))]
     client: Client,
+    #[builder(default, setter(skip))]
+    last_rate_limit: Mutex<Option<RateLimitInfo>>,
+    #[builder(default, setter(skip))]
+    token_expires_at: Option<DateTime<Utc>>,
+    #[builder(default, setter(skip))]
+    resolved: ResolvedHosts,
 }
 
 impl Display for SoracomHarvestClient {
@@ -128,50 +460,151 @@ impl Display for SoracomHarvestClient {
             f,
             "- Endpoint: {}\n- API key: {}...\n- Token: {}...\n- User name: {:?}\n- Operator ID: {:?}",
             self.endpoint,
-            self.api_key
-                .chars()
-                .into_iter()
-                .take(20)
-                .collect::<String>(),
-            self.token.chars().into_iter().take(20).collect::<String>(),
+            self.api_key.chars().take(20).collect::<String>(),
+            self.token.chars().take(20).collect::<String>(),
             self.user_name,
             self.operator_id
         )
     }
 }
 
+impl Clone for SoracomHarvestClient {
+    /// Returns a client with the same configuration and authentication. The clone doesn't share
+    /// [`last_rate_limit`](Self::last_rate_limit) with the original.
+    fn clone(&self) -> Self {
+        SoracomHarvestClient {
+            api_key: self.api_key.clone(),
+            token: self.token.clone(),
+            user_name: self.user_name.clone(),
+            operator_id: self.operator_id.clone(),
+            token_expires_at: self.token_expires_at,
+            ..self.with_endpoint(self.endpoint.clone())
+        }
+    }
+}
+
 impl SoracomHarvestClient {
     /// Authenticate with `auth_key_id` and `auth_key_secret` which were provided while creating a struct with `.builder()`.
     pub fn auth(&self) -> Result<Self, SoracomHarvestClientError> {
+        let start = Instant::now();
+        // count the lifetime from before the request, to be on the safe side
+        let issued_at = Utc::now();
         let response = self
             .client
-            .post(format!("{}/v1/auth", self.endpoint))
+            .post(format!(
+                "{}/v1/auth",
+                self.checked_url(self.auth_endpoint.as_ref().unwrap_or(&self.endpoint))?
+            ))
             .json(&AuthRequest {
                 auth_key_id: self.auth_key_id.clone(),
                 auth_key: self.auth_key_secret.clone(),
             })
-            .send()?
-            .json::<AuthResponse>()?;
+            .send()?;
+        ApiEvent {
+            status: Some(response.status().as_u16()),
+            ..ApiEvent::new("auth", start)
+        }
+        .emit();
+        let response = response.json::<AuthResponse>()?;
 
         Ok(SoracomHarvestClient {
-            auth_key_id: self.auth_key_id.clone(),
-            auth_key_secret: self.auth_key_secret.clone(),
-            endpoint: self.endpoint.clone(),
             api_key: response.api_key,
             token: response.token,
             user_name: response.user_name,
             operator_id: response.operator_id,
-            client: self.client.clone(),
+            token_expires_at: Some(
+                issued_at
+                    + Duration::seconds(
+                        response
+                            .token_timeout_seconds
+                            .unwrap_or(DEFAULT_TOKEN_TIMEOUT_SECONDS),
+                    ),
+            ),
+            ..self.with_endpoint(self.endpoint.clone())
         })
     }
 
+    /// Returns data entries sent from a SIM under both global and japan coverage, for accounts with SIMs
+    /// under both. See [`get_data_entries_across`](Self::get_data_entries_across) for details.
+    pub fn get_data_entries_all_coverages(
+        &self,
+        imsi: impl Into<String>,
+        from: Option<i64>,
+        to: Option<i64>,
+        limit: Option<u32>,
+    ) -> Result<Vec<Data>, SoracomHarvestClientError> {
+        self.get_data_entries_across(&[Endpoint::Global, Endpoint::Japan], imsi, from, to, limit)
+    }
+
+    /// Returns data entries sent from a SIM to each of the endpoints, merged with [`merge_data`] into a
+    /// single series in descending order, with identical entries deduped. `limit` applies to each endpoint.
+    ///
+    /// Each endpoint requires its own authentication, so this authenticates once per endpoint with the
+    /// credentials of this client, regardless of whether this client is authenticated, and then fetches.
+    /// Calling this repeatedly repeats the authentications.
+    pub fn get_data_entries_across(
+        &self,
+        endpoints: &[Endpoint],
+        imsi: impl Into<String>,
+        from: Option<i64>,
+        to: Option<i64>,
+        limit: Option<u32>,
+    ) -> Result<Vec<Data>, SoracomHarvestClientError> {
+        let imsi = imsi.into();
+
+        let mut merged = Vec::new();
+        for endpoint in endpoints {
+            let data = self
+                .with_endpoint(endpoint.clone())
+                .auth()?
+                .get_data_entries(&imsi, from, to, limit)?;
+            merged = merge_data(merged, data);
+        }
+
+        Ok(merged)
+    }
+
+    /// Same as [`get_data_entries_across`](Self::get_data_entries_across), but returns each data entry with
+    /// the endpoint it is fetched from, e.g. to route [`delete_data_entry`](Self::delete_data_entry) to a
+    /// client for the endpoint. Identical entries from different endpoints are kept, as each is stored
+    /// separately. Entries sharing a timestamp are ordered as `endpoints`.
+    pub fn get_data_entries_across_sourced(
+        &self,
+        endpoints: &[Endpoint],
+        imsi: impl Into<String>,
+        from: Option<i64>,
+        to: Option<i64>,
+        limit: Option<u32>,
+    ) -> Result<Vec<SourcedData>, SoracomHarvestClientError> {
+        let imsi = imsi.into();
+
+        let mut sourced = Vec::new();
+        for endpoint in endpoints {
+            let data = self
+                .with_endpoint(endpoint.clone())
+                .auth()?
+                .get_data_entries(&imsi, from, to, limit)?;
+            sourced.extend(data.into_iter().map(|data| SourcedData {
+                data,
+                endpoint: endpoint.clone(),
+            }));
+        }
+        sourced.sort_by_key(|s| Reverse(s.data.time));
+
+        Ok(sourced)
+    }
+
     /// Returns a vec of data entries sent from a SIM based on IMSI provided.
-    /// Sort order is always descending (latest data entry first). No pagination support.
+    /// Sort order is always descending (latest data entry first). No pagination support; entries beyond
+    /// `limit` are not retrieved.
     ///
     /// - `imsi`: IMSI of the target SIM.
     /// - `from`: Start time for the data entries search range (unix time in milliseconds).
     /// - `to`: End time for the data entries search range (unix time in milliseconds).
     /// - `limit`: Maximum number of data entries to retrieve. Should be between 1 and 1000.
+    ///
+    /// `from`, `to` and `limit` default to 24 hours ago, now and 100 respectively, unless the client is in
+    /// strict mode.
     pub fn get_data_entries(
         &self,
         imsi: impl Into<String>,
@@ -179,117 +612,2296 @@ impl SoracomHarvestClient {
         to: Option<i64>,
         limit: Option<u32>,
     ) -> Result<Vec<Data>, SoracomHarvestClientError> {
-        let from = from.unwrap_or_else(|| (Utc::now() - Duration::days(1)).timestamp_millis());
-        let to = to.unwrap_or_else(|| Utc::now().timestamp_millis());
-        let limit = limit.unwrap_or(100);
+        let (from, to) = self.search_range(from, to)?;
+        let limit = match limit {
+            Some(limit) => limit,
+            None if self.strict_defaults => {
+                return Err(SoracomHarvestClientError::MissingParameter("limit"))
+            }
+            None => 100,
+        };
 
-        let response: HarvestDataResponse = self
-            .client
-            .get(format!(
-                "{}/v1/data/Subscriber/{}",
-                &self.endpoint,
-                imsi.into()
-            ))
-            .header(USER_AGENT, "libshsqlite")
-            .header("X-Soracom-Api-Key", &self.api_key)
-            .header("X-Soracom-Token", &self.token)
-            .header("X-Soracom-Lang", "en")
-            .query(&[
-                ("from", from.to_string()),
-                ("to", to.to_string()),
-                ("sort", "desc".to_string()),
-                ("limit", limit.to_string()),
-            ])
-            .send()?
-            .json()?;
+        let (data, _) = self.get_data_entries_page(&imsi.into(), from, to, limit, None)?;
 
-        let mut result: Vec<Data> = Vec::new();
-        for d in response.data {
-            result.push(Data {
-                content: Self::try_decode(d.content),
-                content_type: d.content_type,
-                time: d.time,
-            })
+        Ok(data)
+    }
+
+    /// Same as [`get_data_entries`](Self::get_data_entries), but returns the data entries keyed by
+    /// timestamp, in ascending order, for point lookups and merging windows. If multiple entries share a
+    /// timestamp, the last one in the response wins; use [`find_duplicate_timestamps`] to detect them
+    /// beforehand, or [`bucketize`](Self::bucketize) with 1 millisecond buckets to keep all of them.
+    pub fn get_data_entries_map(
+        &self,
+        imsi: impl Into<String>,
+        from: Option<i64>,
+        to: Option<i64>,
+        limit: Option<u32>,
+    ) -> Result<BTreeMap<i64, Data>, SoracomHarvestClientError> {
+        Ok(self
+            .get_data_entries(imsi, from, to, limit)?
+            .into_iter()
+            .map(|d| (d.time, d))
+            .collect())
+    }
+
+    /// Same as [`get_data_entries`](Self::get_data_entries), but returns the data entries serialized as
+    /// MessagePack, which is smaller and faster to parse than JSON, for archival. See
+    /// [`msgpack`](crate::msgpack) for the format. Requires `msgpack` feature.
+    #[cfg(feature = "msgpack")]
+    pub fn get_data_entries_msgpack(
+        &self,
+        imsi: impl Into<String>,
+        from: Option<i64>,
+        to: Option<i64>,
+        limit: Option<u32>,
+    ) -> Result<Vec<u8>, SoracomHarvestClientError> {
+        crate::msgpack::to_vec(&self.get_data_entries(imsi, from, to, limit)?)
+    }
+
+    /// Returns an estimate of how many data entries, and roughly how many bytes,
+    /// [`get_data_entries`](Self::get_data_entries) with the same arguments would transfer, e.g. to warn
+    /// users of a metered connection before fetching.
+    ///
+    /// As the API has no way to count entries, this probes up to 10 latest entries, and extrapolates the
+    /// number of entries within the search range from their interval, and the bytes from their average size.
+    /// The estimate is exact if the probe yields all the entries, but otherwise assumes entries are sent at
+    /// a steady rate, so bursts or gaps within the search range skew it.
+    pub fn estimate_query_cost(
+        &self,
+        imsi: impl Into<String>,
+        from: Option<i64>,
+        to: Option<i64>,
+        limit: Option<u32>,
+    ) -> Result<QueryEstimate, SoracomHarvestClientError> {
+        let (from, to) = self.search_range(from, to)?;
+        let limit = match limit {
+            Some(limit) => limit,
+            None if self.strict_defaults => {
+                return Err(SoracomHarvestClientError::MissingParameter("limit"))
+            }
+            None => 100,
+        };
+
+        self.estimate(&imsi.into(), from, to, limit)
+    }
+
+    /// Returns an estimate of how many bytes all data entries within the search range are, e.g. as
+    /// [`get_all_data_entries_capped`](Self::get_all_data_entries_capped) or
+    /// [`entries_iter`](Self::entries_iter) would transfer, to warn users before a large pull.
+    ///
+    /// This is an estimate; it fetches a single page of up to 10 latest entries, and extrapolates as
+    /// [`estimate_query_cost`](Self::estimate_query_cost) does, without the limit.
+    ///
+    /// - `imsi`: IMSI of the target SIM.
+    /// - `from`: Start time for the data entries search range (unix time in milliseconds).
+    /// - `to`: End time for the data entries search range (unix time in milliseconds).
+    pub fn estimate_window_size(
+        &self,
+        imsi: impl Into<String>,
+        from: i64,
+        to: i64,
+    ) -> Result<usize, SoracomHarvestClientError> {
+        let estimate = self.estimate(&imsi.into(), from, to, u32::MAX)?;
+
+        Ok(usize::try_from(estimate.bytes).unwrap_or(usize::MAX))
+    }
+
+    /// Returns roughly `sample_size` data entries spread across the search range, in descending order, for a
+    /// quick preview of a large range without fetching all the entries. `sample_size` is capped at 1000.
+    ///
+    /// This is approximate; it probes the number of entries as
+    /// [`estimate_query_cost`](Self::estimate_query_cost) does, and fetches the latest `sample_size` entries
+    /// if the estimate is within it. Otherwise, it splits the search range into `sample_size` slices of the
+    /// same duration, and fetches the latest entry of each slice, with a request per slice. Slices without
+    /// entries yield nothing, so gaps make the sample smaller, and bursts are under-represented.
+    ///
+    /// - `imsi`: IMSI of the target SIM.
+    /// - `from`: Start time for the data entries search range (unix time in milliseconds).
+    /// - `to`: End time for the data entries search range (unix time in milliseconds).
+    /// - `sample_size`: Approximate number of data entries to retrieve.
+    pub fn get_sampled_entries(
+        &self,
+        imsi: impl Into<String>,
+        from: i64,
+        to: i64,
+        sample_size: u32,
+    ) -> Result<Vec<Data>, SoracomHarvestClientError> {
+        let imsi = imsi.into();
+        let sample_size = sample_size.min(1000);
+        if sample_size == 0 || from > to {
+            return Ok(Vec::new());
+        }
+
+        if self.estimate(&imsi, from, to, u32::MAX)?.entries <= sample_size {
+            let (data, _) = self.get_data_entries_page(&imsi, from, to, sample_size, None)?;
+            return Ok(data);
+        }
+
+        let width = (to - from) / sample_size as i64 + 1;
+        let mut data = Vec::with_capacity(sample_size as usize);
+        for i in 0..sample_size as i64 {
+            let slice_to = to - i * width;
+            if slice_to < from {
+                break;
+            }
+            let slice_from = (slice_to - width + 1).max(from);
+            let (latest, _) = self.get_data_entries_page(&imsi, slice_from, slice_to, 1, None)?;
+            data.extend(latest);
         }
 
-        Ok(result)
+        Ok(data)
     }
 
-    /// Deletes a data entry identified with IMSI and timestamp.
+    /// Returns an estimate of the data entries within the search range, up to `limit`, by probing the latest
+    /// entries.
+    fn estimate(
+        &self,
+        imsi: &str,
+        from: i64,
+        to: i64,
+        limit: u32,
+    ) -> Result<QueryEstimate, SoracomHarvestClientError> {
+        let (sample, next_key) =
+            self.get_data_entries_page(imsi, from, to, limit.min(ESTIMATE_SAMPLE_SIZE), None)?;
+        let (latest, oldest) = match (sample.first(), sample.last()) {
+            (Some(latest), Some(oldest)) => (latest.time, oldest.time),
+            _ => {
+                return Ok(QueryEstimate {
+                    entries: 0,
+                    bytes: 0,
+                    exact: true,
+                })
+            }
+        };
+        let bytes_per_entry = sample
+            .iter()
+            .map(|d| serde_json::to_string(d).map_or(0, |s| s.len() as u64 + 1))
+            .sum::<u64>()
+            / sample.len() as u64;
+
+        let count = sample.len() as u32;
+        let exact = next_key.is_none() || count >= limit;
+        let entries = if exact {
+            count
+        } else if latest == oldest {
+            // all at the same timestamp; no interval to extrapolate from
+            limit
+        } else {
+            let interval = (latest - oldest) as f64 / (count - 1) as f64;
+            let rest = ((oldest - from) as f64 / interval) as u64;
+            (count as u64 + rest).min(limit as u64) as u32
+        };
+
+        Ok(QueryEstimate {
+            entries,
+            bytes: bytes_per_entry * entries as u64,
+            exact,
+        })
+    }
+
+    /// Returns a set of distinct content types of data entries sent from a SIM based on IMSI provided.
+    /// Unlike [`get_data_entries`](Self::get_data_entries), this follows pagination until all entries
+    /// within the search range are examined.
     ///
     /// - `imsi`: IMSI of the target SIM.
-    /// - `time`: Timestamp of the target data entry to delete (unix time in milliseconds).
-    pub fn delete_data_entry(
+    /// - `from`: Start time for the data entries search range (unix time in milliseconds).
+    /// - `to`: End time for the data entries search range (unix time in milliseconds).
+    pub fn list_content_types(
         &self,
         imsi: impl Into<String>,
-        time: i64,
-    ) -> Result<(), SoracomHarvestClientError> {
-        self.client
-            .delete(format!(
-                "{}/v1/data/Subscriber/{}/{}",
-                &self.endpoint,
-                imsi.into(),
-                time
-            ))
-            .header(USER_AGENT, "libshsqlite")
-            .header("X-Soracom-Api-Key", &self.api_key)
-            .header("X-Soracom-Token", &self.token)
-            .header("X-Soracom-Lang", "en")
-            .send()?;
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<HashSet<String>, SoracomHarvestClientError> {
+        let imsi = imsi.into();
+        let (from, to) = self.search_range(from, to)?;
+
+        Ok(self
+            .get_all_data_entries(&imsi, from, to)?
+            .into_iter()
+            .map(|d| d.content_type)
+            .collect())
+    }
+
+    /// Returns how many data entries sent from a SIM contain each of `expected_keys` in the content, to check
+    /// that a device sends consistent JSON before e.g. projecting the keys to columns, where a missing key
+    /// yields `NULL`. Nested keys are joined by dots e.g. `sensor.temp`, as with
+    /// [`Data::to_flat_map`]. A key with `null` value is present, and content which isn't JSON contains no
+    /// keys. This follows pagination until all entries within the search range are examined.
+    ///
+    /// - `imsi`: IMSI of the target SIM.
+    /// - `from`: Start time for the data entries search range (unix time in milliseconds).
+    /// - `to`: End time for the data entries search range (unix time in milliseconds).
+    /// - `expected_keys`: Keys to check.
+    pub fn check_schema(
+        &self,
+        imsi: impl Into<String>,
+        from: Option<i64>,
+        to: Option<i64>,
+        expected_keys: &[&str],
+    ) -> Result<SchemaReport, SoracomHarvestClientError> {
+        let imsi = imsi.into();
+        let (from, to) = self.search_range(from, to)?;
+        let entries = self.get_all_data_entries(&imsi, from, to)?;
 
-        Ok(())
-    }
-
-    fn try_decode(content: String) -> String {
-        // If value of the "content" property is like {"payload": "value"}, it could be base64-encoded data.
-        if let Ok(base64_encoded_payload) =
-            serde_json::from_str::<Base64EncodedPayload>(content.as_str())
-        {
-            // If value of the "payload" property can be decoded as base64
-            if let Ok(decoded) = base64::decode(base64_encoded_payload.payload) {
-                // and can be decoded as UTF-8 string,
-                if let Ok(str) = String::from_utf8(decoded) {
-                    // and the decoded string has only ASCII printable characters,
-                    if str.chars().all(|c| matches!(c as u8, 0x20..=0x7E)) {
-                        // return {"value": "<decoded string>"} as the content.
-                        return format!(r#"{{"value":"{str}"}}"#);
-                    }
+        let mut report = SchemaReport {
+            entries: entries.len(),
+            counts: expected_keys.iter().map(|k| (k.to_string(), 0)).collect(),
+        };
+        for d in entries {
+            let Ok(content) = serde_json::from_str::<serde_json::Value>(&d.content) else {
+                continue;
+            };
+            for (key, count) in report.counts.iter_mut() {
+                let found = key.split('.').try_fold(&content, |value, k| match value {
+                    serde_json::Value::Object(object) => object.get(k),
+                    serde_json::Value::Array(array) => array.get(k.parse::<usize>().ok()?),
+                    _ => None,
+                });
+                if found.is_some() {
+                    *count += 1;
                 }
             }
         }
-        // Otherwise return original content as is.
-        content
+
+        Ok(report)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::client::SoracomHarvestClient;
+    /// Returns a vec of data entries sent from a SIM within the last `minutes`, following pagination.
+    /// The search range is computed from the server time (see [`server_time`](Self::server_time)) rather
+    /// than the local clock, so it stays correct on a device with a bad clock. Falls back to the local
+    /// time with a warning if the server time is unavailable.
+    ///
+    /// - `imsi`: IMSI of the target SIM.
+    /// - `minutes`: Length of the search range, ending at the current server time.
+    pub fn get_recent_server(
+        &self,
+        imsi: impl Into<String>,
+        minutes: i64,
+    ) -> Result<Vec<Data>, SoracomHarvestClientError> {
+        let now = self.server_time().unwrap_or_else(|e| {
+            log::warn!("Server time is unavailable, falling back to local time: {e}");
+            Utc::now()
+        });
+        let from = Duration::try_minutes(minutes)
+            .and_then(|minutes| now.checked_sub_signed(minutes))
+            .ok_or(SoracomHarvestClientError::InvalidMinutes)?
+            .timestamp_millis();
+        let to = now.timestamp_millis();
 
-    #[test]
-    fn test_try_decode() {
-        // valid base64
-        assert_eq!(
-            SoracomHarvestClient::try_decode(r#"{"payload":"aGVsbG8="}"#.to_string()),
-            r#"{"value":"hello"}"#,
-        );
+        self.get_all_data_entries(&imsi.into(), from, to)
+    }
 
-        // invalid base64
-        assert_eq!(
-            SoracomHarvestClient::try_decode(r#"{"payload":"aGVsbG"}"#.to_string()),
-            r#"{"payload":"aGVsbG"}"#,
-        );
+    /// Returns data entries sent from a SIM within the search range, following pagination, grouped into
+    /// fixed time buckets e.g. hourly, for charting. Each entry goes to the bucket keyed by its timestamp
+    /// floored to a multiple of `bucket` since the unix epoch, so hourly buckets start at the top of each
+    /// hour in UTC regardless of `from`. Buckets without entries are omitted, and entries within a bucket
+    /// are in descending order.
+    ///
+    /// - `imsi`: IMSI of the target SIM.
+    /// - `from`: Start time for the data entries search range (unix time in milliseconds).
+    /// - `to`: End time for the data entries search range (unix time in milliseconds).
+    /// - `bucket`: Length of each bucket. Should be at least 1 millisecond.
+    pub fn bucketize(
+        &self,
+        imsi: impl Into<String>,
+        from: i64,
+        to: i64,
+        bucket: Duration,
+    ) -> Result<BTreeMap<i64, Vec<Data>>, SoracomHarvestClientError> {
+        let bucket = bucket.num_milliseconds();
+        if bucket < 1 {
+            return Err(SoracomHarvestClientError::InvalidBucket);
+        }
 
-        // not ASCII printable ('\012\033')
-        assert_eq!(
-            SoracomHarvestClient::try_decode(r#"{"payload":"ChsK"}"#.to_string()),
-            r#"{"payload":"ChsK"}"#,
-        );
+        let mut buckets: BTreeMap<i64, Vec<Data>> = BTreeMap::new();
+        for d in self.get_all_data_entries(&imsi.into(), from, to)? {
+            let start = d.time.div_euclid(bucket) * bucket;
+            buckets.entry(start).or_default().push(d);
+        }
+
+        Ok(buckets)
+    }
+
+    /// Returns an iterator of data entries sent from a SIM within the search range, latest first. Unlike
+    /// [`get_data_entries`](Self::get_data_entries), pages of up to 1000 entries are fetched lazily as the
+    /// iterator is advanced, until all entries within the search range are yielded. An error is yielded as
+    /// an `Err` item, and stops further iteration.
+    ///
+    /// - `imsi`: IMSI of the target SIM.
+    /// - `from`: Start time for the data entries search range (unix time in milliseconds).
+    /// - `to`: End time for the data entries search range (unix time in milliseconds).
+    pub fn entries_iter(
+        &self,
+        imsi: impl Into<String>,
+        from: i64,
+        to: i64,
+    ) -> impl Iterator<Item = Result<Data, SoracomHarvestClientError>> + '_ {
+        EntriesIter {
+            client: self,
+            imsi: imsi.into(),
+            from,
+            to,
+            page: Vec::new().into_iter(),
+            next_key: None,
+            state: PageState::First,
+        }
+    }
+
+    /// Returns all data entries sent from a SIM within the search range, latest first, following pagination
+    /// until the cumulative size of the response bodies exceeds `max_total_bytes`, so that a pull over a
+    /// metered connection can't download a surprising amount of data. If it stops at the cap with more pages
+    /// left, the entries fetched so far are returned with `truncated` set.
+    ///
+    /// The cap is checked after each page, which has up to 1000 entries, so the pull may exceed it by up to a
+    /// page. There is no row-count limit, as with [`entries_iter`](Self::entries_iter); for one with a
+    /// row-count limit, use [`get_data_entries`](Self::get_data_entries), which fetches a single page.
+    ///
+    /// - `imsi`: IMSI of the target SIM.
+    /// - `from`: Start time for the data entries search range (unix time in milliseconds).
+    /// - `to`: End time for the data entries search range (unix time in milliseconds).
+    /// - `max_total_bytes`: Cap of the cumulative size of the response bodies.
+    pub fn get_all_data_entries_capped(
+        &self,
+        imsi: impl Into<String>,
+        from: i64,
+        to: i64,
+        max_total_bytes: u64,
+    ) -> Result<CappedData, SoracomHarvestClientError> {
+        let imsi = imsi.into();
+        let mut data = Vec::new();
+        let mut total_bytes = 0u64;
+        let mut next_key: Option<String> = None;
+
+        loop {
+            let (page, key, bytes) =
+                self.get_data_entries_page_sized(&imsi, from, to, 1000, next_key.as_deref())?;
+            data.extend(page);
+            total_bytes += bytes as u64;
+            next_key = key;
+
+            match next_key {
+                None => {
+                    return Ok(CappedData {
+                        data,
+                        truncated: false,
+                    })
+                }
+                Some(_) if total_bytes > max_total_bytes => {
+                    return Ok(CappedData {
+                        data,
+                        truncated: true,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// Returns the current server time, based on the `Date` header of a response from the endpoint.
+    pub fn server_time(&self) -> Result<DateTime<Utc>, SoracomHarvestClientError> {
+        let response = self
+            .client
+            .head(self.base_url()?)
+            .header(USER_AGENT, "libshsqlite")
+            .send()?;
+
+        response
+            .headers()
+            .get(DATE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|s| DateTime::parse_from_rfc2822(s).ok())
+            .map(|t| t.with_timezone(&Utc))
+            .ok_or(SoracomHarvestClientError::NoServerTime)
+    }
+
+    /// Same as [`get_data_entries`](Self::get_data_entries), but returns
+    /// [`SoracomHarvestClientError::NoData`] when the query yields no entries.
+    pub fn get_data_entries_require_data(
+        &self,
+        imsi: impl Into<String>,
+        from: Option<i64>,
+        to: Option<i64>,
+        limit: Option<u32>,
+    ) -> Result<Vec<Data>, SoracomHarvestClientError> {
+        let data = self.get_data_entries(imsi, from, to, limit)?;
+
+        if data.is_empty() {
+            return Err(SoracomHarvestClientError::NoData);
+        }
+
+        Ok(data)
+    }
+
+    /// Returns a page of up to `limit` data entries older than `before`, latest first. Entries exactly at
+    /// `before` are excluded, as the search range is `[0, before - 1]`, so calling this again with the
+    /// timestamp of the oldest returned entry walks backward through history without overlap. An empty vec
+    /// means there are no older entries, which is also the case without a request if `before` is `i64::MIN`.
+    ///
+    /// Note that entries sharing the same timestamp could be skipped if they straddle a page boundary.
+    ///
+    /// - `imsi`: IMSI of the target SIM.
+    /// - `before`: Exclusive upper bound of the timestamps (unix time in milliseconds).
+    /// - `limit`: Maximum number of data entries to retrieve. Should be between 1 and 1000.
+    pub fn get_page_before(
+        &self,
+        imsi: impl Into<String>,
+        before: i64,
+        limit: u32,
+    ) -> Result<Vec<Data>, SoracomHarvestClientError> {
+        if !(1..=1000).contains(&limit) {
+            return Err(SoracomHarvestClientError::InvalidLimit);
+        }
+
+        let Some(to) = before.checked_sub(1) else {
+            return Ok(vec![]);
+        };
+        let (data, _) = self.get_data_entries_page(&imsi.into(), 0, to, limit, None)?;
+
+        Ok(data)
+    }
+
+    /// Returns a data entry identified with IMSI and timestamp, or `None` if there is no entry at the timestamp.
+    /// This is the read-side counterpart of [`delete_data_entry`](Self::delete_data_entry), e.g. to confirm a
+    /// message is stored after a send.
+    ///
+    /// Queries a tight search range of `[time, time + 1]`, then picks the entry exactly matching `time`. The
+    /// query isn't limited to a single entry, as an entry at `time + 1` comes first in descending order. If
+    /// multiple entries share the same millisecond, the first match is returned. `None` is returned without a
+    /// request if `time` is `i64::MAX`, as the range doesn't fit.
+    ///
+    /// - `imsi`: IMSI of the target SIM.
+    /// - `time`: Timestamp of the target data entry (unix time in milliseconds).
+    pub fn get_data_entry(
+        &self,
+        imsi: impl Into<String>,
+        time: i64,
+    ) -> Result<Option<Data>, SoracomHarvestClientError> {
+        let Some(to) = time.checked_add(1) else {
+            return Ok(None);
+        };
+        let (data, _) = self.get_data_entries_page(&imsi.into(), time, to, 100, None)?;
+
+        Ok(data.into_iter().find(|d| d.time == time))
+    }
+
+    /// Deprecated alias of [`get_data_entry`](Self::get_data_entry).
+    #[deprecated(note = "use `get_data_entry` instead")]
+    pub fn get_entry(
+        &self,
+        imsi: impl Into<String>,
+        time: i64,
+    ) -> Result<Option<Data>, SoracomHarvestClientError> {
+        self.get_data_entry(imsi, time)
+    }
+
+    /// Sends `body` via HTTP with the `sender`, then polls the data entries of the SIM until an entry with the
+    /// same content shows up, and returns the timestamp of the stored entry. Useful for end-to-end health
+    /// checks; make `body` unique e.g. with a random tag, so that it isn't confused with other entries.
+    /// Returns [`SoracomHarvestClientError::NotVerified`] if the entry isn't found within `timeout`.
+    /// Polls every 500 milliseconds; use [`send_and_verify_with`](Self::send_and_verify_with) to tune it.
+    ///
+    /// Note that the message is sent from the device running this, so `imsi` should be the IMSI of its SIM.
+    ///
+    /// - `imsi`: IMSI of the SIM sending the message.
+    /// - `body`: Body of the message, in JSON.
+    /// - `timeout`: Maximum duration to wait for the entry to show up.
+    pub fn send_and_verify(
+        &self,
+        imsi: impl Into<String>,
+        body: impl Into<String>,
+        timeout: std::time::Duration,
+    ) -> Result<i64, SoracomHarvestClientError> {
+        let max_attempts = timeout.as_millis() / SEND_AND_VERIFY_POLL_INTERVAL.as_millis() + 1;
+
+        self.send_and_verify_with(
+            imsi,
+            body,
+            SEND_AND_VERIFY_POLL_INTERVAL,
+            u32::try_from(max_attempts).unwrap_or(u32::MAX),
+        )
+    }
+
+    /// Sends data entries in NDJSON, i.e. a data entry in JSON like `{"time":1669024325202,"contentType":
+    /// "application/json","content":"{}"}` per line, back to Harvest with `sender`, e.g. to restore an
+    /// archive. Each entry is sent via HTTP with its content type, one by one. Returns the counts of the lines
+    /// sent, failed to send, and skipped as malformed; empty lines are ignored.
+    ///
+    /// Note that Harvest stores the entries with the time they are received, not `time` of the entries, and
+    /// as sent from the SIM running this.
+    ///
+    /// - `reader`: NDJSON to import. Returns [`SoracomHarvestClientError::Io`] if reading fails, after
+    ///   sending the lines read so far.
+    pub fn import_ndjson<R: Read>(
+        &self,
+        reader: R,
+    ) -> Result<ImportReport, SoracomHarvestClientError> {
+        let mut report = ImportReport::default();
+        for (n, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let data = match serde_json::from_str::<Data>(&line) {
+                Ok(data) => data,
+                Err(e) => {
+                    log::warn!("Skipping malformed line {}: {e}", n + 1);
+                    report.malformed += 1;
+                    continue;
+                }
+            };
+
+            match self
+                .sender
+                .send_http_raw(data.content.as_bytes(), &data.content_type, &[])
+            {
+                Ok(_) => report.sent += 1,
+                Err(e) => {
+                    log::warn!("Failed to send line {}: {e}", n + 1);
+                    report.failed += 1;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Same as [`send_and_verify`](Self::send_and_verify), but polls up to `max_attempts` times with
+    /// `poll_interval` in between, so the worst-case wait is roughly `poll_interval × max_attempts` plus the
+    /// time taken by the requests. Polls at least once even if `max_attempts` is 0.
+    ///
+    /// - `imsi`: IMSI of the SIM sending the message.
+    /// - `body`: Body of the message, in JSON.
+    /// - `poll_interval`: Duration to wait between polls.
+    /// - `max_attempts`: Maximum number of polls.
+    pub fn send_and_verify_with(
+        &self,
+        imsi: impl Into<String>,
+        body: impl Into<String>,
+        poll_interval: std::time::Duration,
+        max_attempts: u32,
+    ) -> Result<i64, SoracomHarvestClientError> {
+        self.send_and_poll(&imsi.into(), &body.into(), poll_interval, max_attempts)
+            .map(|(time, _)| time)
+    }
+
+    /// Returns how long it takes for a message to become queryable after sending, for SLA monitoring. Sends
+    /// a message tagged with a random UUID like `{"ingest_latency_probe": "..."}` with the `sender`, then
+    /// polls the data entries every 500 milliseconds for up to 1 minute, like
+    /// [`send_and_verify`](Self::send_and_verify). Use
+    /// [`measure_ingest_latency_with`](Self::measure_ingest_latency_with) to tune the polling.
+    ///
+    /// The measurement starts before sending and ends when a poll finds the entry, so it includes the round
+    /// trips of the send and the poll, and is rounded up to the polling granularity.
+    ///
+    /// - `imsi`: IMSI of the SIM sending the message.
+    pub fn measure_ingest_latency(
+        &self,
+        imsi: impl Into<String>,
+    ) -> Result<std::time::Duration, SoracomHarvestClientError> {
+        self.measure_ingest_latency_with(
+            imsi,
+            SEND_AND_VERIFY_POLL_INTERVAL,
+            INGEST_LATENCY_MAX_ATTEMPTS,
+        )
+    }
+
+    /// Same as [`measure_ingest_latency`](Self::measure_ingest_latency), but polls up to `max_attempts`
+    /// times with `poll_interval` in between, see [`send_and_verify_with`](Self::send_and_verify_with).
+    pub fn measure_ingest_latency_with(
+        &self,
+        imsi: impl Into<String>,
+        poll_interval: std::time::Duration,
+        max_attempts: u32,
+    ) -> Result<std::time::Duration, SoracomHarvestClientError> {
+        let body = serde_json::json!({ "ingest_latency_probe": new_idempotency_key() }).to_string();
+
+        self.send_and_poll(&imsi.into(), &body, poll_interval, max_attempts)
+            .map(|(_, latency)| latency)
+    }
+
+    /// Deletes a data entry identified with IMSI and timestamp.
+    ///
+    /// - `imsi`: IMSI of the target SIM.
+    /// - `time`: Timestamp of the target data entry to delete (unix time in milliseconds).
+    pub fn delete_data_entry(
+        &self,
+        imsi: impl Into<String>,
+        time: i64,
+    ) -> Result<(), SoracomHarvestClientError> {
+        let imsi = imsi.into();
+        let start = Instant::now();
+        let response = self
+            .client
+            .delete(format!("{}/{}", self.subscriber_url(&imsi)?, time))
+            .header(USER_AGENT, "libshsqlite")
+            .header("X-Soracom-Api-Key", &self.api_key)
+            .header("X-Soracom-Token", &self.token)
+            .header("X-Soracom-Lang", "en")
+            .send()?;
+        self.record_rate_limit(response.headers());
+        ApiEvent {
+            imsi: Some(&imsi),
+            from: Some(time),
+            to: Some(time),
+            status: Some(response.status().as_u16()),
+            ..ApiEvent::new("delete_data_entry", start)
+        }
+        .emit();
+
+        check_status(response.status().as_u16())
+    }
+
+    /// Returns IMSIs of the subscribers (SIMs) of the operator starting with `prefix`, in ascending order,
+    /// following pagination, e.g. to query all SIMs of a deployment batch. An empty `prefix` matches all
+    /// SIMs. Requires `Subscriber:listSubscribers` permission.
+    pub fn list_imsis(&self, prefix: &str) -> Result<Vec<String>, SoracomHarvestClientError> {
+        let url = format!("{}/v1/subscribers", self.base_url()?);
+        let mut imsis = Vec::new();
+        let mut next_key: Option<String> = None;
+
+        loop {
+            let mut query = vec![("limit", "100".to_string())];
+            if let Some(key) = next_key.take() {
+                query.push(("last_evaluated_key", key));
+            }
+
+            let start = Instant::now();
+            let response = self
+                .client
+                .get(&url)
+                .header(USER_AGENT, "libshsqlite")
+                .header("X-Soracom-Api-Key", &self.api_key)
+                .header("X-Soracom-Token", &self.token)
+                .header("X-Soracom-Lang", "en")
+                .query(&query)
+                .send()?;
+            self.record_rate_limit(response.headers());
+
+            next_key = response
+                .headers()
+                .get("x-soracom-next-key")
+                .and_then(|v| v.to_str().ok())
+                .map(|s| s.to_string());
+            let status = response.status().as_u16();
+            check_status(status)?;
+            let subscribers: Vec<SubscriberResponse> = response.json()?;
+            ApiEvent {
+                status: Some(status),
+                rows: Some(subscribers.len()),
+                ..ApiEvent::new("list_subscribers", start)
+            }
+            .emit();
+
+            imsis.extend(
+                subscribers
+                    .into_iter()
+                    .map(|s| s.imsi)
+                    .filter(|imsi| imsi.starts_with(prefix)),
+            );
+            if next_key.is_none() {
+                break;
+            }
+        }
+        imsis.sort();
+
+        Ok(imsis)
+    }
+
+    /// Returns the rate limit from the latest response of
+    /// [`get_data_entries`](Self::get_data_entries) and friends or
+    /// [`delete_data_entry`](Self::delete_data_entry) with the rate limit headers, for adaptive pacing, or
+    /// `None` if no such response is received yet.
+    pub fn last_rate_limit(&self) -> Option<RateLimitInfo> {
+        *self.last_rate_limit.lock().unwrap()
+    }
+
+    /// Returns when the token of the authentication expires, or `None` if the client isn't authenticated.
+    /// If the auth response doesn't tell the lifetime of the token, [`DEFAULT_TOKEN_TIMEOUT_SECONDS`] is
+    /// assumed.
+    pub fn token_expires_at(&self) -> Option<DateTime<Utc>> {
+        self.token_expires_at
+    }
+
+    /// Records the rate limit from the headers, if any.
+    fn record_rate_limit(&self, headers: &HeaderMap) {
+        if let Some(info) = RateLimitInfo::from_headers(headers) {
+            *self.last_rate_limit.lock().unwrap() = Some(info);
+        }
+    }
+
+    /// Sends `body`, then polls until an entry with the same content shows up, and returns its timestamp and
+    /// the time elapsed since sending.
+    fn send_and_poll(
+        &self,
+        imsi: &str,
+        body: &str,
+        poll_interval: std::time::Duration,
+        max_attempts: u32,
+    ) -> Result<(i64, std::time::Duration), SoracomHarvestClientError> {
+        // allow some clock difference between the device and Harvest
+        let from = (Utc::now() - Duration::minutes(1)).timestamp_millis();
+        let start = Instant::now();
+        self.sender
+            .send_http(body)
+            .map_err(|e| SoracomHarvestClientError::Send(e.to_string()))?;
+
+        for attempt in 1..=max_attempts.max(1) {
+            if attempt > 1 {
+                thread::sleep(poll_interval);
+            }
+
+            let to = (Utc::now() + Duration::minutes(1)).timestamp_millis();
+            let data = self.get_data_entries(imsi, Some(from), Some(to), Some(100))?;
+            if let Some(entry) = data.into_iter().find(|d| d.content == body) {
+                return Ok((entry.time, start.elapsed()));
+            }
+        }
+
+        Err(SoracomHarvestClientError::NotVerified)
+    }
+
+    /// Returns an unauthenticated client with the same configuration, for the endpoint.
+    fn with_endpoint(&self, endpoint: Endpoint) -> Self {
+        SoracomHarvestClient {
+            auth_key_id: self.auth_key_id.clone(),
+            auth_key_secret: self.auth_key_secret.clone(),
+            endpoint,
+            auth_endpoint: self.auth_endpoint.clone(),
+            https_only: self.https_only,
+            strict_defaults: self.strict_defaults,
+            sender: self.sender.clone(),
+            decode_options: self.decode_options.clone(),
+            dns_retry: self.dns_retry,
+            api_key: String::new(),
+            token: String::new(),
+            user_name: None,
+            operator_id: None,
+            resolve: self.resolve.clone(),
+            over_limit: self.over_limit,
+            client: self.client.clone(),
+            last_rate_limit: Mutex::default(),
+            token_expires_at: None,
+            resolved: self.resolved.clone(),
+        }
+    }
+
+    /// Returns the search range, defaulting to the last 24 hours unless the client is in strict mode.
+    fn search_range(
+        &self,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<(i64, i64), SoracomHarvestClientError> {
+        if self.strict_defaults {
+            return Ok((
+                from.ok_or(SoracomHarvestClientError::MissingParameter("from"))?,
+                to.ok_or(SoracomHarvestClientError::MissingParameter("to"))?,
+            ));
+        }
+
+        Ok((
+            from.unwrap_or_else(|| (Utc::now() - Duration::days(1)).timestamp_millis()),
+            to.unwrap_or_else(|| Utc::now().timestamp_millis()),
+        ))
+    }
+
+    /// Returns all data entries within the search range, following pagination.
+    fn get_all_data_entries(
+        &self,
+        imsi: &str,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<Data>, SoracomHarvestClientError> {
+        self.entries_iter(imsi, from, to).collect()
+    }
+
+    /// Returns a page of data entries, and the key to retrieve the next page if there are more entries.
+    fn get_data_entries_page(
+        &self,
+        imsi: &str,
+        from: i64,
+        to: i64,
+        limit: u32,
+        last_evaluated_key: Option<&str>,
+    ) -> Result<(Vec<Data>, Option<String>), SoracomHarvestClientError> {
+        let (data, next_key, _) =
+            self.get_data_entries_page_sized(imsi, from, to, limit, last_evaluated_key)?;
+
+        Ok((data, next_key))
+    }
+
+    /// Same as `get_data_entries_page`, but also returns the size of the response body in bytes.
+    fn get_data_entries_page_sized(
+        &self,
+        imsi: &str,
+        from: i64,
+        to: i64,
+        limit: u32,
+        last_evaluated_key: Option<&str>,
+    ) -> Result<(Vec<Data>, Option<String>, usize), SoracomHarvestClientError> {
+        let mut query = vec![
+            ("from", from.to_string()),
+            ("to", to.to_string()),
+            ("sort", "desc".to_string()),
+            ("limit", limit.to_string()),
+        ];
+        if let Some(key) = last_evaluated_key {
+            query.push(("last_evaluated_key", key.to_string()));
+        }
+
+        let start = Instant::now();
+        let response = self
+            .client
+            .get(self.subscriber_url(imsi)?)
+            .header(USER_AGENT, "libshsqlite")
+            .header("X-Soracom-Api-Key", &self.api_key)
+            .header("X-Soracom-Token", &self.token)
+            .header("X-Soracom-Lang", "en")
+            .query(&query)
+            .send()?;
+        self.record_rate_limit(response.headers());
+
+        let next_key = response
+            .headers()
+            .get("x-soracom-next-key")
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string());
+        let status = response.status().as_u16();
+        check_status(status)?;
+        let body = response.bytes()?;
+        let response: HarvestDataResponse = serde_json::from_slice(&body)?;
+        ApiEvent {
+            imsi: Some(imsi),
+            from: Some(from),
+            to: Some(to),
+            status: Some(status),
+            rows: Some(response.data.len()),
+            ..ApiEvent::new("get_data_entries", start)
+        }
+        .emit();
+
+        let mut data = response.data;
+        if data.len() > limit as usize {
+            match self.over_limit {
+                OverLimit::Truncate => {
+                    log::warn!(
+                        "The API returned {} data entries for limit {limit}, truncating",
+                        data.len()
+                    );
+                    data.truncate(limit as usize);
+                }
+                OverLimit::Error => {
+                    return Err(SoracomHarvestClientError::TooManyEntries {
+                        limit,
+                        returned: data.len(),
+                    })
+                }
+            }
+        }
+
+        let mut result: Vec<Data> = Vec::new();
+        for d in data {
+            result.push(Data {
+                content: decode(&self.decode_options, d.content),
+                content_type: d.content_type,
+                time: d.time,
+            })
+        }
+
+        Ok((result, next_key, body.len()))
+    }
+
+    /// Returns the URL of the data entries of the SIM, with IMSI percent-encoded so that a stray character
+    /// e.g. a space or slash from a bad environment variable can't alter the path.
+    fn subscriber_url(&self, imsi: &str) -> Result<String, SoracomHarvestClientError> {
+        Ok(format!(
+            "{}/v1/data/Subscriber/{}",
+            self.base_url()?,
+            utf8_percent_encode(imsi, PATH_SEGMENT)
+        ))
+    }
+
+    /// Returns the base URL of the endpoint of the client, see [`checked_url`](Self::checked_url).
+    fn base_url(&self) -> Result<&str, SoracomHarvestClientError> {
+        self.checked_url(&self.endpoint)
+    }
+
+    /// Returns the base URL of `endpoint`, checking its scheme if the client is `https_only`, and resolving
+    /// its host with `dns_retry` unless it has been resolved before.
+    fn checked_url<'a>(
+        &self,
+        endpoint: &'a Endpoint,
+    ) -> Result<&'a str, SoracomHarvestClientError> {
+        if self.https_only && endpoint.scheme() != "https" {
+            return Err(SoracomHarvestClientError::InsecureEndpoint(
+                endpoint.to_string(),
+            ));
+        }
+
+        let url = endpoint.as_str();
+        if let Ok(parsed) = reqwest::Url::parse(url) {
+            if let (Some(host), Some(port)) = (parsed.host_str(), parsed.port_or_known_default()) {
+                if self.resolve.iter().any(|(h, _)| h == host) {
+                    return Ok(url);
+                }
+                self.resolved
+                    .ensure(host, port, &self.dns_retry)
+                    .map_err(|e| SoracomHarvestClientError::Resolve(format!("{host}: {e}")))?;
+            }
+        }
+
+        Ok(url)
+    }
+}
+
+/// Returns [`SoracomHarvestClientError::Unauthorized`] for 401 status code, or
+/// [`SoracomHarvestClientError::Status`] for other non-2xx status code.
+fn check_status(status: u16) -> Result<(), SoracomHarvestClientError> {
+    match status {
+        200..=299 => Ok(()),
+        401 => Err(SoracomHarvestClientError::Unauthorized),
+        status => Err(SoracomHarvestClientError::Status { status }),
+    }
+}
+
+/// Where [`EntriesIter`] is in the pagination.
+enum PageState {
+    /// No page is fetched yet.
+    First,
+    /// There may be more pages to fetch with the `next_key`.
+    More,
+    /// All pages are fetched, or an error occurred.
+    Done,
+}
+
+/// Iterator of data entries, created with [`SoracomHarvestClient::entries_iter`].
+struct EntriesIter<'a> {
+    client: &'a SoracomHarvestClient,
+    imsi: String,
+    from: i64,
+    to: i64,
+    page: std::vec::IntoIter<Data>,
+    next_key: Option<String>,
+    state: PageState,
+}
+
+impl Iterator for EntriesIter<'_> {
+    type Item = Result<Data, SoracomHarvestClientError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(d) = self.page.next() {
+                return Some(Ok(d));
+            }
+
+            match self.state {
+                PageState::Done => return None,
+                PageState::More if self.next_key.is_none() => {
+                    self.state = PageState::Done;
+                    return None;
+                }
+                _ => {}
+            }
+
+            match self.client.get_data_entries_page(
+                &self.imsi,
+                self.from,
+                self.to,
+                1000,
+                self.next_key.as_deref(),
+            ) {
+                Ok((data, next_key)) => {
+                    self.page = data.into_iter();
+                    self.next_key = next_key;
+                    self.state = PageState::More;
+                }
+                Err(e) => {
+                    self.state = PageState::Done;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        client::{
+            find_duplicate_timestamps, merge_data, to_series, AuthResponse, Data, ImportReport,
+            OverLimit, QueryEstimate, RateLimitInfo, SchemaReport, SoracomHarvestClient,
+            DEFAULT_TOKEN_TIMEOUT_SECONDS,
+        },
+        endpoint::Endpoint,
+        error::SoracomHarvestClientError,
+    };
+    use chrono::{Duration as ChronoDuration, Utc};
+    use mockito::{Matcher, Server};
+    use soracom_harvest_client::{dns::DnsRetry, sender::Sender};
+    use std::time::Duration;
+
+    fn mock_client(server: &Server) -> SoracomHarvestClient {
+        SoracomHarvestClient::builder()
+            .auth_key_id("keyId")
+            .auth_key_secret("secret")
+            .endpoint(server.url())
+            .build()
+    }
+
+    #[cfg(feature = "chrono-tz")]
+    #[test]
+    fn test_datetime_in() {
+        let data = Data {
+            time: 1669024325202,
+            content_type: "application/json".to_string(),
+            content: "{}".to_string(),
+        };
+
+        assert_eq!(
+            data.datetime_in(chrono_tz::Asia::Tokyo)
+                .unwrap()
+                .to_rfc3339(),
+            "2022-11-21T18:52:05.202+09:00"
+        );
+        assert_eq!(
+            data.datetime_in(chrono_tz::UTC).unwrap().to_rfc3339(),
+            "2022-11-21T09:52:05.202+00:00"
+        );
+        assert_eq!(data.time, 1669024325202);
+    }
+
+    #[test]
+    fn test_to_flat_map() {
+        let data = Data {
+            time: 1669024325202,
+            content_type: "application/json".to_string(),
+            content: r#"{"sensor":{"temp":20.5,"ok":true},"readings":[1,2],"name":"a","time":0}"#
+                .to_string(),
+        };
+        let mut map = data.to_flat_map().into_iter().collect::<Vec<_>>();
+        map.sort();
+        assert_eq!(
+            map,
+            [
+                ("content_type", "application/json"),
+                ("name", "a"),
+                ("readings.0", "1"),
+                ("readings.1", "2"),
+                ("sensor.ok", "true"),
+                ("sensor.temp", "20.5"),
+                ("time", "1669024325202"),
+                ("time_iso", "2022-11-21T09:52:05.202Z"),
+            ]
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+        );
+
+        let data = Data {
+            content_type: "text/plain".to_string(),
+            content: "hello".to_string(),
+            ..data
+        };
+        assert_eq!(data.to_flat_map()["content"], "hello");
+    }
+
+    #[test]
+    fn test_find_duplicate_timestamps() {
+        let entries = [
+            1669024325203,
+            1669024325202,
+            1669024325203,
+            1669024325201,
+            1669024325201,
+        ]
+        .into_iter()
+        .map(|time| Data {
+            time,
+            content_type: "application/json".to_string(),
+            content: "{}".to_string(),
+        })
+        .collect::<Vec<_>>();
+
+        assert_eq!(
+            find_duplicate_timestamps(&entries),
+            vec![1669024325201, 1669024325203]
+        );
+        assert!(find_duplicate_timestamps(&entries[..2]).is_empty());
+    }
+
+    #[test]
+    fn test_get_data_entries_across() {
+        let mut servers = [Server::new(), Server::new()];
+        for (server, body) in servers.iter_mut().zip([
+            r#"[
+                {"time":1669024325203,"contentType":"application/json","content":"{\"t\":3}"},
+                {"time":1669024325201,"contentType":"application/json","content":"{\"t\":1}"}
+            ]"#,
+            r#"[
+                {"time":1669024325203,"contentType":"application/json","content":"{\"t\":3}"},
+                {"time":1669024325202,"contentType":"application/json","content":"{\"t\":2}"}
+            ]"#,
+        ]) {
+            server
+                .mock("POST", "/v1/auth")
+                .with_body(r#"{"apiKey":"api-key","token":"token"}"#)
+                .expect(2)
+                .create();
+            server
+                .mock("GET", "/v1/data/Subscriber/441200000050000")
+                .match_query(Matcher::Any)
+                .match_header("x-soracom-token", "token")
+                .with_body(body)
+                .create();
+        }
+
+        let client = mock_client(&servers[0]);
+        let endpoints = servers
+            .iter()
+            .map(|s| Endpoint::from(s.url().as_str()))
+            .collect::<Vec<_>>();
+
+        let data = client
+            .get_data_entries_across(&endpoints, "441200000050000", Some(0), Some(1), Some(10))
+            .unwrap();
+        assert_eq!(
+            data.iter().map(|d| d.time).collect::<Vec<_>>(),
+            vec![1669024325203, 1669024325202, 1669024325201]
+        );
+
+        let sourced = client
+            .get_data_entries_across_sourced(
+                &endpoints,
+                "441200000050000",
+                Some(0),
+                Some(1),
+                Some(10),
+            )
+            .unwrap();
+        assert_eq!(
+            sourced
+                .iter()
+                .map(|s| (s.data.time, &s.endpoint))
+                .collect::<Vec<_>>(),
+            vec![
+                (1669024325203, &endpoints[0]),
+                (1669024325203, &endpoints[1]),
+                (1669024325202, &endpoints[1]),
+                (1669024325201, &endpoints[0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_sampled_entries() {
+        let entry = |time: i64| {
+            format!(r#"{{"time":{time},"contentType":"application/json","content":"{{}}"}}"#)
+        };
+        let mut server = Server::new();
+        let client = mock_client(&server);
+
+        // an entry every second from 0 to 999000, with more than the probe
+        server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::UrlEncoded("limit".into(), "10".into()))
+            .with_header("x-soracom-next-key", "990000")
+            .with_body(format!(
+                "[{}]",
+                (0..10)
+                    .map(|i| entry(999_000 - i * 1000))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ))
+            .create();
+        let slices = server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::UrlEncoded("limit".into(), "1".into()))
+            .with_body_from_request(move |request| {
+                let to = request
+                    .path_and_query()
+                    .split(['?', '&'])
+                    .find_map(|param| param.strip_prefix("to="))
+                    .and_then(|to| to.parse::<i64>().ok())
+                    .unwrap();
+                format!("[{}]", entry(to - to % 1000)).into()
+            })
+            .expect(10)
+            .create();
+
+        let data = client
+            .get_sampled_entries("441200000050000", 0, 999_999, 10)
+            .unwrap();
+        // the latest entry of each 100 seconds
+        assert_eq!(
+            data.iter().map(|d| d.time).collect::<Vec<_>>(),
+            (0..10).map(|i| 999_000 - i * 100_000).collect::<Vec<_>>()
+        );
+        slices.assert();
+
+        // the probe yields all the entries
+        let mut server = Server::new();
+        let client = mock_client(&server);
+        let all = server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::Any)
+            .with_body(format!("[{},{}]", entry(2000), entry(1000)))
+            .expect(2)
+            .create();
+        let data = client
+            .get_sampled_entries("441200000050000", 0, 999_999, 10)
+            .unwrap();
+        assert_eq!(data.len(), 2);
+        all.assert();
+
+        assert!(client
+            .get_sampled_entries("441200000050000", 0, 999_999, 0)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_estimate_query_cost() {
+        let entries = |n: i64| {
+            let entries = (0..n)
+                .map(|i| {
+                    format!(
+                        r#"{{"time":{},"contentType":"application/json","content":"{{}}"}}"#,
+                        1_000_000 - i * 1000
+                    )
+                })
+                .collect::<Vec<_>>();
+            format!("[{}]", entries.join(","))
+        };
+        let mut server = Server::new();
+        let client = mock_client(&server);
+
+        // 10 entries every second, with more before them
+        let mock = server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::UrlEncoded("limit".into(), "10".into()))
+            .with_header("x-soracom-next-key", "991000")
+            .with_body(entries(10))
+            .create();
+        let estimate = client
+            .estimate_query_cost(
+                "441200000050000",
+                Some(901_000),
+                Some(1_000_000),
+                Some(1000),
+            )
+            .unwrap();
+        assert_eq!(estimate.entries, 100);
+        assert!(!estimate.exact);
+        // 64 bytes on average, e.g. {"time":999000,"contentType":"application/json","content":"{}"} and a comma
+        assert_eq!(estimate.bytes, 100 * 64);
+
+        let estimate = client
+            .estimate_query_cost("441200000050000", Some(901_000), Some(1_000_000), Some(50))
+            .unwrap();
+        assert_eq!(estimate.entries, 50);
+
+        // extrapolated from the sampled page without the limit, to 100 entries from 901000
+        assert_eq!(
+            client
+                .estimate_window_size("441200000050000", 901_000, 1_000_000)
+                .unwrap(),
+            100 * 64
+        );
+        mock.remove();
+
+        // all entries are in the probe
+        let mock = server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::Any)
+            .with_body(entries(3))
+            .create();
+        let estimate = client
+            .estimate_query_cost(
+                "441200000050000",
+                Some(901_000),
+                Some(1_000_000),
+                Some(1000),
+            )
+            .unwrap();
+        assert_eq!(estimate.entries, 3);
+        assert!(estimate.exact);
+        mock.remove();
+
+        server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::Any)
+            .with_body("[]")
+            .create();
+        let estimate = client
+            .estimate_query_cost(
+                "441200000050000",
+                Some(901_000),
+                Some(1_000_000),
+                Some(1000),
+            )
+            .unwrap();
+        assert_eq!(
+            estimate,
+            QueryEstimate {
+                entries: 0,
+                bytes: 0,
+                exact: true
+            }
+        );
+    }
+
+    #[test]
+    fn test_to_series() {
+        let entries = [
+            r#"{"sensor":{"temp":20.5}}"#,
+            r#"{"sensor":{"temp":"20.5"}}"#,
+            r#"{"sensor":{}}"#,
+            "not json",
+            r#"{"sensor":{"temp":-3}}"#,
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, content)| Data {
+            time: i as i64,
+            content_type: "application/json".to_string(),
+            content: content.to_string(),
+        })
+        .collect::<Vec<_>>();
+
+        assert_eq!(
+            to_series(&entries, "/sensor/temp"),
+            vec![(0, 20.5), (4, -3.0)]
+        );
+        assert!(to_series(&entries, "/missing").is_empty());
+    }
+
+    #[test]
+    fn test_merge_data() {
+        let data = |time, content: &str| Data {
+            time,
+            content_type: "application/json".to_string(),
+            content: content.to_string(),
+        };
+        let cached = vec![
+            data(1669024325203, "{\"t\":3}"),
+            data(1669024325202, "{\"t\":2}"),
+            data(1669024325201, "{\"t\":1}"),
+        ];
+        let fresh = vec![
+            data(1669024325205, "{\"t\":5}"),
+            // overlaps with the cache
+            data(1669024325203, "{\"t\":3}"),
+            // conflicts with the cache at the same timestamp
+            data(1669024325202, "{\"t\":-2}"),
+        ];
+
+        assert_eq!(
+            merge_data(cached.clone(), fresh.clone()),
+            vec![
+                data(1669024325205, "{\"t\":5}"),
+                data(1669024325203, "{\"t\":3}"),
+                data(1669024325202, "{\"t\":-2}"),
+                data(1669024325202, "{\"t\":2}"),
+                data(1669024325201, "{\"t\":1}"),
+            ]
+        );
+        assert_eq!(merge_data(cached.clone(), vec![]), cached);
+        assert_eq!(merge_data(vec![], fresh.clone()), {
+            let mut sorted = fresh.clone();
+            sorted.sort_by_key(|d| std::cmp::Reverse(d.time));
+            sorted
+        });
+        // duplicates within one side are deduped too
+        assert_eq!(merge_data(cached.clone(), cached.clone()), cached);
+    }
+
+    #[test]
+    fn test_get_recent_server() {
+        let mut server = Server::new();
+        // 2015-10-21T07:28:00Z, which is far from the local clock
+        server
+            .mock("HEAD", "/")
+            .with_header("date", "Wed, 21 Oct 2015 07:28:00 GMT")
+            .create();
+        let data = server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("from".into(), "1445412180000".into()),
+                Matcher::UrlEncoded("to".into(), "1445412480000".into()),
+            ]))
+            .with_body(
+                r#"[{"time":1445412400000,"contentType":"application/json","content":"{}"}]"#,
+            )
+            .create();
+
+        let client = mock_client(&server);
+
+        let entries = client.get_recent_server("441200000050000", 5).unwrap();
+        data.assert();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].time, 1445412400000);
+
+        assert!(matches!(
+            client.get_recent_server("441200000050000", i64::MAX),
+            Err(SoracomHarvestClientError::InvalidMinutes)
+        ));
+    }
+
+    #[test]
+    fn test_get_data_entry() {
+        let mut server = Server::new();
+        server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("from".into(), "1669024325202".into()),
+                Matcher::UrlEncoded("to".into(), "1669024325203".into()),
+            ]))
+            .with_body(
+                r#"[
+                    {"time":1669024325203,"contentType":"application/json","content":"{\"t\":2}"},
+                    {"time":1669024325202,"contentType":"application/json","content":"{\"t\":1}"},
+                    {"time":1669024325202,"contentType":"application/json","content":"{\"t\":0}"}
+                ]"#,
+            )
+            .create();
+        server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::UrlEncoded("from".into(), "1669024325200".into()))
+            .with_body(
+                r#"[{"time":1669024325201,"contentType":"application/json","content":"{}"}]"#,
+            )
+            .create();
+
+        let client = mock_client(&server);
+
+        let entry = client
+            .get_data_entry("441200000050000", 1669024325202)
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.time, 1669024325202);
+        assert_eq!(entry.content, r#"{"t":1}"#);
+
+        assert_eq!(
+            client
+                .get_data_entry("441200000050000", 1669024325200)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            client.get_data_entry("441200000050000", i64::MAX).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_get_page_before() {
+        let mut server = Server::new();
+        for (to, body) in [
+            (
+                "1669024325299",
+                r#"[
+                    {"time":1669024325299,"contentType":"application/json","content":"{\"t\":4}"},
+                    {"time":1669024325250,"contentType":"application/json","content":"{\"t\":3}"}
+                ]"#,
+            ),
+            (
+                "1669024325249",
+                r#"[
+                    {"time":1669024325200,"contentType":"application/json","content":"{\"t\":2}"},
+                    {"time":1669024325100,"contentType":"application/json","content":"{\"t\":1}"}
+                ]"#,
+            ),
+            ("1669024325099", "[]"),
+        ] {
+            server
+                .mock("GET", "/v1/data/Subscriber/441200000050000")
+                .match_query(Matcher::AllOf(vec![
+                    Matcher::UrlEncoded("from".into(), "0".into()),
+                    Matcher::UrlEncoded("to".into(), to.into()),
+                    Matcher::UrlEncoded("sort".into(), "desc".into()),
+                    Matcher::UrlEncoded("limit".into(), "2".into()),
+                ]))
+                .with_body(body)
+                .create();
+        }
+
+        let client = mock_client(&server);
+
+        let mut before = 1669024325300;
+        let mut times = vec![];
+        loop {
+            let page = client
+                .get_page_before("441200000050000", before, 2)
+                .unwrap();
+            match page.last() {
+                Some(oldest) => before = oldest.time,
+                None => break,
+            }
+            times.extend(page.iter().map(|d| d.time));
+        }
+
+        assert_eq!(
+            times,
+            vec![1669024325299, 1669024325250, 1669024325200, 1669024325100]
+        );
+        assert!(matches!(
+            client.get_page_before("441200000050000", before, 0),
+            Err(SoracomHarvestClientError::InvalidLimit)
+        ));
+        assert!(client
+            .get_page_before("441200000050000", i64::MIN, 2)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_strict_defaults() {
+        let mut server = Server::new();
+        let mock = server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("from".into(), "1669024325100".into()),
+                Matcher::UrlEncoded("to".into(), "1669024325200".into()),
+                Matcher::UrlEncoded("limit".into(), "10".into()),
+            ]))
+            .with_body("[]")
+            .create();
+
+        let client = SoracomHarvestClient::builder()
+            .auth_key_id("keyId")
+            .auth_key_secret("secret")
+            .endpoint(server.url())
+            .strict_defaults(true)
+            .build();
+
+        for (from, to, limit, missing) in [
+            (None, Some(1669024325200), Some(10), "from"),
+            (Some(1669024325100), None, Some(10), "to"),
+            (Some(1669024325100), Some(1669024325200), None, "limit"),
+        ] {
+            assert!(matches!(
+                client.get_data_entries("441200000050000", from, to, limit),
+                Err(SoracomHarvestClientError::MissingParameter(p)) if p == missing
+            ));
+        }
+
+        client
+            .get_data_entries(
+                "441200000050000",
+                Some(1669024325100),
+                Some(1669024325200),
+                Some(10),
+            )
+            .unwrap();
+        mock.assert();
+    }
+
+    #[test]
+    fn test_send_and_verify() {
+        let mut server = Server::new();
+        let send = server
+            .mock("POST", "/")
+            .match_body(r#"{"tag":"a1b2c3"}"#)
+            .with_status(201)
+            .create();
+        // the entry shows up at the second poll
+        server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::Any)
+            .with_body(
+                r#"[{"time":1669024325201,"contentType":"application/json","content":"{}"}]"#,
+            )
+            .expect(1)
+            .create();
+        server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::Any)
+            .with_body(
+                r#"[{"time":1669024325202,"contentType":"application/json","content":"{\"tag\":\"a1b2c3\"}"}]"#,
+            )
+            .create();
+
+        let client = SoracomHarvestClient::builder()
+            .auth_key_id("keyId")
+            .auth_key_secret("secret")
+            .endpoint(server.url())
+            .sender(Sender::builder().http_endpoint(server.url()).build())
+            .build();
+
+        let time = client
+            .send_and_verify(
+                "441200000050000",
+                r#"{"tag":"a1b2c3"}"#,
+                Duration::from_secs(5),
+            )
+            .unwrap();
+        send.assert();
+        assert_eq!(time, 1669024325202);
+
+        assert!(matches!(
+            client.send_and_verify("441200000050000", r#"{"tag":"d4e5f6"}"#, Duration::ZERO),
+            Err(SoracomHarvestClientError::NotVerified)
+        ));
+    }
+
+    #[test]
+    fn test_import_ndjson() {
+        let mut server = Server::new();
+        let json = server
+            .mock("POST", "/")
+            .match_header("content-type", "application/json")
+            .match_body(r#"{"t":1}"#)
+            .with_status(201)
+            .create();
+        let csv = server
+            .mock("POST", "/")
+            .match_header("content-type", "text/csv")
+            .match_body("20,60")
+            .with_status(201)
+            .create();
+
+        let client = SoracomHarvestClient::builder()
+            .auth_key_id("keyId")
+            .auth_key_secret("secret")
+            .endpoint(server.url())
+            .sender(
+                Sender::builder()
+                    .http_endpoint(server.url())
+                    .error_for_status(true)
+                    .build(),
+            )
+            .build();
+
+        let ndjson = [
+            r#"{"time":2,"contentType":"application/json","content":"{\"t\":1}"}"#,
+            "",
+            r#"{"time":1,"contentType":"text/csv","content":"20,60"}"#,
+            "not json",
+            r#"{"time":0}"#,
+            // no mock matches, so it fails with 501
+            r#"{"time":0,"contentType":"text/plain","content":"x"}"#,
+        ]
+        .join("\n");
+        let report = client.import_ndjson(ndjson.as_bytes()).unwrap();
+
+        json.assert();
+        csv.assert();
+        assert_eq!(
+            report,
+            ImportReport {
+                sent: 2,
+                failed: 1,
+                malformed: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_measure_ingest_latency() {
+        use std::{
+            sync::{Arc, Mutex},
+            time::Instant,
+        };
+
+        // the entry sent shows up 200 milliseconds after it is sent
+        let sent: Arc<Mutex<Option<(String, Instant)>>> = Arc::default();
+        let mut server = Server::new();
+        let on_send = sent.clone();
+        server
+            .mock("POST", "/")
+            .with_body_from_request(move |request| {
+                let body = request.utf8_lossy_body().unwrap().to_string();
+                *on_send.lock().unwrap() = Some((body, Instant::now()));
+                vec![]
+            })
+            .create();
+        server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::Any)
+            .with_body_from_request(move |_| match &*sent.lock().unwrap() {
+                Some((body, at)) if at.elapsed() >= Duration::from_millis(200) => {
+                    serde_json::json!([
+                        { "time": 1669024325202i64, "contentType": "application/json", "content": body }
+                    ])
+                    .to_string()
+                    .into_bytes()
+                }
+                _ => b"[]".to_vec(),
+            })
+            .create();
+
+        let client = SoracomHarvestClient::builder()
+            .auth_key_id("keyId")
+            .auth_key_secret("secret")
+            .endpoint(server.url())
+            .sender(Sender::builder().http_endpoint(server.url()).build())
+            .build();
+
+        let latency = client
+            .measure_ingest_latency_with("441200000050000", Duration::from_millis(50), 100)
+            .unwrap();
+        assert!(latency >= Duration::from_millis(200), "{latency:?}");
+        assert!(latency < Duration::from_secs(2), "{latency:?}");
+    }
+
+    #[test]
+    fn test_send_and_verify_with_max_attempts() {
+        let mut server = Server::new();
+        server.mock("POST", "/").with_status(201).create();
+        let polls = server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::Any)
+            .with_body("[]")
+            .expect(3)
+            .create();
+
+        let client = SoracomHarvestClient::builder()
+            .auth_key_id("keyId")
+            .auth_key_secret("secret")
+            .endpoint(server.url())
+            .sender(Sender::builder().http_endpoint(server.url()).build())
+            .build();
+
+        assert!(matches!(
+            client.send_and_verify_with(
+                "441200000050000",
+                r#"{"tag":"a1b2c3"}"#,
+                Duration::from_millis(10),
+                3
+            ),
+            Err(SoracomHarvestClientError::NotVerified)
+        ));
+        polls.assert();
+    }
+
+    #[test]
+    fn test_last_rate_limit() {
+        let mut server = Server::new();
+        server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::Any)
+            .with_header("x-ratelimit-limit", "100")
+            .with_header("x-ratelimit-remaining", "42")
+            .with_header("x-ratelimit-reset", "1669024385")
+            .with_body("[]")
+            .create();
+        server
+            .mock(
+                "DELETE",
+                "/v1/data/Subscriber/441200000050000/1669024325202",
+            )
+            .with_header("x-ratelimit-remaining", "41")
+            .with_header("x-ratelimit-reset", "soon")
+            .create();
+
+        let client = mock_client(&server);
+        assert_eq!(client.last_rate_limit(), None);
+
+        client
+            .get_data_entries("441200000050000", Some(0), Some(1), Some(1))
+            .unwrap();
+        assert_eq!(
+            client.last_rate_limit(),
+            Some(RateLimitInfo {
+                limit: Some(100),
+                remaining: Some(42),
+                reset: Some(1669024385),
+            })
+        );
+
+        client
+            .delete_data_entry("441200000050000", 1669024325202)
+            .unwrap();
+        assert_eq!(
+            client.last_rate_limit(),
+            Some(RateLimitInfo {
+                limit: None,
+                remaining: Some(41),
+                reset: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_check_schema() {
+        let mut server = Server::new();
+        server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::Any)
+            .with_body(
+                r#"[
+                    {"time":4,"contentType":"application/json","content":"{\"t\":1,\"sensor\":{\"h\":2}}"},
+                    {"time":3,"contentType":"application/json","content":"{\"t\":null,\"sensor\":{}}"},
+                    {"time":2,"contentType":"application/json","content":"{\"sensor\":1}"},
+                    {"time":1,"contentType":"text/plain","content":"t"}
+                ]"#,
+            )
+            .create();
+
+        let report = mock_client(&server)
+            .check_schema(
+                "441200000050000",
+                Some(0),
+                Some(5),
+                &["t", "sensor", "sensor.h", "x"],
+            )
+            .unwrap();
+        assert_eq!(
+            report,
+            SchemaReport {
+                entries: 4,
+                counts: [("t", 2), ("sensor", 3), ("sensor.h", 1), ("x", 0)]
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), v))
+                    .collect(),
+            }
+        );
+        assert_eq!(report.missing_keys(), vec!["sensor", "sensor.h", "t", "x"]);
+    }
+
+    #[test]
+    fn test_get_data_entries_map() {
+        let mut server = Server::new();
+        server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::Any)
+            .with_body(
+                r#"[
+                    {"time":1669024325203,"contentType":"application/json","content":"{\"t\":3}"},
+                    {"time":1669024325202,"contentType":"application/json","content":"{\"t\":2}"},
+                    {"time":1669024325202,"contentType":"application/json","content":"{\"t\":1}"}
+                ]"#,
+            )
+            .create();
+
+        let client = mock_client(&server);
+
+        let map = client
+            .get_data_entries_map("441200000050000", Some(0), Some(1), Some(10))
+            .unwrap();
+        let map = map
+            .iter()
+            .map(|(time, d)| (*time, d.content.as_str()))
+            .collect::<Vec<_>>();
+
+        // ascending, and the last one wins for the duplicate timestamp
+        assert_eq!(
+            map,
+            vec![(1669024325202, r#"{"t":1}"#), (1669024325203, r#"{"t":3}"#)]
+        );
+    }
+
+    #[test]
+    fn test_over_limit() {
+        let mut server = Server::new();
+        server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::Any)
+            .with_body(
+                r#"[
+                    {"time":1669024325203,"contentType":"application/json","content":"{\"t\":3}"},
+                    {"time":1669024325202,"contentType":"application/json","content":"{\"t\":2}"},
+                    {"time":1669024325201,"contentType":"application/json","content":"{\"t\":1}"}
+                ]"#,
+            )
+            .create();
+
+        // truncates to the latest entries by default
+        let data = mock_client(&server)
+            .get_data_entries("441200000050000", Some(0), Some(1), Some(2))
+            .unwrap();
+        assert_eq!(
+            data.iter().map(|d| d.time).collect::<Vec<_>>(),
+            vec![1669024325203, 1669024325202]
+        );
+
+        let client = SoracomHarvestClient::builder()
+            .auth_key_id("keyId")
+            .auth_key_secret("secret")
+            .endpoint(server.url())
+            .over_limit(OverLimit::Error)
+            .build();
+        assert!(matches!(
+            client.get_data_entries("441200000050000", Some(0), Some(1), Some(2)),
+            Err(SoracomHarvestClientError::TooManyEntries {
+                limit: 2,
+                returned: 3
+            })
+        ));
+        assert_eq!(
+            client
+                .get_data_entries("441200000050000", Some(0), Some(1), Some(3))
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_bucketize() {
+        let mut server = Server::new();
+        server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("from".into(), "1669021200000".into()),
+                Matcher::UrlEncoded("to".into(), "1669032000000".into()),
+            ]))
+            .with_body(
+                // 2022-11-21T10:59:59.999Z, 10:00:00.000Z, 09:52:05.202Z and 09:00:00.000Z
+                r#"[
+                    {"time":1669028399999,"contentType":"application/json","content":"{\"t\":4}"},
+                    {"time":1669024800000,"contentType":"application/json","content":"{\"t\":3}"},
+                    {"time":1669024325202,"contentType":"application/json","content":"{\"t\":2}"},
+                    {"time":1669021200000,"contentType":"application/json","content":"{\"t\":1}"}
+                ]"#,
+            )
+            .create();
+
+        let client = mock_client(&server);
+
+        let buckets = client
+            .bucketize(
+                "441200000050000",
+                1669021200000,
+                1669032000000,
+                ChronoDuration::hours(1),
+            )
+            .unwrap();
+        let buckets = buckets
+            .iter()
+            .map(|(start, data)| (*start, data.iter().map(|d| d.time).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            buckets,
+            vec![
+                (1669021200000, vec![1669024325202, 1669021200000]),
+                (1669024800000, vec![1669028399999, 1669024800000]),
+            ]
+        );
+        assert!(matches!(
+            client.bucketize("441200000050000", 0, 1, ChronoDuration::zero()),
+            Err(SoracomHarvestClientError::InvalidBucket)
+        ));
+    }
+
+    #[test]
+    fn test_imsi_is_percent_encoded() {
+        let mut server = Server::new();
+        let get = server
+            .mock("GET", "/v1/data/Subscriber/4412%2F..%2F0%20050000")
+            .match_query(Matcher::Any)
+            .with_body("[]")
+            .create();
+        let delete = server
+            .mock(
+                "DELETE",
+                "/v1/data/Subscriber/4412%2F..%2F0%20050000/1669024325202",
+            )
+            .create();
+
+        let client = mock_client(&server);
+
+        client
+            .get_data_entries("4412/../0 050000", Some(0), Some(1), Some(1))
+            .unwrap();
+        client
+            .delete_data_entry("4412/../0 050000", 1669024325202)
+            .unwrap();
+        get.assert();
+        delete.assert();
+    }
+
+    #[test]
+    fn test_http_custom_endpoint() {
+        let mut server = Server::new();
+        server
+            .mock("POST", "/v1/auth")
+            .with_body(r#"{"apiKey":"api-key","token":"token"}"#)
+            .create();
+        let data = server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::Any)
+            .with_body("[]")
+            .create();
+
+        let endpoint = format!("http://localhost:{}", server.socket_address().port());
+        let client = SoracomHarvestClient::builder()
+            .auth_key_id("keyId")
+            .auth_key_secret("secret")
+            .endpoint(endpoint.as_str())
+            .build();
+        assert_eq!(client.endpoint.scheme(), "http");
+
+        let client = client.auth().unwrap();
+        client
+            .get_data_entries("441200000050000", Some(0), Some(1), Some(1))
+            .unwrap();
+        data.assert();
+
+        let client = SoracomHarvestClient::builder()
+            .auth_key_id("keyId")
+            .auth_key_secret("secret")
+            .endpoint(endpoint.as_str())
+            .https_only(true)
+            .build();
+        assert!(matches!(
+            client.auth(),
+            Err(SoracomHarvestClientError::InsecureEndpoint(e)) if e == endpoint
+        ));
+    }
+
+    #[test]
+    fn test_token_expires_at() {
+        let response = serde_json::from_str::<AuthResponse>(
+            r#"{"apiKey":"api-key","token":"token","operatorId":"OP0000000000","tokenTimeoutSeconds":86400}"#,
+        )
+        .unwrap();
+        assert_eq!(response.token_timeout_seconds, Some(86400));
+
+        let mut server = Server::new();
+        for (body, timeout) in [
+            (
+                r#"{"apiKey":"api-key","token":"token","tokenTimeoutSeconds":86400}"#,
+                86400,
+            ),
+            (
+                r#"{"apiKey":"api-key","token":"token"}"#,
+                DEFAULT_TOKEN_TIMEOUT_SECONDS,
+            ),
+        ] {
+            let auth = server.mock("POST", "/v1/auth").with_body(body).create();
+
+            let client = mock_client(&server);
+            assert_eq!(client.token_expires_at(), None);
+
+            let before = Utc::now();
+            let client = client.auth().unwrap();
+            let expires_at = client.token_expires_at().unwrap();
+            assert!(expires_at >= before + ChronoDuration::seconds(timeout));
+            assert!(expires_at <= Utc::now() + ChronoDuration::seconds(timeout));
+            assert_eq!(client.clone().token_expires_at(), Some(expires_at));
+            auth.remove();
+        }
+    }
+
+    #[test]
+    fn test_error_status() {
+        let mut server = Server::new();
+        server
+            .mock("POST", "/v1/auth")
+            .with_body(r#"{"apiKey":"api-key","token":"token"}"#)
+            .create();
+        let client = mock_client(&server).auth().unwrap();
+
+        for (status, imsi) in [(401, "441200000050001"), (500, "441200000050002")] {
+            server
+                .mock("GET", format!("/v1/data/Subscriber/{imsi}").as_str())
+                .match_query(Matcher::Any)
+                .with_status(status)
+                .with_body(r#"{"code":"AUT0001","message":"rejected"}"#)
+                .create();
+
+            match (client.get_data_entries(imsi, None, None, None), status) {
+                (Err(SoracomHarvestClientError::Unauthorized), 401) => {}
+                (Err(SoracomHarvestClientError::Status { status: 500 }), 500) => {}
+                (result, _) => panic!("unexpected result for {status}: {result:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_auth_endpoint() {
+        let mut auth_server = Server::new();
+        let auth = auth_server
+            .mock("POST", "/auth_endpoint/v1/auth")
+            .with_body(r#"{"apiKey":"api-key","token":"token"}"#)
+            .create();
+        let mut data_server = Server::new();
+        let data = data_server
+            .mock("GET", "/auth_endpoint/v1/data/Subscriber/441200000050000")
+            .match_header("x-soracom-token", "token")
+            .match_query(Matcher::Any)
+            .with_body("[]")
+            .create();
+
+        let client = SoracomHarvestClient::builder()
+            .auth_key_id("keyId")
+            .auth_key_secret("secret")
+            .endpoint(format!("{}/auth_endpoint", data_server.url()))
+            .auth_endpoint(format!("{}/auth_endpoint", auth_server.url()))
+            .build()
+            .auth()
+            .unwrap();
+        client
+            .get_data_entries("441200000050000", Some(0), Some(1), Some(1))
+            .unwrap();
+        auth.assert();
+        data.assert();
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut server = Server::new();
+        let data = server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::Any)
+            .with_body("[]")
+            .create();
+        let addr = server.socket_address();
+
+        // `.invalid` never resolves, unless overridden
+        let client = SoracomHarvestClient::builder()
+            .auth_key_id("keyId")
+            .auth_key_secret("secret")
+            .endpoint(format!("http://api.harvest.invalid:{}", addr.port()).as_str())
+            .dns_retry(DnsRetry::none())
+            .resolve([("api.harvest.invalid".to_string(), addr)])
+            .build();
+        client
+            .get_data_entries("441200000050000", Some(0), Some(1), Some(1))
+            .unwrap();
+        data.assert();
+    }
+
+    #[test]
+    fn test_unresolvable_endpoint() {
+        let client = SoracomHarvestClient::builder()
+            .auth_key_id("keyId")
+            .auth_key_secret("secret")
+            .endpoint("https://api.harvest.invalid")
+            .dns_retry(DnsRetry {
+                retries: 1,
+                backoff: Duration::from_millis(10),
+            })
+            .build();
+        assert!(matches!(
+            client.auth(),
+            Err(SoracomHarvestClientError::Resolve(_))
+        ));
+    }
+
+    #[test]
+    fn test_entries_iter() {
+        let mut server = Server::new();
+        let first = server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::Any)
+            .with_header("x-soracom-next-key", "1669024325202")
+            .with_body(
+                r#"[
+                    {"time":1669024325203,"contentType":"application/json","content":"{}"},
+                    {"time":1669024325202,"contentType":"application/json","content":"{}"}
+                ]"#,
+            )
+            .expect(1)
+            .create();
+        let second = server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::UrlEncoded(
+                "last_evaluated_key".into(),
+                "1669024325202".into(),
+            ))
+            .with_header("x-soracom-next-key", "1669024325201")
+            .with_body(
+                r#"[{"time":1669024325201,"contentType":"application/json","content":"{}"}]"#,
+            )
+            .expect(1)
+            .create();
+        let third = server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::UrlEncoded(
+                "last_evaluated_key".into(),
+                "1669024325201".into(),
+            ))
+            .with_status(500)
+            .with_body("Internal Server Error")
+            .create();
+
+        let client = mock_client(&server);
+
+        let mut iter = client.entries_iter("441200000050000", 0, 1669024325300);
+        assert_eq!(iter.next().unwrap().unwrap().time, 1669024325203);
+        assert_eq!(iter.next().unwrap().unwrap().time, 1669024325202);
+        // the next page isn't fetched until needed
+        first.assert();
+        assert!(!second.matched());
+
+        assert_eq!(iter.next().unwrap().unwrap().time, 1669024325201);
+        second.assert();
+        assert!(matches!(iter.next(), Some(Err(_))));
+        assert!(iter.next().is_none());
+        third.assert();
+    }
+
+    #[test]
+    fn test_list_imsis() {
+        let mut server = Server::new();
+        server
+            .mock("GET", "/v1/subscribers")
+            .match_query(Matcher::Any)
+            .with_header("x-soracom-next-key", "441200000050001")
+            .with_body(
+                r#"[{"imsi":"441200000050001","status":"active"},{"imsi":"440100000000001"}]"#,
+            )
+            .expect(1)
+            .create();
+        server
+            .mock("GET", "/v1/subscribers")
+            .match_query(Matcher::UrlEncoded(
+                "last_evaluated_key".into(),
+                "441200000050001".into(),
+            ))
+            .with_body(r#"[{"imsi":"441200000050000"}]"#)
+            .expect(1)
+            .create();
+
+        let client = mock_client(&server);
+
+        assert_eq!(
+            client.list_imsis("44120").unwrap(),
+            vec!["441200000050000", "441200000050001"]
+        );
+    }
+
+    #[test]
+    fn test_get_all_data_entries_capped() {
+        let page = r#"[{"time":1669024325202,"contentType":"application/json","content":"{}"}]"#;
+        let mut server = Server::new();
+        server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::Any)
+            .with_header("x-soracom-next-key", "1669024325202")
+            .with_body(page)
+            .expect(2)
+            .create();
+        let second = server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(Matcher::UrlEncoded(
+                "last_evaluated_key".into(),
+                "1669024325202".into(),
+            ))
+            .with_body(
+                r#"[{"time":1669024325201,"contentType":"application/json","content":"{}"}]"#,
+            )
+            .expect(1)
+            .create();
+
+        let client = mock_client(&server);
+
+        // the first page exceeds the cap, so the second page isn't fetched
+        let capped = client
+            .get_all_data_entries_capped("441200000050000", 0, 1669024325300, page.len() as u64 - 1)
+            .unwrap();
+        assert!(capped.truncated);
+        assert_eq!(capped.data.len(), 1);
+        assert!(!second.matched());
 
-        // plain JSON
+        let capped = client
+            .get_all_data_entries_capped("441200000050000", 0, 1669024325300, 1024)
+            .unwrap();
         assert_eq!(
-            SoracomHarvestClient::try_decode(r#"{"temperature":20}"#.to_string()),
-            r#"{"temperature":20}"#,
+            capped.data.iter().map(|d| d.time).collect::<Vec<_>>(),
+            vec![1669024325202, 1669024325201]
         );
+        assert!(!capped.truncated);
+        second.assert();
     }
 }
@@ -0,0 +1,117 @@
+//! Content type declaration for Soracom Harvest Data entries.
+//!
+//! # Example
+//!
+//! ```
+//! use soracom_harvest_api_client::content_type::ContentType;
+//!
+//! let json = ContentType::from("application/json");
+//! let csv = ContentType::from("Text/CSV; charset=utf-8");
+//! let other = ContentType::from("application/octet-stream");
+//!
+//! assert_eq!(json, ContentType::ApplicationJson);
+//! assert_eq!(csv.to_string(), "text/csv");
+//! assert_eq!(other, ContentType::Other("application/octet-stream".to_string()));
+//! ```
+
+use std::fmt::{Display, Formatter};
+
+/// Content type representation, based on the types commonly seen in Harvest.
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+pub enum ContentType {
+    /// `application/json`
+    ApplicationJson,
+
+    /// `text/csv`
+    TextCsv,
+
+    /// `text/plain`
+    TextPlain,
+
+    /// Any other content type, normalized to lowercase without parameters.
+    Other(String),
+}
+
+impl ContentType {
+    /// Returns `&str` representation of the content type.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ContentType::ApplicationJson => "application/json",
+            ContentType::TextCsv => "text/csv",
+            ContentType::TextPlain => "text/plain",
+            ContentType::Other(s) => s.as_str(),
+        }
+    }
+}
+
+impl Display for ContentType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl From<&str> for ContentType {
+    fn from(s: &str) -> Self {
+        // Ignore parameters like "; charset=utf-8" and casing of the media type.
+        let media_type = s
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+
+        match media_type.as_str() {
+            "application/json" => ContentType::ApplicationJson,
+            "text/csv" => ContentType::TextCsv,
+            "text/plain" => ContentType::TextPlain,
+            _ => ContentType::Other(media_type),
+        }
+    }
+}
+
+impl From<String> for ContentType {
+    fn from(s: String) -> Self {
+        ContentType::from(s.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::content_type::ContentType;
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(
+            ContentType::from("application/json"),
+            ContentType::ApplicationJson
+        );
+        assert_eq!(
+            ContentType::from("Application/JSON;charset=utf-8"),
+            ContentType::ApplicationJson
+        );
+        assert_eq!(ContentType::from("text/csv"), ContentType::TextCsv);
+        assert_eq!(
+            ContentType::from(" text/plain ; charset=us-ascii"),
+            ContentType::TextPlain
+        );
+        assert_eq!(
+            ContentType::from("application/octet-stream"),
+            ContentType::Other("application/octet-stream".to_string())
+        );
+        assert_eq!(
+            ContentType::from("Image/PNG"),
+            ContentType::Other("image/png".to_string())
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(ContentType::ApplicationJson.to_string(), "application/json");
+        assert_eq!(ContentType::TextCsv.to_string(), "text/csv");
+        assert_eq!(ContentType::TextPlain.to_string(), "text/plain");
+        assert_eq!(
+            ContentType::Other("image/png".to_string()).to_string(),
+            "image/png"
+        );
+    }
+}
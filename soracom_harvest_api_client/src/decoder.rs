@@ -0,0 +1,387 @@
+//! Decoders for the payload of Soracom Harvest Data entries.
+//!
+//! If the content of an entry is like `{"payload": "value"}`, the payload could be encoded binary data. The
+//! decoders are tried in order, and the first one yielding a UTF-8 string with only ASCII printable
+//! characters wins, returning `{"value": "<decoded string>"}` as the content, where the key is configurable
+//! with [`DecodeOptions::value_key`]. If [`DecodeOptions::unwrap_json`] is set, a decoded JSON object or
+//! array is returned as the content itself instead. Otherwise the content is
+//! returned as is, or as `{"hex": "..."}` or `{"base64": "..."}` if the payload is base64-encoded binary and
+//! [`BinaryFormat`] is set accordingly.
+//!
+//! # Example
+//!
+//! ```
+//! use soracom_harvest_api_client::decoder::{decode, BinaryFormat, DecodeOptions, Decoder};
+//!
+//! let options = DecodeOptions {
+//!     decoders: Decoder::parse_list("hex,base64").unwrap(),
+//!     binary_format: BinaryFormat::Hex,
+//!     ..Default::default()
+//! };
+//! assert_eq!(options.decoders, vec![Decoder::Hex, Decoder::Base64]);
+//!
+//! assert_eq!(decode(&options, r#"{"payload":"68656c6c6f"}"#.to_string()), r#"{"value":"hello"}"#);
+//! assert_eq!(decode(&options, r#"{"payload":"aGVsbG8="}"#.to_string()), r#"{"value":"hello"}"#);
+//! assert_eq!(decode(&options, r#"{"payload":"ChsK"}"#.to_string()), r#"{"hex":"0a1b0a"}"#);
+//! ```
+
+use serde::Deserialize;
+use serde_json::Value;
+use std::str::FromStr;
+
+#[derive(Deserialize, Debug)]
+struct EncodedPayload {
+    pub payload: String,
+}
+
+/// Options to decode a payload.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct DecodeOptions {
+    /// Decoders to try in order. Defaults to [`Decoder::default_order`].
+    pub decoders: Vec<Decoder>,
+
+    /// Representation of a binary payload none of the decoders yields printable output for. Defaults to
+    /// [`BinaryFormat::Payload`].
+    pub binary_format: BinaryFormat,
+
+    /// Key of the decoded string in the output, i.e. `{"<value_key>": "<decoded string>"}`. Defaults to
+    /// `value`.
+    pub value_key: String,
+
+    /// Return the decoded string as the content as is if it is a JSON object or array, e.g. `{"temp":21}`
+    /// rather than `{"value":"{\"temp\":21}"}`, for devices encoding JSON. Defaults to `false`.
+    pub unwrap_json: bool,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        DecodeOptions {
+            decoders: Decoder::default_order(),
+            binary_format: BinaryFormat::default(),
+            value_key: "value".to_string(),
+            unwrap_json: false,
+        }
+    }
+}
+
+/// Representation of a base64-encoded binary payload, which can't be decoded as printable string.
+#[derive(Debug, Default, Eq, PartialEq, Clone, Copy)]
+pub enum BinaryFormat {
+    /// Keep the content as is, i.e. `{"payload": "<base64>"}`.
+    #[default]
+    Payload,
+
+    /// `{"hex": "<hex>"}`, e.g. `{"hex": "0a1b0a"}`.
+    Hex,
+
+    /// `{"base64": "<base64>"}`, marking the content as binary.
+    Base64,
+}
+
+impl FromStr for BinaryFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "payload" => Ok(BinaryFormat::Payload),
+            "hex" => Ok(BinaryFormat::Hex),
+            "base64" => Ok(BinaryFormat::Base64),
+            _ => Err(format!("Unknown binary format: {s}")),
+        }
+    }
+}
+
+/// Strategy to decode a payload.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Decoder {
+    /// Base64-encoded data.
+    Base64,
+
+    /// Hex-encoded data, e.g. `68656c6c6f`.
+    Hex,
+
+    /// Base64-encoded gzip-compressed data. Requires `flate2` feature.
+    #[cfg(feature = "flate2")]
+    Gzip,
+
+    /// Keep the content as is, or in the [`BinaryFormat`]. Decoders after this are never tried.
+    Raw,
+}
+
+impl Decoder {
+    /// Returns the default order, which tries base64 only.
+    pub fn default_order() -> Vec<Decoder> {
+        vec![Decoder::Base64, Decoder::Raw]
+    }
+
+    /// Parses a comma-separated list of decoders, e.g. `base64,hex,raw`.
+    pub fn parse_list(s: &str) -> Result<Vec<Decoder>, String> {
+        s.split(',').map(|d| d.trim().parse()).collect()
+    }
+
+    /// Returns the decoded bytes, or `None` if the payload isn't in the encoding.
+    fn decode(&self, payload: &str) -> Option<Vec<u8>> {
+        match self {
+            Decoder::Base64 => base64::decode(payload).ok(),
+            Decoder::Hex => decode_hex(payload),
+            #[cfg(feature = "flate2")]
+            Decoder::Gzip => {
+                use std::io::Read;
+
+                let compressed = base64::decode(payload).ok()?;
+                let mut decompressed = Vec::new();
+                flate2::read::GzDecoder::new(compressed.as_slice())
+                    .read_to_end(&mut decompressed)
+                    .ok()?;
+                Some(decompressed)
+            }
+            Decoder::Raw => None,
+        }
+    }
+}
+
+impl FromStr for Decoder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "base64" => Ok(Decoder::Base64),
+            "hex" => Ok(Decoder::Hex),
+            #[cfg(feature = "flate2")]
+            "gzip" => Ok(Decoder::Gzip),
+            "raw" => Ok(Decoder::Raw),
+            _ => Err(format!("Unknown decoder: {s}")),
+        }
+    }
+}
+
+/// Returns the decoded content by trying the decoders in order, or the content in the binary format if none
+/// of them yields printable output.
+pub fn decode(options: &DecodeOptions, content: String) -> String {
+    if let Ok(encoded) = serde_json::from_str::<EncodedPayload>(content.as_str()) {
+        for decoder in &options.decoders {
+            if *decoder == Decoder::Raw {
+                break;
+            }
+
+            // If the payload can be decoded, and can be decoded as UTF-8 string,
+            if let Some(str) = decoder
+                .decode(&encoded.payload)
+                .and_then(|d| String::from_utf8(d).ok())
+            {
+                // and the decoded string is a JSON object or array to unwrap, return it as the content,
+                if options.unwrap_json {
+                    if let Ok(json @ (Value::Object(_) | Value::Array(_))) =
+                        serde_json::from_str::<Value>(&str)
+                    {
+                        return json.to_string();
+                    }
+                }
+
+                // or the decoded string has only ASCII printable characters,
+                if str.chars().all(|c| matches!(c as u8, 0x20..=0x7E)) {
+                    // return {"<value key>": "<decoded string>"} as the content.
+                    let mut value = serde_json::Map::new();
+                    value.insert(options.value_key.clone(), str.into());
+                    return Value::Object(value).to_string();
+                }
+            }
+        }
+
+        // If the payload is base64-encoded binary, return it in the binary format.
+        if let Ok(binary) = base64::decode(&encoded.payload) {
+            match options.binary_format {
+                BinaryFormat::Payload => {}
+                BinaryFormat::Hex => {
+                    let hex = binary
+                        .iter()
+                        .map(|b| format!("{b:02x}"))
+                        .collect::<String>();
+                    return serde_json::json!({ "hex": hex }).to_string();
+                }
+                BinaryFormat::Base64 => {
+                    return serde_json::json!({ "base64": encoded.payload }).to_string();
+                }
+            }
+        }
+    }
+    // Otherwise return original content as is.
+    content
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decoder::{decode, BinaryFormat, DecodeOptions, Decoder};
+
+    fn options(decoders: &[Decoder]) -> DecodeOptions {
+        DecodeOptions {
+            decoders: decoders.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        let options = DecodeOptions::default();
+
+        // valid base64
+        assert_eq!(
+            decode(&options, r#"{"payload":"aGVsbG8="}"#.to_string()),
+            r#"{"value":"hello"}"#,
+        );
+
+        // invalid base64
+        assert_eq!(
+            decode(&options, r#"{"payload":"aGVsbG"}"#.to_string()),
+            r#"{"payload":"aGVsbG"}"#,
+        );
+
+        // not ASCII printable ('\012\033')
+        assert_eq!(
+            decode(&options, r#"{"payload":"ChsK"}"#.to_string()),
+            r#"{"payload":"ChsK"}"#,
+        );
+
+        // plain JSON
+        assert_eq!(
+            decode(&options, r#"{"temperature":20}"#.to_string()),
+            r#"{"temperature":20}"#,
+        );
+    }
+
+    #[test]
+    fn test_decode_order() {
+        // "63616665" is valid as both hex and base64, but only hex yields printable output
+        let content = r#"{"payload":"63616665"}"#;
+        assert_eq!(
+            decode(
+                &options(&[Decoder::Hex, Decoder::Base64]),
+                content.to_string()
+            ),
+            r#"{"value":"cafe"}"#
+        );
+        assert_eq!(
+            decode(&options(&[Decoder::Raw, Decoder::Hex]), content.to_string()),
+            content
+        );
+        assert_eq!(decode(&options(&[]), content.to_string()), content);
+
+        assert_eq!(
+            Decoder::parse_list("base64, HEX,raw"),
+            Ok(vec![Decoder::Base64, Decoder::Hex, Decoder::Raw])
+        );
+        assert!(Decoder::parse_list("base64,rot13").is_err());
+    }
+
+    #[test]
+    fn test_decode_value_key() {
+        let options = DecodeOptions {
+            value_key: "decoded".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            decode(&options, r#"{"payload":"aGVsbG8="}"#.to_string()),
+            r#"{"decoded":"hello"}"#
+        );
+
+        // quotes in the decoded string are escaped ('say "hi"')
+        assert_eq!(
+            decode(&options, r#"{"payload":"c2F5ICJoaSI="}"#.to_string()),
+            r#"{"decoded":"say \"hi\""}"#
+        );
+    }
+
+    #[test]
+    fn test_decode_unwrap_json() {
+        // '{"temp":21}'
+        let content = r#"{"payload":"eyJ0ZW1wIjoyMX0="}"#;
+        assert_eq!(
+            decode(&DecodeOptions::default(), content.to_string()),
+            r#"{"value":"{\"temp\":21}"}"#
+        );
+
+        let options = DecodeOptions {
+            unwrap_json: true,
+            ..Default::default()
+        };
+        assert_eq!(decode(&options, content.to_string()), r#"{"temp":21}"#);
+        // pretty-printed JSON isn't ASCII printable, but unwrapped ('{\n  "temp": 21\n}')
+        assert_eq!(
+            decode(
+                &options,
+                r#"{"payload":"ewogICJ0ZW1wIjogMjEKfQ=="}"#.to_string()
+            ),
+            r#"{"temp":21}"#
+        );
+        // JSON other than object or array, or not JSON at all, is wrapped as usual ('21', 'hello')
+        assert_eq!(
+            decode(&options, r#"{"payload":"MjE="}"#.to_string()),
+            r#"{"value":"21"}"#
+        );
+        assert_eq!(
+            decode(&options, r#"{"payload":"aGVsbG8="}"#.to_string()),
+            r#"{"value":"hello"}"#
+        );
+    }
+
+    #[test]
+    fn test_decode_binary() {
+        // not ASCII printable ('\012\033\012')
+        let content = r#"{"payload":"ChsK"}"#;
+        let with_format = |binary_format| DecodeOptions {
+            binary_format,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            decode(&with_format(BinaryFormat::Payload), content.to_string()),
+            content
+        );
+        assert_eq!(
+            decode(&with_format(BinaryFormat::Hex), content.to_string()),
+            r#"{"hex":"0a1b0a"}"#
+        );
+        assert_eq!(
+            decode(&with_format(BinaryFormat::Base64), content.to_string()),
+            r#"{"base64":"ChsK"}"#
+        );
+        // invalid base64 is kept as is
+        assert_eq!(
+            decode(
+                &with_format(BinaryFormat::Hex),
+                r#"{"payload":"aGVsbG"}"#.to_string()
+            ),
+            r#"{"payload":"aGVsbG"}"#
+        );
+    }
+
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn test_decode_gzip() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"hello").unwrap();
+        let payload = base64::encode(encoder.finish().unwrap());
+
+        assert_eq!(
+            decode(
+                &options(&[Decoder::Gzip, Decoder::Base64]),
+                format!(r#"{{"payload":"{payload}"}}"#)
+            ),
+            r#"{"value":"hello"}"#
+        );
+    }
+}
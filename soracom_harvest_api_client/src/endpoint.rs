@@ -8,28 +8,31 @@
 //! let default = Endpoint::default();
 //! let jp = Endpoint::Japan;
 //! let g = Endpoint::from("global");
+//! let custom = Endpoint::from("http://localhost:8080/");
 //!
 //! assert_eq!(default.to_string(), "https://g.api.soracom.io");
 //! assert_eq!(jp.to_string(), "https://api.soracom.io");
 //! assert_eq!(g.to_string(), "https://g.api.soracom.io");
+//! assert_eq!(custom.to_string(), "http://localhost:8080");
+//! assert_eq!(custom.scheme(), "http");
+//! assert_eq!(jp.scheme(), "https");
 //! ```
 
 use std::fmt::{Display, Formatter};
 
-/// Endpoint representation, based on SORACOM coverage.
-#[derive(Debug, Eq, PartialEq, Clone)]
+/// Endpoint representation, based on SORACOM coverage. Non-exhaustive, so that more endpoints can be added.
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+#[non_exhaustive]
 pub enum Endpoint {
     /// Global coverage
+    #[default]
     Global,
 
     /// Japan coverage
     Japan,
-}
 
-impl Default for Endpoint {
-    fn default() -> Self {
-        Endpoint::Global
-    }
+    /// Custom base URL, e.g. for a proxy or a local mock server
+    Custom(String),
 }
 
 impl Endpoint {
@@ -38,6 +41,16 @@ impl Endpoint {
         match self {
             Endpoint::Global => "https://g.api.soracom.io",
             Endpoint::Japan => "https://api.soracom.io",
+            Endpoint::Custom(url) => url.as_str(),
+        }
+    }
+
+    /// Returns the scheme of the endpoint, `https` or `http`. The scheme of a custom endpoint is kept as
+    /// given, and never upgraded to `https`.
+    pub fn scheme(&self) -> &str {
+        match self.as_str().split_once("://") {
+            Some((scheme, _)) => scheme,
+            None => "https",
         }
     }
 }
@@ -50,6 +63,10 @@ impl Display for Endpoint {
 
 impl From<&str> for Endpoint {
     fn from(s: &str) -> Self {
+        if s.starts_with("http://") || s.starts_with("https://") {
+            return Endpoint::Custom(s.trim_end_matches('/').to_string());
+        }
+
         match s.to_lowercase().as_str() {
             "g" | "global" => Endpoint::Global,
             "jp" | "japan" => Endpoint::Japan,
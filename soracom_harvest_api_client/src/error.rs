@@ -9,14 +9,79 @@ pub enum SoracomHarvestClientError {
     #[error("Failed to authenticate with auth key ID and auth key secret given")]
     Auth,
 
+    /// The API responded with 401 status code, e.g. as the token is expired. Authenticate again to retry.
+    #[error("The API rejected the credentials, e.g. as the token is expired")]
+    Unauthorized,
+
+    /// The API responded with other non-2xx status code.
+    #[error("The API responded with status {status}")]
+    Status {
+        /// HTTP status code of the response.
+        status: u16,
+    },
+
     /// Invalid limit is provided. It should be from 1 to 1000.
     #[error("Invalid limit is provided. It should be from 1 to 1000")]
     InvalidLimit,
 
+    /// Invalid minutes is provided, which goes out of the range of a timestamp.
+    #[error("Invalid minutes is provided. It should be within the range of a timestamp")]
+    InvalidMinutes,
+
+    /// Invalid bucket duration is provided. It should be at least 1 millisecond.
+    #[error("Invalid bucket is provided. It should be at least 1 millisecond")]
+    InvalidBucket,
+
+    /// The endpoint isn't `https`, while the client is `https_only`.
+    #[error("The endpoint {0} is not https, while the client is https only")]
+    InsecureEndpoint(String),
+
+    /// A parameter is omitted while the client is in strict mode, see `strict_defaults`.
+    #[error("`{0}` is required as the client is in strict mode")]
+    MissingParameter(&'static str),
+
+    /// The API returned more data entries than the requested limit, with `OverLimit::Error`.
+    #[error("The API returned {returned} data entries, more than the limit {limit}")]
+    TooManyEntries {
+        /// Requested limit.
+        limit: u32,
+        /// Number of data entries returned.
+        returned: usize,
+    },
+
+    /// Data entries are required, but the query yields no entries.
+    #[error("No data entries found for the query")]
+    NoData,
+
+    /// Failed to send a message to Soracom Harvest Data.
+    #[error("Failed to send a message: {0}")]
+    Send(String),
+
+    /// The message sent isn't found in the data entries within the timeout.
+    #[error("The message sent is not found within the timeout")]
+    NotVerified,
+
+    /// Server time is not available from the response.
+    #[error("Server time is not available from the response")]
+    NoServerTime,
+
+    /// The host of the endpoint couldn't be resolved, even after retries.
+    #[error("Failed to resolve {0}")]
+    Resolve(String),
+
+    /// Malformed MessagePack is provided. Requires `msgpack` feature.
+    #[cfg(feature = "msgpack")]
+    #[error("Malformed MessagePack: {0}")]
+    Msgpack(String),
+
     /// Transparent error from [`reqwest`](https://docs.rs/reqwest/latest/reqwest/) crate.
     #[error(transparent)]
     Request(#[from] reqwest::Error),
 
+    /// Transparent I/O error e.g. while reading NDJSON to import.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
     /// Transparent error from [`serde_json`](https://docs.rs/serde_json/latest/serde_json/) crate.
     #[error(transparent)]
     Json(#[from] serde_json::Error),
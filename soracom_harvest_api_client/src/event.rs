@@ -0,0 +1,110 @@
+//! Debug events emitted for each API call via the [`log`](https://docs.rs/log/latest/log/) crate.
+//!
+//! Events are logged as free text like `operation=get_data_entries imsi=... status=200 rows=2 elapsed_ms=120`
+//! by default, or as a JSON object with the `json-logs` feature.
+
+use serde::Serialize;
+use std::{
+    fmt::{Display, Formatter},
+    time::Instant,
+};
+
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ApiEvent<'a> {
+    pub operation: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub imsi: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rows: Option<usize>,
+    pub elapsed_ms: u128,
+}
+
+impl<'a> ApiEvent<'a> {
+    /// Returns a new event for the operation, with elapsed time since `start`.
+    pub fn new(operation: &'a str, start: Instant) -> Self {
+        ApiEvent {
+            operation,
+            elapsed_ms: start.elapsed().as_millis(),
+            ..Default::default()
+        }
+    }
+
+    /// Emits the event at debug level.
+    pub fn emit(&self) {
+        #[cfg(feature = "json-logs")]
+        log::debug!("{}", serde_json::to_string(self).unwrap_or_default());
+        #[cfg(not(feature = "json-logs"))]
+        log::debug!("{self}");
+    }
+}
+
+impl Display for ApiEvent<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "operation={}", self.operation)?;
+        if let Some(imsi) = self.imsi {
+            write!(f, " imsi={imsi}")?;
+        }
+        if let Some(from) = self.from {
+            write!(f, " from={from}")?;
+        }
+        if let Some(to) = self.to {
+            write!(f, " to={to}")?;
+        }
+        if let Some(status) = self.status {
+            write!(f, " status={status}")?;
+        }
+        if let Some(rows) = self.rows {
+            write!(f, " rows={rows}")?;
+        }
+        write!(f, " elapsed_ms={}", self.elapsed_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event::ApiEvent;
+
+    #[test]
+    fn test_format() {
+        let event = ApiEvent {
+            operation: "get_data_entries",
+            imsi: Some("441200000050000"),
+            from: Some(1668003111681),
+            to: Some(1668604289406),
+            status: Some(200),
+            rows: Some(2),
+            elapsed_ms: 120,
+        };
+
+        assert_eq!(
+            event.to_string(),
+            "operation=get_data_entries imsi=441200000050000 from=1668003111681 to=1668604289406 status=200 rows=2 elapsed_ms=120"
+        );
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"operation":"get_data_entries","imsi":"441200000050000","from":1668003111681,"to":1668604289406,"status":200,"rows":2,"elapsed_ms":120}"#
+        );
+    }
+
+    #[test]
+    fn test_format_skips_missing_fields() {
+        let event = ApiEvent {
+            operation: "auth",
+            status: Some(200),
+            elapsed_ms: 80,
+            ..Default::default()
+        };
+
+        assert_eq!(event.to_string(), "operation=auth status=200 elapsed_ms=80");
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"operation":"auth","status":200,"elapsed_ms":80}"#
+        );
+    }
+}
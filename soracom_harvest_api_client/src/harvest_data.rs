@@ -0,0 +1,103 @@
+//! Trait abstracting the operations of [`SoracomHarvestClient`], so that code depending on it can be tested
+//! against a fake without network or credentials.
+
+use crate::{
+    client::{Data, SoracomHarvestClient},
+    error::SoracomHarvestClientError,
+};
+
+/// Operations on Soracom Harvest Data, implemented by [`SoracomHarvestClient`].
+///
+/// # Example
+///
+/// ```
+/// use soracom_harvest_api_client::{
+///     client::Data, error::SoracomHarvestClientError, harvest_data::HarvestData,
+/// };
+///
+/// // code under test depends on the trait, instead of the client
+/// fn latest_content(client: &impl HarvestData, imsi: &str) -> Option<String> {
+///     let data = client.get_data_entries(imsi, None, None, Some(1)).ok()?;
+///     data.into_iter().next().map(|d| d.content)
+/// }
+///
+/// struct FakeClient;
+///
+/// impl HarvestData for FakeClient {
+///     fn auth(&self) -> Result<Self, SoracomHarvestClientError> {
+///         Ok(FakeClient)
+///     }
+///
+///     fn get_data_entries(
+///         &self,
+///         _imsi: impl Into<String>,
+///         _from: Option<i64>,
+///         _to: Option<i64>,
+///         _limit: Option<u32>,
+///     ) -> Result<Vec<Data>, SoracomHarvestClientError> {
+///         Ok(vec![Data {
+///             time: 1669024325202,
+///             content_type: "application/json".to_string(),
+///             content: r#"{"temperature":20}"#.to_string(),
+///         }])
+///     }
+///
+///     fn delete_data_entry(
+///         &self,
+///         _imsi: impl Into<String>,
+///         _time: i64,
+///     ) -> Result<(), SoracomHarvestClientError> {
+///         Ok(())
+///     }
+/// }
+///
+/// assert_eq!(
+///     latest_content(&FakeClient, "441200000050000"),
+///     Some(r#"{"temperature":20}"#.to_string())
+/// );
+/// ```
+pub trait HarvestData: Sized {
+    /// Authenticates, and returns the authenticated one. See [`SoracomHarvestClient::auth`].
+    fn auth(&self) -> Result<Self, SoracomHarvestClientError>;
+
+    /// Returns a vec of data entries sent from a SIM. See [`SoracomHarvestClient::get_data_entries`].
+    fn get_data_entries(
+        &self,
+        imsi: impl Into<String>,
+        from: Option<i64>,
+        to: Option<i64>,
+        limit: Option<u32>,
+    ) -> Result<Vec<Data>, SoracomHarvestClientError>;
+
+    /// Deletes a data entry identified with IMSI and timestamp. See
+    /// [`SoracomHarvestClient::delete_data_entry`].
+    fn delete_data_entry(
+        &self,
+        imsi: impl Into<String>,
+        time: i64,
+    ) -> Result<(), SoracomHarvestClientError>;
+}
+
+impl HarvestData for SoracomHarvestClient {
+    fn auth(&self) -> Result<Self, SoracomHarvestClientError> {
+        SoracomHarvestClient::auth(self)
+    }
+
+    fn get_data_entries(
+        &self,
+        imsi: impl Into<String>,
+        from: Option<i64>,
+        to: Option<i64>,
+        limit: Option<u32>,
+    ) -> Result<Vec<Data>, SoracomHarvestClientError> {
+        SoracomHarvestClient::get_data_entries(self, imsi, from, to, limit)
+    }
+
+    fn delete_data_entry(
+        &self,
+        imsi: impl Into<String>,
+        time: i64,
+    ) -> Result<(), SoracomHarvestClientError> {
+        SoracomHarvestClient::delete_data_entry(self, imsi, time)
+    }
+}
@@ -1,5 +1,15 @@
 //! Simple API client for Soracom Harvest Data.
+//!
+//! Each API call emits a debug event via the [`log`](https://docs.rs/log/latest/log/) crate, with fields like
+//! `operation`, `imsi`, `from`, `to`, `status`, `rows` and `elapsed_ms`. Enable the `json-logs` feature to
+//! emit the events as JSON objects instead of free text.
 
 pub mod client;
+pub mod content_type;
+pub mod decoder;
 pub mod endpoint;
 pub mod error;
+mod event;
+pub mod harvest_data;
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
@@ -0,0 +1,63 @@
+//! [MessagePack](https://msgpack.org) serialization of data entries with
+//! [`rmp-serde`](https://docs.rs/rmp-serde/latest/rmp_serde/), for archival. Requires `msgpack` feature.
+//!
+//! Data entries are serialized as an array of maps with `time`, `contentType` and `content` keys, the same
+//! shape as the JSON of the API, so that any MessagePack library can read it.
+//!
+//! # Example
+//!
+//! ```
+//! use soracom_harvest_api_client::{client::Data, msgpack};
+//!
+//! let data = vec![Data {
+//!     time: 1669024325202,
+//!     content_type: "application/json".to_string(),
+//!     content: r#"{"value":"hello"}"#.to_string(),
+//! }];
+//!
+//! let bytes = msgpack::to_vec(&data).unwrap();
+//! assert_eq!(msgpack::from_slice(&bytes).unwrap(), data);
+//! ```
+
+use crate::{client::Data, error::SoracomHarvestClientError};
+
+/// Returns the data entries serialized as MessagePack.
+pub fn to_vec(data: &[Data]) -> Result<Vec<u8>, SoracomHarvestClientError> {
+    rmp_serde::to_vec_named(data).map_err(|e| SoracomHarvestClientError::Msgpack(e.to_string()))
+}
+
+/// Returns data entries deserialized from MessagePack, as serialized by [`to_vec`].
+pub fn from_slice(bytes: &[u8]) -> Result<Vec<Data>, SoracomHarvestClientError> {
+    rmp_serde::from_slice(bytes).map_err(|e| SoracomHarvestClientError::Msgpack(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        client::Data,
+        msgpack::{from_slice, to_vec},
+    };
+
+    #[test]
+    fn test_round_trip() {
+        let data = vec![
+            Data {
+                time: 1669024327201,
+                content_type: "application/json".to_string(),
+                content: r#"{"temperature":4096}"#.to_string(),
+            },
+            Data {
+                time: -1,
+                content_type: "".to_string(),
+                content: "x".repeat(70000),
+            },
+        ];
+
+        let bytes = to_vec(&data).unwrap();
+        assert_eq!(&bytes[..2], &[0x92, 0x83]);
+        assert_eq!(from_slice(&bytes).unwrap(), data);
+        assert_eq!(from_slice(&to_vec(&[]).unwrap()).unwrap(), vec![]);
+
+        assert!(from_slice(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+//! Async variants of the send functions, for async runtimes e.g. tokio. Requires `async` feature. They send
+//! to the production endpoints without a timeout, unlike [`Sender`](crate::sender::Sender).
+
+use crate::{
+    error::SendError, sender::unspecified_addr, SORACOM_HARVEST_HTTP_ENDPOINT,
+    SORACOM_HARVEST_TCP_UDP_ENDPOINT, SORACOM_HARVEST_USER_AGENT,
+};
+use reqwest::{
+    header::{CONTENT_TYPE, USER_AGENT},
+    Client,
+};
+use std::sync::OnceLock;
+use tokio::net::{lookup_host, UdpSocket};
+
+/// Client shared by all async HTTP sends, so that connections are pooled.
+static CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// Async variant of [`send_http_message`](crate::send_http_message). All calls share one `reqwest::Client`,
+/// so that connections are reused under load.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// soracom_harvest_client::send_http_message_async(r#"{"temperature":20}"#).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn send_http_message_async(body: impl Into<String>) -> Result<(), SendError> {
+    post(SORACOM_HARVEST_HTTP_ENDPOINT, body.into()).await
+}
+
+/// Async variant of [`send_udp_message`](crate::send_udp_message), using `tokio::net::UdpSocket`.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// soracom_harvest_client::send_udp_message_async("hello").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn send_udp_message_async(data: impl Into<String>) -> Result<(), SendError> {
+    send_to(SORACOM_HARVEST_TCP_UDP_ENDPOINT, data.into()).await
+}
+
+async fn post(url: &str, body: String) -> Result<(), SendError> {
+    CLIENT
+        .get_or_init(Client::new)
+        .post(url)
+        .header(USER_AGENT, SORACOM_HARVEST_USER_AGENT)
+        .header(CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await?;
+
+    Ok(())
+}
+
+async fn send_to(endpoint: &str, data: String) -> Result<(), SendError> {
+    // try the addresses in order, with a socket of the address family of each
+    let mut last_error = None;
+    for addr in lookup_host(endpoint)
+        .await
+        .map_err(|e| SendError::Resolve(format!("{endpoint}: {e}")))?
+    {
+        let socket = match UdpSocket::bind(unspecified_addr(addr)).await {
+            Ok(socket) => socket,
+            Err(e) => {
+                last_error = Some(e);
+                continue;
+            }
+        };
+        match socket.send_to(data.as_bytes(), addr).await {
+            Ok(_) => return Ok(()),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    Err(match last_error {
+        Some(e) => e.into(),
+        None => SendError::Resolve(format!("{endpoint}: no addresses")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::async_sender::{post, send_to};
+    use std::{net::UdpSocket, time::Duration};
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn test_futures_are_send() {
+        fn assert_send<T: Send>(_: T) {}
+
+        assert_send(crate::send_http_message_async("{}"));
+        assert_send(crate::send_udp_message_async("hello"));
+    }
+
+    #[test]
+    fn test_post() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .match_header("content-type", "application/json")
+            .match_header("user-agent", "soracom_harvest_api_client")
+            .match_body("{}")
+            .expect(2)
+            .create();
+
+        block_on(async {
+            post(&server.url(), "{}".to_string()).await.unwrap();
+            post(&server.url(), "{}".to_string()).await.unwrap();
+        });
+        mock.assert();
+    }
+
+    #[test]
+    fn test_send_to() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let endpoint = receiver.local_addr().unwrap().to_string();
+        let v6_receiver = UdpSocket::bind("[::1]:0").unwrap();
+        v6_receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let v6_endpoint = v6_receiver.local_addr().unwrap().to_string();
+
+        block_on(send_to(&endpoint, "hello".to_string())).unwrap();
+        block_on(send_to(&v6_endpoint, "hello".to_string())).unwrap();
+
+        let mut buf = [0u8; 16];
+        for receiver in [receiver, v6_receiver] {
+            let len = receiver.recv(&mut buf).unwrap();
+            assert_eq!(&buf[..len], b"hello");
+        }
+    }
+}
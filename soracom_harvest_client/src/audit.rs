@@ -0,0 +1,37 @@
+//! Local SQLite audit log of sent messages.
+
+use chrono::Utc;
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+/// Appends a record of a send to the `sent_messages` table of the database at `path`, creating the table if
+/// needed. `result` is the detail of the outcome, e.g. the HTTP status code or the error message.
+pub(crate) fn record(
+    path: &Path,
+    protocol: &str,
+    bytes: usize,
+    result: Result<String, String>,
+) -> rusqlite::Result<()> {
+    let conn = Connection::open(path)?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sent_messages (
+            timestamp INTEGER NOT NULL,
+            protocol TEXT NOT NULL,
+            bytes INTEGER NOT NULL,
+            outcome TEXT NOT NULL,
+            detail TEXT
+        )",
+        (),
+    )?;
+
+    let (outcome, detail) = match result {
+        Ok(detail) => ("ok", detail),
+        Err(detail) => ("error", detail),
+    };
+    conn.execute(
+        "INSERT INTO sent_messages (timestamp, protocol, bytes, outcome, detail) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![Utc::now().timestamp_millis(), protocol, bytes, outcome, detail],
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,120 @@
+//! Retry of DNS resolution.
+//!
+//! On a freshly-booted cellular device, DNS may not be ready for a few seconds after the modem connects, so
+//! the first sends fail only because `harvest.soracom.io` can't be resolved yet. [`resolve`] retries the
+//! resolution with backoff to paper over the race, before the actual connect. [`ResolvedHosts`] does it only
+//! until the host has been resolved once, as DNS is ready from then on.
+
+use std::{
+    collections::HashSet,
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// Policy to retry DNS resolution failures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DnsRetry {
+    /// Number of retries after the first attempt. `0` disables retry.
+    pub retries: u32,
+
+    /// Wait before the first retry, doubled on each retry.
+    pub backoff: Duration,
+}
+
+impl DnsRetry {
+    /// Returns a policy which never retries.
+    pub fn none() -> Self {
+        DnsRetry {
+            retries: 0,
+            backoff: Duration::ZERO,
+        }
+    }
+}
+
+impl Default for DnsRetry {
+    /// Retries 3 times, waiting 0.5, 1 and 2 seconds.
+    fn default() -> Self {
+        DnsRetry {
+            retries: 3,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Resolves `addr` e.g. `("harvest.soracom.io", 80)` or `"harvest.soracom.io:8514"`, retrying with the
+/// policy. Returns the error of the last attempt if all attempts fail.
+pub fn resolve(addr: impl ToSocketAddrs, retry: &DnsRetry) -> io::Result<Vec<SocketAddr>> {
+    let mut backoff = retry.backoff;
+    let mut attempt = 0;
+
+    loop {
+        match addr.to_socket_addrs() {
+            Ok(addrs) => return Ok(addrs.collect()),
+            Err(e) if attempt >= retry.retries => return Err(e),
+            Err(e) => {
+                log::debug!("failed to resolve, retrying in {backoff:?}: {e}");
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Hosts resolved successfully, shared by clones, so that the resolution with retry blocks only the requests
+/// until DNS is ready, not every request. The HTTP client resolves the host on its own anyway.
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedHosts(Arc<Mutex<HashSet<(String, u16)>>>);
+
+impl ResolvedHosts {
+    /// Resolves `host` and `port` with the policy, unless they have been resolved successfully before.
+    pub fn ensure(&self, host: &str, port: u16, retry: &DnsRetry) -> io::Result<()> {
+        let key = (host.to_string(), port);
+        if self.0.lock().unwrap().contains(&key) {
+            return Ok(());
+        }
+
+        resolve((host, port), retry)?;
+        self.0.lock().unwrap().insert(key);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dns::{resolve, DnsRetry, ResolvedHosts};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_resolve() {
+        let addrs = resolve(("127.0.0.1", 80), &DnsRetry::none()).unwrap();
+        assert_eq!(addrs, vec!["127.0.0.1:80".parse().unwrap()]);
+
+        // `.invalid` never resolves (RFC 2606), so it's retried with backoff 10ms + 20ms
+        let retry = DnsRetry {
+            retries: 2,
+            backoff: Duration::from_millis(10),
+        };
+        let start = Instant::now();
+        assert!(resolve("harvest.invalid:80", &retry).is_err());
+        assert!(start.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[test]
+    fn test_resolved_hosts() {
+        let resolved = ResolvedHosts::default();
+        resolved.ensure("127.0.0.1", 80, &DnsRetry::none()).unwrap();
+        assert!(resolved
+            .ensure("harvest.invalid", 80, &DnsRetry::none())
+            .is_err());
+
+        // failures aren't cached, and clones share the resolved hosts
+        let hosts = resolved.clone().0.lock().unwrap().clone();
+        assert_eq!(hosts.len(), 1);
+        assert!(hosts.contains(&("127.0.0.1".to_string(), 80)));
+    }
+}
@@ -0,0 +1,117 @@
+//! Error definition.
+
+use std::{io, time::Duration};
+use thiserror::Error;
+
+/// Possible errors while sending a message
+#[derive(Debug, Error)]
+pub enum SendError {
+    /// The endpoint is invalid; the HTTP endpoint isn't a valid `http` or `https` URL, or the UDP or TCP
+    /// endpoint isn't `host:port`.
+    #[error("Invalid endpoint: {0}")]
+    InvalidEndpoint(String),
+
+    /// The name or value of an additional HTTP header is invalid.
+    #[error("Invalid header: {0}")]
+    InvalidHeader(String),
+
+    /// The host of the endpoint couldn't be resolved, even after retries. Other connect errors are returned
+    /// as [`Http`](Self::Http) or [`Io`](Self::Io).
+    #[error("Failed to resolve {0}")]
+    Resolve(String),
+
+    /// Connecting, sending or receiving the response timed out, see `timeout` of
+    /// [`Sender`](crate::sender::Sender).
+    #[error("Timed out")]
+    Timeout,
+
+    /// An identical message was sent successfully within the dedup window, so the message isn't sent.
+    #[error("An identical message was sent within the dedup window")]
+    Duplicate,
+
+    /// Harvest responded with 4xx status code. The message shouldn't be retried as is.
+    #[error("Harvest responded with client error status {status}")]
+    ClientError {
+        /// HTTP status code of the response.
+        status: u16,
+    },
+
+    /// Harvest responded with 5xx status code. The message may be retried.
+    #[error("Harvest responded with server error status {status}")]
+    ServerError {
+        /// HTTP status code of the response.
+        status: u16,
+    },
+
+    /// Harvest responded with 429 status code. The message may be retried after a while.
+    #[error("Harvest responded with too many requests status")]
+    RateLimited {
+        /// Duration to wait before retrying, from the `retry-after` header if any.
+        retry_after: Option<Duration>,
+    },
+
+    /// Harvest responded with other non-2xx status code.
+    #[error("Harvest responded with unexpected status {status}")]
+    UnexpectedStatus {
+        /// HTTP status code of the response.
+        status: u16,
+    },
+
+    /// HTTP request failed other than by timeout, e.g. the connection is refused. Transparent error from
+    /// [`reqwest`](https://docs.rs/reqwest/latest/reqwest/) crate.
+    #[error(transparent)]
+    Http(reqwest::Error),
+
+    /// I/O of the UDP or TCP socket failed other than by timeout, e.g. the network is unreachable.
+    #[error(transparent)]
+    Io(io::Error),
+
+    /// Transparent error from [`serde_json`](https://docs.rs/serde_json/latest/serde_json/) crate.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+impl SendError {
+    /// Returns the HTTP status code Harvest responded with, regardless of the class of the status, or `None`
+    /// if the message failed before a response.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            SendError::ClientError { status }
+            | SendError::ServerError { status }
+            | SendError::UnexpectedStatus { status } => Some(*status),
+            SendError::RateLimited { .. } => Some(429),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the message couldn't reach the endpoint, e.g. resolving, connecting or I/O failed or
+    /// timed out, so that another protocol may get through. An error of the message or the configuration,
+    /// e.g. [`Duplicate`](Self::Duplicate) or [`InvalidHeader`](Self::InvalidHeader), or a non-2xx
+    /// response isn't.
+    pub fn is_transport_failure(&self) -> bool {
+        matches!(
+            self,
+            SendError::Resolve(_) | SendError::Timeout | SendError::Http(_) | SendError::Io(_)
+        )
+    }
+}
+
+impl From<reqwest::Error> for SendError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            SendError::Timeout
+        } else {
+            SendError::Http(e)
+        }
+    }
+}
+
+impl From<io::Error> for SendError {
+    fn from(e: io::Error) -> Self {
+        // a socket with a write timeout fails with `WouldBlock` on some platforms
+        match e.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => SendError::Timeout,
+            _ => SendError::Io(e),
+        }
+    }
+}
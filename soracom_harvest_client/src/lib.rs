@@ -1,44 +1,306 @@
 //! Deadly simple client library for Soracom Harvest Data. Provides simple functions to send a message with following protocols:
 //! - HTTP
+//! - TCP
 //! - UDP
+//!
+//! The functions share one [`Sender`](sender::Sender) with the default configuration, created on the first
+//! call, so that a device sending frequently reuses its HTTP connections and UDP socket. Use your own
+//! [`Sender`](sender::Sender) for more control over how a message is sent, or
+//! [`UdpSender`](udp_sender::UdpSender) to also resolve the endpoint only once for many messages.
+//!
+//! The functions send to the production endpoints of Harvest. [`send_http_message_to`],
+//! [`send_udp_message_to`] and [`send_tcp_message_to`] send to another endpoint instead, e.g. a mock receiver
+//! for integration testing.
+//!
+//! The functions return [`SendError`](error::SendError) on failure, so that callers can tell e.g. a timeout
+//! from a rejected message and decide whether to retry.
+//!
+//! # Async
+//!
+//! With `async` feature, `send_http_message_async` and `send_udp_message_async` are available for async
+//! runtimes e.g. tokio, returning `impl Future<Output = Result<(), SendError>>`. The futures are `Send`,
+//! so they can be awaited in a spawned task. Without the feature, tokio isn't a dependency and only the
+//! blocking functions are available.
+//!
+//! The async functions always send to the production endpoints, and don't time out by themselves; wrap
+//! them with e.g. `tokio::time::timeout` to bound a send, or use a [`Sender`](sender::Sender) with
+//! `timeout` and custom endpoints from a blocking task.
+//!
+//! ```no_run
+//! # #[cfg(feature = "async")]
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use soracom_harvest_client::{send_http_message_async, send_udp_message_async};
+//!
+//! send_http_message_async(r#"{"temperature":20}"#).await?;
+//! send_udp_message_async("hello").await?;
+//! # Ok(())
+//! # }
+//! ```
 
-use reqwest::{
-    blocking::Client,
-    header::{CONTENT_TYPE, USER_AGENT},
-};
-use std::{error::Error, net::UdpSocket, time::Duration};
+#[cfg(feature = "async")]
+mod async_sender;
+#[cfg(feature = "audit")]
+mod audit;
+pub mod dns;
+pub mod error;
+pub mod sender;
+pub mod udp_sender;
+
+#[cfg(feature = "async")]
+pub use async_sender::{send_http_message_async, send_udp_message_async};
+use error::SendError;
+pub use reqwest::StatusCode;
+use sender::{HttpSendOutcome, RetryPolicy, SendOutcome, Sender};
+use std::{sync::OnceLock, time::Duration};
 
 const SORACOM_HARVEST_HTTP_ENDPOINT: &str = "http://harvest.soracom.io";
 const SORACOM_HARVEST_TCP_UDP_ENDPOINT: &str = "harvest.soracom.io:8514";
+/// `user-agent` of the HTTP requests, shared by the blocking and async senders.
+const SORACOM_HARVEST_USER_AGENT: &str = "soracom_harvest_api_client";
+
+/// Returns the sender with the default configuration shared by the functions below, so that repeated calls
+/// reuse its HTTP connections and UDP socket.
+fn default_sender() -> &'static Sender {
+    static SENDER: OnceLock<Sender> = OnceLock::new();
+    SENDER.get_or_init(Sender::new)
+}
 
 /// Send a message to Soracom Harvest Data via HTTP. Roughly equivalents to:
 ///
 /// ```shell
 /// curl -X POST \
-///      -H "user-agent:soracom_harvest_client" \
+///      -H "user-agent:soracom_harvest_api_client" \
 ///      -H "content-type:application/json" \
 ///      -d "body" \
 ///      http://harvest.soracom.io
 /// ```
-pub fn send_http_message(body: impl Into<String>) -> Result<(), Box<dyn Error>> {
-    Client::new()
-        .post(SORACOM_HARVEST_HTTP_ENDPOINT)
-        .header(USER_AGENT, "soracom_harvest_api_client")
-        .header(CONTENT_TYPE, "application/json")
-        .body(body.into())
-        .send()?;
+///
+/// Use [`send_http`] for another content type or additional headers, or [`send_http_bytes`] for binary
+/// payloads.
+pub fn send_http_message(body: impl Into<String>) -> Result<(), SendError> {
+    default_sender().send_http(body)
+}
+
+/// Same as [`send_http_message`], but sends to `url` instead, which may include a path e.g. for a reverse
+/// proxy in front of Harvest. Returns [`SendError::InvalidEndpoint`](error::SendError::InvalidEndpoint) if
+/// `url` isn't a valid `http` or `https` URL.
+pub fn send_http_message_to(
+    url: impl Into<String>,
+    body: impl Into<String>,
+) -> Result<(), SendError> {
+    default_sender().with_http_endpoint(url).send_http(body)
+}
+
+/// Deprecated alias of [`send_http`] without additional headers.
+#[deprecated(note = "use `send_http` instead")]
+pub fn send_http_message_with_content_type(
+    body: impl Into<String>,
+    content_type: &str,
+) -> Result<(), SendError> {
+    send_http(body.into().as_bytes(), content_type, &[])?;
+
+    Ok(())
+}
+
+/// Same as [`send_http_message`], but returns details of the outcome such as bytes sent, status code,
+/// round-trip time and resolved endpoint.
+pub fn send_http_message_detailed(body: impl Into<String>) -> Result<SendOutcome, SendError> {
+    default_sender().send_http_detailed(body)
+}
+
+/// Same as [`send_http_message`], but returns the status code and the body of the response, or
+/// [`SendError`](error::SendError) for a non-2xx response e.g. 400 for a malformed payload, which
+/// [`send_http_message`] ignores.
+pub fn send_http_message_with_response(
+    body: impl Into<String>,
+) -> Result<HttpSendOutcome, SendError> {
+    default_sender().send_http_with_response(body)
+}
+
+/// Send a pre-serialized body to Soracom Harvest Data via HTTP with the content type and additional headers,
+/// and returns the status code of the response. This is the low-level primitive the other HTTP functions
+/// are built on, see [`Sender::send_http_raw`].
+///
+/// - `body`: Body of the request, sent as is, e.g. CSV or protobuf. Harvest stores the content type with
+///   the data entry, and returns a binary body base64-encoded as `{"payload": "..."}` from the API.
+/// - `content_type`: Value of the `content-type` header e.g. `text/csv` or `application/octet-stream`.
+/// - `headers`: Additional headers e.g. a device serial for a backend to route readings by. They replace
+///   the default `user-agent` and `content-type` if given.
+///
+/// ```no_run
+/// soracom_harvest_client::send_http(b"20,60", "text/csv", &[]).unwrap();
+/// soracom_harvest_client::send_http(
+///     br#"{"temperature":20}"#,
+///     "application/json",
+///     &[("x-device-id", "device-1"), ("x-firmware-version", "1.2.0")],
+/// )
+/// .unwrap();
+/// ```
+pub fn send_http(
+    body: &[u8],
+    content_type: &str,
+    headers: &[(&str, &str)],
+) -> Result<StatusCode, SendError> {
+    let outcome = default_sender().send_http_raw(body, content_type, headers)?;
+
+    StatusCode::from_u16(outcome.status).map_err(|_| SendError::UnexpectedStatus {
+        status: outcome.status,
+    })
+}
+
+/// Deprecated alias of [`send_http`] with `application/json`.
+#[deprecated(note = "use `send_http` instead")]
+pub fn send_http_message_with_headers(
+    body: impl Into<String>,
+    headers: &[(&str, &str)],
+) -> Result<(), SendError> {
+    send_http(body.into().as_bytes(), "application/json", headers)?;
+
+    Ok(())
+}
+
+/// Send binary data e.g. CBOR to Soracom Harvest Data via HTTP as `application/octet-stream`, without going
+/// through UTF-8. Use [`send_http`] for another content type.
+///
+/// ```no_run
+/// soracom_harvest_client::send_http_bytes(vec![0xa1, 0x61, 0x74, 0x14]).unwrap();
+/// ```
+pub fn send_http_bytes(body: impl Into<Vec<u8>>) -> Result<(), SendError> {
+    send_http(&body.into(), "application/octet-stream", &[])?;
+
+    Ok(())
+}
+
+/// Deprecated alias of [`send_http`] without additional headers. Takes the data first, like the other
+/// functions.
+#[deprecated(note = "use `send_http` instead")]
+pub fn send_http_bytes_with_content_type(data: &[u8], content_type: &str) -> Result<(), SendError> {
+    send_http(data, content_type, &[])?;
+
+    Ok(())
+}
+
+/// Send each element of `entries` to Soracom Harvest Data via HTTP as a separate record, reusing one
+/// connection. Note that [`send_http_message`] stores a JSON array as a single record.
+pub fn send_http_array(entries: &[serde_json::Value]) -> Vec<Result<SendOutcome, SendError>> {
+    default_sender().send_http_array(entries)
+}
+
+/// Send a message via HTTP to each of `endpoints` concurrently e.g. the production endpoint and a mirror, and
+/// returns the outcome of each send in the same order. Partial success is possible; see
+/// [`Sender::send_http_fanout`].
+pub fn send_http_fanout(
+    endpoints: &[&str],
+    body: impl Into<String>,
+) -> Vec<Result<SendOutcome, SendError>> {
+    default_sender().send_http_fanout(endpoints, body)
+}
+
+/// Same as [`send_http_message`], but retries on connection errors, timeouts and 5xx responses with
+/// exponential backoff and jitter, as configured by `policy`. A 4xx response isn't retried. Returns the
+/// error of the last attempt if all attempts fail.
+///
+/// ```no_run
+/// use soracom_harvest_client::{send_http_message_with_retry, sender::RetryPolicy};
+/// use std::time::Duration;
+///
+/// let policy = RetryPolicy {
+///     max_attempts: 5,
+///     max_delay: Duration::from_secs(30),
+///     ..Default::default()
+/// };
+/// send_http_message_with_retry(r#"{"temperature":20}"#, policy).unwrap();
+/// ```
+pub fn send_http_message_with_retry(
+    body: impl Into<String>,
+    policy: RetryPolicy,
+) -> Result<(), SendError> {
+    default_sender().send_http_with_retry(body, &policy)?;
 
     Ok(())
 }
 
+/// Same as [`send_http_message`], but compresses the body with gzip if it is at least
+/// [`DEFAULT_GZIP_THRESHOLD`](sender::DEFAULT_GZIP_THRESHOLD) bytes, e.g. for a batch of buffered readings
+/// over a metered link. Use [`Sender`] with `gzip_threshold` for another threshold. Requires `flate2`
+/// feature.
+#[cfg(feature = "flate2")]
+pub fn send_http_message_gzip(body: impl Into<String>) -> Result<(), SendError> {
+    default_sender()
+        .with_gzip_threshold(sender::DEFAULT_GZIP_THRESHOLD)
+        .send_http(body)
+}
+
+/// Same as [`send_http_message`], but fails if the request doesn't complete within `timeout`, including
+/// connect, so that a stalled connection on a flaky link can't hang the thread.
+pub fn send_http_message_with_timeout(
+    body: impl Into<String>,
+    timeout: Duration,
+) -> Result<(), SendError> {
+    default_sender().with_timeout(timeout).send_http(body)
+}
+
 /// Send a message to Soracom Harvest Data via UDP. Equivalents to:
 /// ```shell
 /// echo -n "data" | nc -u -w5 harvest.soracom.io 8514
 /// ```
-pub fn send_udp_message(data: impl Into<String>) -> Result<(), Box<dyn Error>> {
-    let socket = UdpSocket::bind("0.0.0.0:0")?;
-    socket.set_write_timeout(Some(Duration::from_secs(5)))?;
-    socket.send_to(data.into().as_bytes(), SORACOM_HARVEST_TCP_UDP_ENDPOINT)?;
+///
+/// This is a string form of [`send_udp_bytes`]; use it for binary payloads.
+pub fn send_udp_message(data: impl Into<String>) -> Result<(), SendError> {
+    send_udp_bytes(data.into())
+}
 
-    Ok(())
+/// Send binary data e.g. a packed telemetry frame to Soracom Harvest Data via UDP as is, without going
+/// through UTF-8.
+pub fn send_udp_bytes(data: impl AsRef<[u8]>) -> Result<(), SendError> {
+    default_sender().send_udp_bytes(data.as_ref())
+}
+
+/// Same as [`send_udp_message`], but retries when the socket fails to send, as configured by `policy`.
+/// There is no response to UDP, so a lost datagram isn't retried.
+pub fn send_udp_message_with_retry(
+    data: impl Into<String>,
+    policy: RetryPolicy,
+) -> Result<(), SendError> {
+    default_sender().send_udp_with_retry(data.into().as_bytes(), &policy)
+}
+
+/// Same as [`send_udp_message`], but sends to `endpoint` (`host:port`) instead, e.g. a Harvest-compatible
+/// collector for staging. Returns [`SendError::InvalidEndpoint`](error::SendError::InvalidEndpoint) if
+/// `endpoint` isn't `host:port`, or [`SendError::Resolve`](error::SendError::Resolve) if it can't be
+/// resolved.
+pub fn send_udp_message_to(
+    endpoint: impl Into<String>,
+    data: impl Into<String>,
+) -> Result<(), SendError> {
+    default_sender().with_udp_endpoint(endpoint).send_udp(data)
+}
+
+/// Same as [`send_udp_message`], but with the write timeout instead of 5 seconds. Unlike the other functions,
+/// this binds a UDP socket for each call, as the timeout is set on binding; use [`Sender`] with `timeout`
+/// to reuse one.
+pub fn send_udp_message_with_timeout(
+    data: impl Into<String>,
+    timeout: Duration,
+) -> Result<(), SendError> {
+    default_sender().with_timeout(timeout).send_udp(data)
+}
+
+/// Send a message to Soracom Harvest Data via TCP, which confirms that the message is written unlike UDP.
+/// Equivalents to:
+/// ```shell
+/// echo -n "data" | nc -w5 harvest.soracom.io 8514
+/// ```
+pub fn send_tcp_message(data: impl Into<String>) -> Result<(), SendError> {
+    default_sender().send_tcp(data)
+}
+
+/// Same as [`send_tcp_message`], but sends to `endpoint` (`host:port`) instead, e.g. a mock Harvest receiver
+/// at `127.0.0.1:9000` for integration testing. Returns
+/// [`SendError::InvalidEndpoint`](error::SendError::InvalidEndpoint) if `endpoint` isn't `host:port`.
+pub fn send_tcp_message_to(
+    endpoint: impl Into<String>,
+    data: impl Into<String>,
+) -> Result<(), SendError> {
+    default_sender().with_tcp_endpoint(endpoint).send_tcp(data)
 }
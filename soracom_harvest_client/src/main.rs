@@ -2,23 +2,76 @@
 //!
 //! # Usage
 //!
-//! soracom_harvest_client [FLAGS] [message]
+//! soracom_harvest_client [FLAGS] [OPTIONS] [message]
 //!
 //! # Flags
 //!
 //! -h, --help       Prints help information
-//!     --http       Use HTTP to send your message
-//!     --udp        use UDP to send your message
-//! -V, --version    Prints version information
+//!     --http            Use HTTP to send your message
+//!     --stdin           Read the message from the standard input
+//!     --sysinfo-full    Send structured system information instead of CPUs temperature, if no message is given
+//!     --tcp             Use TCP to send your message
+//!     --udp             use UDP to send your message
+//! -V, --version         Prints version information
+//!
+//! # Options
+//!
+//!     --count <count>          Number of messages to send. 0 to send forever [default: 1] [alias: repeat]
+//!     --interval <interval>    Interval between sends in seconds [default: 0]
+//!     --jitter <jitter>        Delay each send by a random duration up to given seconds
 //!
 //! # Argument
 //!
-//! <message>    Message to sent. If none, sent CPUs temperature instead.
+//! <message>    Message to sent. If none, sent CPUs temperature instead. `-` to read it from the standard input.
+//!
+//! The message is read from the standard input with `--stdin` or `-` as the message, e.g.
+//! `cat reading.json | soracom_harvest_client --http -`, for payloads with shell-unfriendly characters or
+//! large JSON. `--stdin` can't be used with a message argument. The whole input is read once, and sent as is
+//! on each send; empty input is an error.
+//!
+//! # Periodic publishing
+//!
+//! With `--count` (or `--repeat`) and `--interval`, the client works as a lightweight daemon, e.g.
+//! `--repeat 0 --interval 60` sends every minute until interrupted. The default message is collected for
+//! each send, so that the values are fresh. On Unix, SIGINT (Ctrl-C) stops the client after the send in
+//! progress, if any; press it again to terminate immediately.
+//!
+//! The default message is a JSON object of the temperature in degrees Celsius of each sensor, keyed by its
+//! label, e.g. `{"CPU": 48.5}`. If the device has no temperature sensors, usage of each core in percent is
+//! sent instead, keyed by its name, e.g. `{"cpu0": 12.5}`, with a note to the standard error.
+//!
+//! # System information
+//!
+//! With `--sysinfo-full`, the default message is a JSON object of following schema, stable across devices
+//! so that the data entries can be queried in a consistent shape:
+//!
+//! ```json
+//! {
+//!   "hostname": "raspberrypi",
+//!   "timestamp": 1669024325202,
+//!   "cpus": [{ "name": "cpu0", "usage": 12.5 }],
+//!   "total_memory": 4124237824
+//! }
+//! ```
+//!
+//! - `hostname`: Host name of the device, or `null` if unavailable.
+//! - `timestamp`: Time of the measurement (unix time in milliseconds).
+//! - `cpus`: Usage of each core in percent.
+//! - `total_memory`: Total memory in bytes.
 
-use soracom_harvest_client::{send_http_message, send_udp_message};
-use std::{collections::HashMap, error::Error};
+use rand::Rng;
+use serde::Serialize;
+use soracom_harvest_client::{send_http_message, send_tcp_message, send_udp_message};
+use std::{
+    collections::HashMap,
+    error::Error,
+    io::{self, Read},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
 use structopt::StructOpt;
-use sysinfo::{CpuExt, System, SystemExt};
+use sysinfo::{ComponentExt, CpuExt, System, SystemExt};
 
 #[derive(Debug, StructOpt)]
 #[structopt(
@@ -30,34 +83,313 @@ struct Opt {
     /// Use HTTP to send your message.
     http: bool,
 
+    #[structopt(long, group = "protocol")]
+    /// Use TCP to send your message.
+    tcp: bool,
+
     #[structopt(long, group = "protocol")]
     /// use UDP to send your message.
     udp: bool,
 
-    /// Message to sent. If none, sent CPUs temperature instead.
+    /// Number of messages to send. 0 to send forever.
+    #[structopt(long, visible_alias = "repeat", default_value = "1")]
+    count: u64,
+
+    /// Interval between sends in seconds.
+    #[structopt(long, default_value = "0")]
+    interval: u64,
+
+    /// Delay each send, including the first one, by a random duration up to given seconds. Use this to
+    /// de-synchronize many devices started at the same time, e.g. by cron.
+    #[structopt(long)]
+    jitter: Option<u64>,
+
+    /// Send structured system information, with host name, timestamp, usage of each core and total memory,
+    /// instead of CPUs temperature, if no message is given.
+    #[structopt(long)]
+    sysinfo_full: bool,
+
+    /// Read the message from the standard input, same as `-` as the message.
+    #[structopt(long, conflicts_with = "message")]
+    stdin: bool,
+
+    /// Message to sent. If none, sent CPUs temperature instead. `-` to read it from the standard input.
     #[structopt()]
     message: Option<String>,
 }
 
+/// Set by SIGINT, to stop sending after the send in progress.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
 fn main() -> Result<(), Box<dyn Error>> {
-    let opt: Opt = Opt::from_args();
-    let message = match opt.message {
-        None => {
-            let mut data = HashMap::new();
-            for cpu in System::new_all().cpus() {
-                data.insert(cpu.name().to_string(), cpu.cpu_usage());
-            }
-            serde_json::to_string(&data)?
+    let mut opt: Opt = Opt::from_args();
+    if opt.stdin || opt.message.as_deref() == Some("-") {
+        opt.message = Some(read_message(io::stdin().lock())?);
+    }
+    handle_sigint();
+
+    let sent = run(&opt, |message| {
+        if opt.http {
+            send_http_message(message)?;
+        } else if opt.tcp {
+            send_tcp_message(message)?;
+        } else if opt.udp {
+            send_udp_message(message)?;
         }
-        Some(s) => s,
-    };
+        Ok(())
+    })?;
 
-    if opt.http {
-        send_http_message(&message)?;
-    } else if opt.udp {
-        send_udp_message(&message)?;
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        eprintln!("Interrupted after {sent} sends");
     }
 
-    println!("{} {}", chrono::Local::now().to_rfc3339(), message);
     Ok(())
 }
+
+/// Sends the message with `send` as many times as `--count`, or until interrupted, and returns the number of
+/// sends.
+fn run(
+    opt: &Opt,
+    mut send: impl FnMut(&str) -> Result<(), Box<dyn Error>>,
+) -> Result<u64, Box<dyn Error>> {
+    let mut rng = rand::thread_rng();
+    // refreshed for each send, as CPU usage is computed since the previous refresh
+    let mut system = System::new_all();
+
+    let mut sent = 0;
+    loop {
+        if let Some(jitter) = opt.jitter {
+            sleep_unless_interrupted(jitter_delay(&mut rng, jitter));
+        }
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            break;
+        }
+
+        let message = match &opt.message {
+            None => {
+                system.refresh_cpu();
+                system.refresh_components();
+                if opt.sysinfo_full {
+                    serde_json::to_string(&SysinfoPayload::collect(&system))?
+                } else {
+                    default_message(&system)?
+                }
+            }
+            Some(s) => s.clone(),
+        };
+
+        send(&message)?;
+
+        println!("{} {}", chrono::Local::now().to_rfc3339(), message);
+
+        sent += 1;
+        if opt.count != 0 && sent >= opt.count {
+            break;
+        }
+        sleep_unless_interrupted(Duration::from_secs(opt.interval));
+    }
+
+    Ok(sent)
+}
+
+/// Returns the whole input as the message, or an error if it is empty.
+fn read_message(mut reader: impl Read) -> Result<String, Box<dyn Error>> {
+    let mut message = String::new();
+    reader.read_to_string(&mut message)?;
+    if message.trim().is_empty() {
+        return Err("No message is given from the standard input".into());
+    }
+
+    Ok(message)
+}
+
+/// Sleeps for the duration, waking up early if interrupted.
+fn sleep_unless_interrupted(duration: Duration) {
+    let deadline = Instant::now() + duration;
+    while !INTERRUPTED.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        thread::sleep((deadline - now).min(Duration::from_millis(100)));
+    }
+}
+
+/// Makes the first SIGINT set [`INTERRUPTED`] instead of terminating the process, and the next one terminate.
+#[cfg(unix)]
+fn handle_sigint() {
+    extern "C" fn on_sigint(_: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        // SAFETY: `signal` is async-signal-safe
+        unsafe { libc::signal(libc::SIGINT, libc::SIG_DFL) };
+    }
+
+    // SAFETY: the handler only touches an atomic and calls an async-signal-safe function
+    unsafe { libc::signal(libc::SIGINT, on_sigint as *const () as libc::sighandler_t) };
+}
+
+#[cfg(not(unix))]
+fn handle_sigint() {}
+
+fn default_message(system: &System) -> Result<String, Box<dyn Error>> {
+    let (data, is_temperature) = default_data(system);
+    if !is_temperature {
+        eprintln!("No temperature sensors are available, sending CPUs usage instead");
+    }
+    Ok(serde_json::to_string(&data)?)
+}
+
+/// Returns the temperature of each sensor, or usage of each core if there are no sensors, and whether the
+/// data is the temperature.
+fn default_data(system: &System) -> (HashMap<String, f32>, bool) {
+    let temperatures = system
+        .components()
+        .iter()
+        .filter(|component| component.temperature().is_finite())
+        .map(|component| (component.label().to_string(), component.temperature()))
+        .collect::<HashMap<_, _>>();
+    if !temperatures.is_empty() {
+        return (temperatures, true);
+    }
+
+    let usages = system
+        .cpus()
+        .iter()
+        .map(|cpu| (cpu.name().to_string(), cpu.cpu_usage()))
+        .collect();
+    (usages, false)
+}
+
+/// Structured system information sent with `--sysinfo-full`. See the module documentation for the schema.
+#[derive(Debug, Serialize)]
+struct SysinfoPayload {
+    hostname: Option<String>,
+    timestamp: i64,
+    cpus: Vec<CpuUsage>,
+    total_memory: u64,
+}
+
+/// Usage of a core in percent.
+#[derive(Debug, Serialize)]
+struct CpuUsage {
+    name: String,
+    usage: f32,
+}
+
+impl SysinfoPayload {
+    /// Returns the system information of this device from `system`.
+    fn collect(system: &System) -> Self {
+        SysinfoPayload {
+            hostname: system.host_name(),
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            cpus: system
+                .cpus()
+                .iter()
+                .map(|cpu| CpuUsage {
+                    name: cpu.name().to_string(),
+                    usage: cpu.cpu_usage(),
+                })
+                .collect(),
+            total_memory: system.total_memory(),
+        }
+    }
+}
+
+/// Returns a random delay between zero and `jitter` seconds, in millisecond resolution. A `jitter` too large
+/// to represent in milliseconds is capped.
+fn jitter_delay(rng: &mut impl Rng, jitter: u64) -> Duration {
+    Duration::from_millis(rng.gen_range(0..=jitter.saturating_mul(1000)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{default_data, jitter_delay, read_message, run, CpuUsage, Opt, SysinfoPayload};
+    use std::time::Duration;
+    use structopt::StructOpt;
+    use sysinfo::{ComponentExt, CpuExt, System, SystemExt};
+
+    #[test]
+    fn test_run_repeat() {
+        let opt = Opt::from_iter([
+            "soracom_harvest_client",
+            "--repeat",
+            "3",
+            "--interval",
+            "0",
+            "hey",
+        ]);
+
+        let mut messages = Vec::new();
+        let sent = run(&opt, |message| {
+            messages.push(message.to_string());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(sent, 3);
+        assert_eq!(messages, vec!["hey"; 3]);
+    }
+
+    #[test]
+    fn test_read_message() {
+        let message = "{\"temperature\": 21, \"note\": \"it's $HOME\"}\n";
+        assert_eq!(read_message(message.as_bytes()).unwrap(), message);
+        assert!(read_message(&b""[..]).is_err());
+        assert!(read_message(&b" \n"[..]).is_err());
+
+        assert!(Opt::from_iter_safe(["soracom_harvest_client", "--stdin", "hey"]).is_err());
+    }
+
+    #[test]
+    fn test_default_data() {
+        let system = System::new_all();
+        let (data, is_temperature) = default_data(&system);
+
+        let expected = if is_temperature {
+            system
+                .components()
+                .iter()
+                .map(|component| component.label().to_string())
+                .collect::<Vec<_>>()
+        } else {
+            system
+                .cpus()
+                .iter()
+                .map(|cpu| cpu.name().to_string())
+                .collect()
+        };
+        assert!(!data.is_empty());
+        assert!(data.keys().all(|key| expected.contains(key)));
+    }
+
+    #[test]
+    fn test_sysinfo_payload() {
+        let payload = SysinfoPayload {
+            hostname: Some("raspberrypi".to_string()),
+            timestamp: 1669024325202,
+            cpus: vec![CpuUsage {
+                name: "cpu0".to_string(),
+                usage: 12.5,
+            }],
+            total_memory: 4124237824,
+        };
+        assert_eq!(
+            serde_json::to_string(&payload).unwrap(),
+            r#"{"hostname":"raspberrypi","timestamp":1669024325202,"cpus":[{"name":"cpu0","usage":12.5}],"total_memory":4124237824}"#
+        );
+
+        let payload = SysinfoPayload::collect(&System::new_all());
+        assert!(payload.timestamp > 0);
+        assert!(!payload.cpus.is_empty());
+    }
+
+    #[test]
+    fn test_jitter_delay() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..100 {
+            assert!(jitter_delay(&mut rng, 3) <= Duration::from_secs(3));
+        }
+        assert_eq!(jitter_delay(&mut rng, 0), Duration::ZERO);
+        assert!(jitter_delay(&mut rng, u64::MAX) <= Duration::from_millis(u64::MAX));
+    }
+}
@@ -0,0 +1,1738 @@
+//! Configurable sender for Soracom Harvest Data.
+
+use crate::{
+    dns::{self, DnsRetry, ResolvedHosts},
+    error::SendError,
+    SORACOM_HARVEST_HTTP_ENDPOINT, SORACOM_HARVEST_TCP_UDP_ENDPOINT, SORACOM_HARVEST_USER_AGENT,
+};
+use reqwest::{
+    blocking::{Client, Response},
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, RETRY_AFTER, USER_AGENT},
+};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    io::{self, Write},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket},
+    panic,
+    path::PathBuf,
+    sync::{Arc, Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+use typed_builder::TypedBuilder;
+
+/// Size of HTTP bodies in bytes, below which gzip compression isn't worth its overhead.
+pub const DEFAULT_GZIP_THRESHOLD: usize = 1024;
+
+/// Timeout of connecting and writing to UDP and TCP sockets, unless `timeout` is given.
+const DEFAULT_SOCKET_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Details of the outcome of a send.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendOutcome {
+    /// Number of bytes of the payload sent.
+    pub bytes: usize,
+    /// HTTP status code of the response.
+    pub status: u16,
+    /// Time elapsed until the response is received.
+    pub elapsed: Duration,
+    /// Endpoint the message was sent to.
+    pub endpoint: String,
+    /// Resolved address of the endpoint, if available.
+    pub remote_addr: Option<SocketAddr>,
+}
+
+/// Status code and body of the response to an HTTP send.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpSendOutcome {
+    /// HTTP status code of the response.
+    pub status: u16,
+    /// Body of the response.
+    pub body: String,
+}
+
+/// Policy to retry a failed send, see [`Sender::send_http_with_retry`] and [`Sender::send_udp_with_retry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of attempts including the first one. `0` or `1` disables retry.
+    pub max_attempts: u32,
+
+    /// Wait before the first retry, doubled on each retry.
+    pub base_delay: Duration,
+
+    /// Upper bound of the wait before a retry.
+    pub max_delay: Duration,
+
+    /// Whether to wait a random duration between the half and the whole of the delay, so that devices
+    /// failing at the same time e.g. on a cell outage don't retry in lockstep.
+    pub jitter: bool,
+
+    /// Whether to retry on 5xx responses, in addition to connection errors and timeouts. Disable this not to
+    /// hammer the endpoint during an outage.
+    pub retry_server_errors: bool,
+}
+
+impl Default for RetryPolicy {
+    /// Attempts 4 times on connection errors, timeouts and 5xx responses, waiting up to 0.5, 1 and 2 seconds
+    /// with jitter, but never more than 10 seconds.
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            jitter: true,
+            retry_server_errors: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the wait before the `retry`-th retry, starting from 0.
+    fn delay(&self, retry: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        if self.jitter {
+            delay.mul_f64(rand::random::<f64>() / 2.0 + 0.5)
+        } else {
+            delay
+        }
+    }
+
+    /// Calls `send` until it succeeds, `is_retryable` returns `false` for the error, or the attempts run out,
+    /// and returns the last result.
+    fn retry<T>(
+        &self,
+        is_retryable: impl Fn(&SendError) -> bool,
+        send: impl Fn() -> Result<T, SendError>,
+    ) -> Result<T, SendError> {
+        let mut retry = 0;
+        loop {
+            match send() {
+                Ok(t) => return Ok(t),
+                Err(e) if retry + 1 >= self.max_attempts || !is_retryable(&e) => return Err(e),
+                Err(e) => {
+                    let delay = self.delay(retry);
+                    log::debug!("failed to send, retrying in {delay:?}: {e}");
+                    thread::sleep(delay);
+                    retry += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Protocol to send a message with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// HTTP, to `http_endpoint`.
+    Http,
+    /// UDP, to `udp_endpoint`.
+    Udp,
+    /// TCP, to `tcp_endpoint`.
+    Tcp,
+}
+
+/// Returns a new idempotency key, a random UUID (version 4) e.g. `0b6a8d3e-5f1c-4c1e-9a0e-6e2f7c1d9b42`.
+pub fn new_idempotency_key() -> String {
+    let uuid = rand::random::<u128>() & !(0xf << 76) & !(0x3 << 62) | (0x4 << 76) | (0x2 << 62);
+    let hex = format!("{uuid:032x}");
+
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Sender for Soracom Harvest Data.
+///
+/// Use `.builder()` to construct a new, with following optional methods.
+///
+/// - `http_endpoint`: URL to send HTTP requests to, optionally with path e.g. `https://proxy.example.com/ingest`.
+///   Defaults to `http://harvest.soracom.io`, where Harvest accepts messages at the root.
+/// - `udp_endpoint`: `host:port` to send UDP datagrams to. Defaults to `harvest.soracom.io:8514`.
+/// - `tcp_endpoint`: `host:port` to send TCP messages to. Defaults to `harvest.soracom.io:8514`.
+/// - `udp_warmup`: Send an empty "warmup" datagram, then wait for given duration before sending the payload.
+///   Some cellular modems drop the first datagram after an idle period while the radio wakes up, so this
+///   improves delivery for devices which send infrequently. Note that Harvest may record the warmup datagram
+///   as an empty entry.
+/// - `audit_db`: Path to a local SQLite database to append a record of each send to, in `sent_messages` table
+///   with timestamp, protocol, body length and outcome. Requires `audit` feature, otherwise ignored. Failing
+///   to record doesn't fail the send.
+/// - `error_for_status`: Treat non-2xx HTTP responses as [`SendError`], by status class; `ClientError` for
+///   4xx, `ServerError` for 5xx, and `RateLimited` for 429. Defaults to `false`, where any response is
+///   returned as [`SendOutcome`].
+/// - `dedup_window`: Skip a message identical to one sent successfully via the same protocol to the same
+///   endpoint within given duration, returning [`SendError::Duplicate`], to avoid duplicate records from overly aggressive
+///   retries. Harvest doesn't dedup messages on the server side, e.g. with an idempotency key header, so
+///   this is done by tracking hashes of recently sent messages. Clones of the sender share the history.
+/// - `idempotency_header`: Name of the header to tag each HTTP send with a random UUID as an idempotency key
+///   e.g. `Idempotency-Key`, unless the key is given with
+///   [`send_http_with_idempotency_key`](Self::send_http_with_idempotency_key). Retry a message with the same
+///   key so that a cooperating backend e.g. a proxy in front of Harvest won't store it twice. Note that
+///   Harvest itself doesn't honor the header, and stores every message it receives.
+/// - `timeout`: Timeout of each send. For HTTP, it is the total timeout of the request including connect, so
+///   that a stalled connection on a flaky link can't hang the thread; no timeout by default. For UDP and TCP,
+///   it is the timeout of connecting and writing; 5 seconds by default.
+/// - `headers`: Additional headers as name-value pairs sent with each HTTP request, e.g. a device serial and
+///   a firmware version for a backend to route readings by. They replace `user-agent` or `content-type` if
+///   given, and are replaced by the headers given to [`send_http_raw`](Self::send_http_raw) in turn.
+/// - `gzip_threshold`: Compress HTTP bodies of at least given bytes with gzip, with `content-encoding: gzip`
+///   header, to save data on a metered link, e.g. for a batch of buffered readings. Smaller bodies are sent
+///   as is, where the overhead of gzip isn't worth it; [`DEFAULT_GZIP_THRESHOLD`] is a reasonable choice.
+///   Not compressed by default. Requires `flate2` feature, otherwise ignored.
+/// - `dns_retry`: Retry resolving the host of the endpoint with backoff before connecting, as DNS may not be
+///   ready for a few seconds after the modem connects. Returns [`SendError::Resolve`] if it still fails.
+///   Once the host of the HTTP endpoint is resolved, HTTP sends skip it, as reqwest resolves on its own.
+///   Defaults to [`DnsRetry::default`]; use [`DnsRetry::none`] to fail immediately.
+///
+/// Build a sender once and reuse it for the sends, rather than building one per message. A sender keeps
+/// HTTP connections alive in its connection pool, and binds a UDP socket on the first UDP send to each
+/// address family, IPv4 or IPv6, of the resolved endpoint, so that
+/// e.g. a reading every second costs a `sendto` per UDP send instead of `socket`, `bind`, `sendto` and
+/// `close`, and an HTTP request on an open connection instead of a TCP handshake per send. Clones of the
+/// sender share them.
+///
+/// # Example
+///
+/// ```no_run
+/// use soracom_harvest_client::sender::Sender;
+/// use std::time::Duration;
+///
+/// let sender = Sender::builder()
+///     .udp_warmup(Duration::from_millis(500))
+///     .build();
+///
+/// sender.send_udp("hello").unwrap();
+/// ```
+#[derive(TypedBuilder, Clone)]
+pub struct Sender {
+    #[builder(setter(into), default = SORACOM_HARVEST_HTTP_ENDPOINT.to_string())]
+    http_endpoint: String,
+    #[builder(setter(into), default = SORACOM_HARVEST_TCP_UDP_ENDPOINT.to_string())]
+    udp_endpoint: String,
+    #[builder(setter(into), default = SORACOM_HARVEST_TCP_UDP_ENDPOINT.to_string())]
+    tcp_endpoint: String,
+    #[builder(default, setter(strip_option))]
+    udp_warmup: Option<Duration>,
+    #[builder(default, setter(strip_option, into))]
+    audit_db: Option<PathBuf>,
+    #[builder(default)]
+    error_for_status: bool,
+    #[builder(default, setter(strip_option))]
+    dedup_window: Option<Duration>,
+    #[builder(default, setter(skip))]
+    sent: Arc<Mutex<HashMap<u64, Instant>>>,
+    #[builder(default, setter(strip_option, into))]
+    idempotency_header: Option<String>,
+    #[builder(default, setter(strip_option))]
+    timeout: Option<Duration>,
+    #[builder(default, setter(into))]
+    headers: Vec<(String, String)>,
+    #[builder(default, setter(strip_option))]
+    gzip_threshold: Option<usize>,
+    #[builder(default)]
+    dns_retry: DnsRetry,
+    #[builder(default)]
+    client: Client,
+    #[builder(default, setter(skip))]
+    udp_sockets: Arc<[OnceLock<UdpSocket>; 2]>,
+    #[builder(default, setter(skip))]
+    resolved: ResolvedHosts,
+}
+
+impl Default for Sender {
+    fn default() -> Self {
+        Sender::new()
+    }
+}
+
+impl Sender {
+    /// Returns a new sender with the default configuration, same as `Sender::builder().build()`.
+    pub fn new() -> Self {
+        Sender::builder().build()
+    }
+
+    /// Returns a clone sending via HTTP to `url` instead, sharing the HTTP connections.
+    pub(crate) fn with_http_endpoint(&self, url: impl Into<String>) -> Self {
+        Sender {
+            http_endpoint: url.into(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a clone sending via UDP to `endpoint` instead, sharing the UDP sockets, which aren't
+    /// connected to an endpoint.
+    pub(crate) fn with_udp_endpoint(&self, endpoint: impl Into<String>) -> Self {
+        Sender {
+            udp_endpoint: endpoint.into(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a clone sending via TCP to `endpoint` instead. TCP connections aren't reused anyway.
+    pub(crate) fn with_tcp_endpoint(&self, endpoint: impl Into<String>) -> Self {
+        Sender {
+            tcp_endpoint: endpoint.into(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a clone with the timeout, sharing the HTTP connections, as the timeout is set on each request.
+    /// The UDP sockets aren't shared, as the timeout is set on binding.
+    pub(crate) fn with_timeout(&self, timeout: Duration) -> Self {
+        Sender {
+            timeout: Some(timeout),
+            udp_sockets: Arc::default(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a clone compressing HTTP bodies of at least `threshold` bytes, sharing the HTTP connections.
+    #[cfg(feature = "flate2")]
+    pub(crate) fn with_gzip_threshold(&self, threshold: usize) -> Self {
+        Sender {
+            gzip_threshold: Some(threshold),
+            ..self.clone()
+        }
+    }
+
+    /// Send a message to Soracom Harvest Data via HTTP. The whole body is stored as a single record, even if
+    /// it is a JSON array; use [`send_http_array`](Self::send_http_array) to store each element separately.
+    pub fn send_http(&self, body: impl Into<String>) -> Result<(), SendError> {
+        self.send_http_detailed(body)?;
+
+        Ok(())
+    }
+
+    /// Send a message to Soracom Harvest Data via HTTP, and returns details of the outcome.
+    pub fn send_http_detailed(&self, body: impl Into<String>) -> Result<SendOutcome, SendError> {
+        self.send_http_raw(body.into().as_bytes(), "application/json", &[])
+    }
+
+    /// Same as [`send_http_detailed`](Self::send_http_detailed), but with the content type e.g. `text/csv`
+    /// instead of `application/json`. Harvest stores the content type with the data entry, so consumers
+    /// of the API can tell CSV from JSON.
+    pub fn send_http_with_content_type(
+        &self,
+        body: impl Into<String>,
+        content_type: &str,
+    ) -> Result<SendOutcome, SendError> {
+        self.send_http_raw(body.into().as_bytes(), content_type, &[])
+    }
+
+    /// Send a pre-serialized body to Soracom Harvest Data via HTTP with the content type and additional
+    /// headers, and returns details of the outcome. The other HTTP methods delegate to this.
+    ///
+    /// - `body`: Body of the request, sent as is.
+    /// - `content_type`: Value of the `content-type` header e.g. `text/csv` or `application/octet-stream`.
+    /// - `headers`: Additional headers as name-value pairs, which replace the ones with the same name, i.e.
+    ///   `user-agent`, `content-type` or `headers` of the sender. Returns [`SendError::InvalidHeader`] if
+    ///   a name or value is invalid.
+    pub fn send_http_raw(
+        &self,
+        body: &[u8],
+        content_type: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<SendOutcome, SendError> {
+        self.post_with_audit(body, content_type, headers, self.error_for_status)
+            .map(|(outcome, _)| outcome)
+    }
+
+    /// Send a message to Soracom Harvest Data via HTTP, and returns the status code and the body of the
+    /// response. Unlike the other HTTP methods, a non-2xx response is always returned as [`SendError`]
+    /// regardless of `error_for_status`, so that e.g. a malformed payload rejected with 400 isn't mistaken
+    /// for a success.
+    pub fn send_http_with_response(
+        &self,
+        body: impl Into<String>,
+    ) -> Result<HttpSendOutcome, SendError> {
+        let (outcome, response) =
+            self.post_with_audit(body.into().as_bytes(), "application/json", &[], true)?;
+
+        Ok(HttpSendOutcome {
+            status: outcome.status,
+            body: response.text()?,
+        })
+    }
+
+    fn post_with_audit(
+        &self,
+        body: &[u8],
+        content_type: &str,
+        headers: &[(&str, &str)],
+        error_for_status: bool,
+    ) -> Result<(SendOutcome, Response), SendError> {
+        let mut headers = headers.to_vec();
+        let key;
+        if let Some(name) = &self.idempotency_header {
+            if !headers.iter().any(|(n, _)| n.eq_ignore_ascii_case(name)) {
+                key = new_idempotency_key();
+                headers.push((name, &key));
+            }
+        }
+
+        let result = self.dedup("http", body, || {
+            self.post(body, content_type, &headers, error_for_status)
+        });
+        self.audit(
+            "http",
+            body.len(),
+            result
+                .as_ref()
+                .map(|(o, _)| o.status.to_string())
+                .map_err(|e| e.to_string()),
+        );
+        result
+    }
+
+    /// Same as [`send_http_detailed`](Self::send_http_detailed), but tags the message with the idempotency
+    /// key, in `idempotency_header` or `Idempotency-Key` header if not set. Use the same key, e.g. from
+    /// [`new_idempotency_key`], to retry the message.
+    pub fn send_http_with_idempotency_key(
+        &self,
+        body: impl Into<String>,
+        key: &str,
+    ) -> Result<SendOutcome, SendError> {
+        let name = self
+            .idempotency_header
+            .as_deref()
+            .unwrap_or("Idempotency-Key");
+
+        self.send_http_raw(body.into().as_bytes(), "application/json", &[(name, key)])
+    }
+
+    /// Same as [`send_http_detailed`](Self::send_http_detailed), but retries with exponential backoff and
+    /// jitter on transient failures, i.e. connection errors, timeouts, and 5xx responses unless disabled by
+    /// `policy`.
+    /// A 4xx response is never retried, as the message would be rejected again. Returns the error of the last
+    /// attempt if all attempts fail.
+    ///
+    /// A non-2xx response is always an error regardless of `error_for_status`. With `idempotency_header`,
+    /// all attempts share one key.
+    pub fn send_http_with_retry(
+        &self,
+        body: impl Into<String>,
+        policy: &RetryPolicy,
+    ) -> Result<SendOutcome, SendError> {
+        let body = body.into();
+        let key = new_idempotency_key();
+        let headers = match &self.idempotency_header {
+            Some(name) => vec![(name.as_str(), key.as_str())],
+            None => vec![],
+        };
+
+        policy.retry(
+            |e| is_transient(e, policy),
+            || {
+                self.post_with_audit(body.as_bytes(), "application/json", &headers, true)
+                    .map(|(outcome, _)| outcome)
+            },
+        )
+    }
+
+    /// Send each element of `entries` to Soracom Harvest Data via HTTP as a separate record, and returns the
+    /// outcome of each send in the same order. A failure doesn't stop sending the rest.
+    pub fn send_http_array(
+        &self,
+        entries: &[serde_json::Value],
+    ) -> Vec<Result<SendOutcome, SendError>> {
+        entries
+            .iter()
+            .map(|entry| self.send_http_detailed(entry.to_string()))
+            .collect()
+    }
+
+    /// Send a message via HTTP to each of `endpoints` concurrently, instead of `http_endpoint`, e.g. to
+    /// deliver a reading to both the production endpoint and a mirror for redundancy, and returns the
+    /// outcome of each send in the same order. The sends share the connection pool of this sender.
+    ///
+    /// This only provides at-least-one delivery if any of the results is `Ok`; the other sends may fail, and
+    /// a failure isn't retried, nor rolls back the other sends.
+    pub fn send_http_fanout(
+        &self,
+        endpoints: &[&str],
+        body: impl Into<String>,
+    ) -> Vec<Result<SendOutcome, SendError>> {
+        let body = body.into();
+
+        thread::scope(|scope| {
+            let handles = endpoints
+                .iter()
+                .map(|endpoint| {
+                    let sender = Sender {
+                        http_endpoint: endpoint.to_string(),
+                        ..self.clone()
+                    };
+                    let body = body.as_str();
+                    scope.spawn(move || sender.send_http_detailed(body))
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+                .collect()
+        })
+    }
+
+    /// Send a message to Soracom Harvest Data via UDP.
+    pub fn send_udp(&self, data: impl Into<String>) -> Result<(), SendError> {
+        self.send_udp_bytes(data.into().as_bytes())
+    }
+
+    /// Shorthand for [`send_http`](Self::send_http).
+    pub fn http(&self, body: impl Into<String>) -> Result<(), SendError> {
+        self.send_http(body)
+    }
+
+    /// Shorthand for [`send_udp`](Self::send_udp).
+    pub fn udp(&self, data: impl Into<String>) -> Result<(), SendError> {
+        self.send_udp(data)
+    }
+
+    /// Send binary data e.g. a packed telemetry frame to Soracom Harvest Data via UDP, as is.
+    pub fn send_udp_bytes(&self, data: &[u8]) -> Result<(), SendError> {
+        let result = self.dedup("udp", data, || self.send_to(data));
+        self.audit(
+            "udp",
+            data.len(),
+            result
+                .as_ref()
+                .map(|_| "".to_string())
+                .map_err(|e| e.to_string()),
+        );
+        result
+    }
+
+    /// Same as [`send_udp_bytes`](Self::send_udp_bytes), but retries with the backoff of `policy` when the
+    /// socket fails to send e.g. the network is unreachable while the modem reconnects. Note that a successful
+    /// return doesn't mean the datagram arrived. An invalid or unresolvable endpoint isn't retried.
+    pub fn send_udp_with_retry(&self, data: &[u8], policy: &RetryPolicy) -> Result<(), SendError> {
+        policy.retry(
+            |e| matches!(e, SendError::Io(_) | SendError::Timeout),
+            || self.send_udp_bytes(data),
+        )
+    }
+
+    /// Send a message to Soracom Harvest Data via TCP. Unlike UDP, a successful return means the whole
+    /// message is written to the connection. Connecting and writing time out after `timeout`, 5 seconds each
+    /// by default, so that a dead network fails fast.
+    pub fn send_tcp(&self, data: impl Into<String>) -> Result<(), SendError> {
+        let data = data.into();
+
+        let result = self.dedup("tcp", data.as_bytes(), || self.write_to(data.as_bytes()));
+        self.audit(
+            "tcp",
+            data.len(),
+            result
+                .as_ref()
+                .map(|_| "".to_string())
+                .map_err(|e| e.to_string()),
+        );
+        result
+    }
+
+    /// Send a message via `primary` protocol, and on failure, via `fallback` protocol instead, for networks
+    /// where either HTTP, TCP or UDP is blocked. Returns the protocol which succeeded, or the error of the
+    /// fallback if both failed. Only a transport failure falls back, see
+    /// [`SendError::is_transport_failure`]; other errors of `primary` e.g. [`SendError::Duplicate`] are
+    /// returned as is, so that a message suppressed by `dedup_window` isn't sent via `fallback` instead.
+    ///
+    /// Note that UDP is fire-and-forget, so the success of UDP only means the datagram is sent, not
+    /// delivered; a failed HTTP send followed by a lost datagram still returns [`Protocol::Udp`]. A non-2xx
+    /// HTTP response is a success unless `error_for_status` is set.
+    pub fn send_with_fallback(
+        &self,
+        data: impl Into<String>,
+        primary: Protocol,
+        fallback: Protocol,
+    ) -> Result<Protocol, SendError> {
+        let data = data.into();
+        let send = |protocol| match protocol {
+            Protocol::Http => self.send_http(data.as_str()),
+            Protocol::Udp => self.send_udp(data.as_str()),
+            Protocol::Tcp => self.send_tcp(data.as_str()),
+        };
+
+        match send(primary) {
+            Ok(()) => Ok(primary),
+            Err(e) if !e.is_transport_failure() => Err(e),
+            Err(e) => {
+                log::warn!("Failed to send via {primary:?}, falling back to {fallback:?}: {e}");
+                send(fallback).map(|_| fallback)
+            }
+        }
+    }
+
+    fn post(
+        &self,
+        body: &[u8],
+        content_type: &str,
+        headers: &[(&str, &str)],
+        error_for_status: bool,
+    ) -> Result<(SendOutcome, Response), SendError> {
+        let bytes = body.len();
+
+        let url = reqwest::Url::parse(&self.http_endpoint)
+            .map_err(|e| SendError::InvalidEndpoint(format!("{}: {e}", self.http_endpoint)))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(SendError::InvalidEndpoint(format!(
+                "{}: scheme must be http or https",
+                self.http_endpoint
+            )));
+        }
+        if let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) {
+            self.resolved
+                .ensure(host, port, &self.dns_retry)
+                .map_err(|e| SendError::Resolve(format!("{host}: {e}")))?;
+        }
+
+        // the additional headers replace the default ones with the same name, instead of adding another value
+        let mut additional = HeaderMap::new();
+        let sender_headers = self.headers.iter().map(|(n, v)| (n.as_str(), v.as_str()));
+        for (name, value) in sender_headers.chain(headers.iter().copied()) {
+            let invalid =
+                |e: &dyn std::fmt::Display| SendError::InvalidHeader(format!("{name}: {e}"));
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| invalid(&e))?;
+            let value = HeaderValue::from_str(value).map_err(|e| invalid(&e))?;
+            additional.insert(name, value);
+        }
+        let mut request = self
+            .client
+            .post(url)
+            .header(USER_AGENT, SORACOM_HARVEST_USER_AGENT)
+            .header(CONTENT_TYPE, content_type)
+            .headers(additional);
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+
+        #[cfg(feature = "flate2")]
+        let body = if self
+            .gzip_threshold
+            .is_some_and(|threshold| body.len() >= threshold)
+        {
+            request = request.header(reqwest::header::CONTENT_ENCODING, "gzip");
+            gzip(body)?
+        } else {
+            body.to_vec()
+        };
+        #[cfg(not(feature = "flate2"))]
+        let body = {
+            if self.gzip_threshold.is_some() {
+                log::warn!(
+                    "gzip_threshold is set, but the body isn't compressed as `flate2` feature is disabled"
+                );
+            }
+            body.to_vec()
+        };
+
+        let start = Instant::now();
+        let response = request.body(body).send()?;
+
+        if error_for_status {
+            check_status(&response)?;
+        }
+
+        let outcome = SendOutcome {
+            bytes,
+            status: response.status().as_u16(),
+            elapsed: start.elapsed(),
+            endpoint: self.http_endpoint.clone(),
+            remote_addr: response.remote_addr(),
+        };
+        Ok((outcome, response))
+    }
+
+    fn send_to(&self, data: &[u8]) -> Result<(), SendError> {
+        check_host_port(&self.udp_endpoint)?;
+        let addrs = dns::resolve(self.udp_endpoint.as_str(), &self.dns_retry)
+            .map_err(|e| SendError::Resolve(format!("{}: {e}", self.udp_endpoint)))?;
+
+        // try the addresses in order, e.g. an IPv6 address unreachable from an IPv4-only network, then IPv4
+        let mut last_error = None;
+        for addr in addrs {
+            match self.send_datagram(data, addr) {
+                Ok(()) => return Ok(()),
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(match last_error {
+            Some(e) => e.into(),
+            None => SendError::Resolve(format!("{}: no addresses", self.udp_endpoint)),
+        })
+    }
+
+    /// Sends a datagram to the address, from the socket of its address family.
+    fn send_datagram(&self, data: &[u8], addr: SocketAddr) -> io::Result<()> {
+        let socket = self.udp_socket(addr)?;
+        if let Some(wait) = self.udp_warmup {
+            socket.send_to(&[], addr)?;
+            thread::sleep(wait);
+        }
+        socket.send_to(data, addr)?;
+
+        Ok(())
+    }
+
+    fn write_to(&self, data: &[u8]) -> Result<(), SendError> {
+        check_host_port(&self.tcp_endpoint)?;
+        let addrs = dns::resolve(self.tcp_endpoint.as_str(), &self.dns_retry)
+            .map_err(|e| SendError::Resolve(format!("{}: {e}", self.tcp_endpoint)))?;
+
+        let mut last_error = None;
+        for addr in addrs {
+            match TcpStream::connect_timeout(&addr, self.socket_timeout()) {
+                Ok(mut stream) => {
+                    stream.set_write_timeout(Some(self.socket_timeout()))?;
+                    stream.write_all(data)?;
+                    stream.flush()?;
+                    return Ok(());
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(match last_error {
+            Some(e) => e.into(),
+            None => SendError::Resolve(format!("{}: no addresses", self.tcp_endpoint)),
+        })
+    }
+
+    /// Returns the UDP socket of the address family of `addr`, binding it on the first call for the family.
+    fn udp_socket(&self, addr: SocketAddr) -> io::Result<&UdpSocket> {
+        let cell = &self.udp_sockets[usize::from(addr.is_ipv6())];
+        if let Some(socket) = cell.get() {
+            return Ok(socket);
+        }
+
+        let socket = UdpSocket::bind(unspecified_addr(addr))?;
+        socket.set_write_timeout(Some(self.socket_timeout()))?;
+        // another thread may have bound one meanwhile, in which case this one is dropped
+        Ok(cell.get_or_init(|| socket))
+    }
+
+    /// Returns the timeout for UDP and TCP sockets.
+    fn socket_timeout(&self) -> Duration {
+        self.timeout.unwrap_or(DEFAULT_SOCKET_TIMEOUT)
+    }
+
+    /// Calls `send` unless an identical message was sent within `dedup_window`, and records the message if
+    /// sent successfully.
+    fn dedup<T>(
+        &self,
+        protocol: &str,
+        data: &[u8],
+        send: impl FnOnce() -> Result<T, SendError>,
+    ) -> Result<T, SendError> {
+        let window = match self.dedup_window {
+            Some(window) => window,
+            None => return send(),
+        };
+
+        let endpoint = match protocol {
+            "http" => &self.http_endpoint,
+            "udp" => &self.udp_endpoint,
+            _ => &self.tcp_endpoint,
+        };
+        let mut hasher = DefaultHasher::new();
+        (protocol, endpoint, data).hash(&mut hasher);
+        let hash = hasher.finish();
+
+        {
+            let mut sent = self.sent.lock().unwrap();
+            sent.retain(|_, at| at.elapsed() < window);
+            if sent.contains_key(&hash) {
+                return Err(SendError::Duplicate);
+            }
+        }
+
+        let result = send();
+        if result.is_ok() {
+            self.sent.lock().unwrap().insert(hash, Instant::now());
+        }
+        result
+    }
+
+    #[cfg(feature = "audit")]
+    fn audit(&self, protocol: &str, bytes: usize, result: Result<String, String>) {
+        if let Some(path) = &self.audit_db {
+            if let Err(e) = crate::audit::record(path, protocol, bytes, result) {
+                log::warn!("Failed to record the send to the audit log: {e}");
+            }
+        }
+    }
+
+    #[cfg(not(feature = "audit"))]
+    fn audit(&self, _protocol: &str, _bytes: usize, _result: Result<String, String>) {
+        if self.audit_db.is_some() {
+            log::warn!(
+                "audit_db is set, but the send isn't recorded as `audit` feature is disabled"
+            );
+        }
+    }
+}
+
+/// Returns the body compressed with gzip.
+#[cfg(feature = "flate2")]
+fn gzip(body: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(body)?;
+    encoder.finish()
+}
+
+/// Returns the address to bind a socket to send to `addr`, i.e. `0.0.0.0:0` or `[::]:0` for its address
+/// family.
+pub(crate) fn unspecified_addr(addr: SocketAddr) -> SocketAddr {
+    if addr.is_ipv6() {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    }
+}
+
+/// Checks that the UDP or TCP endpoint is `host:port` e.g. `harvest.soracom.io:8514` or `[::1]:8514`,
+/// before resolving it, so that a typo fails immediately rather than after DNS retries.
+fn check_host_port(endpoint: &str) -> Result<(), SendError> {
+    match endpoint.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(()),
+        _ => Err(SendError::InvalidEndpoint(format!(
+            "{endpoint}: must be host:port"
+        ))),
+    }
+}
+
+/// Returns whether the error of an HTTP send is worth retrying with the policy.
+fn is_transient(e: &SendError, policy: &RetryPolicy) -> bool {
+    match e {
+        SendError::Http(e) => e.is_connect(),
+        SendError::Timeout => true,
+        SendError::ServerError { .. } => policy.retry_server_errors,
+        _ => false,
+    }
+}
+
+/// Maps non-2xx status of the response to [`SendError`].
+fn check_status(response: &Response) -> Result<(), SendError> {
+    let status = response.status();
+    match status.as_u16() {
+        _ if status.is_success() => Ok(()),
+        429 => Err(SendError::RateLimited {
+            retry_after: response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map(Duration::from_secs),
+        }),
+        _ if status.is_client_error() => Err(SendError::ClientError {
+            status: status.as_u16(),
+        }),
+        _ if status.is_server_error() => Err(SendError::ServerError {
+            status: status.as_u16(),
+        }),
+        _ => Err(SendError::UnexpectedStatus {
+            status: status.as_u16(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dns::DnsRetry,
+        error::SendError,
+        sender::{new_idempotency_key, HttpSendOutcome, Protocol, RetryPolicy, Sender},
+    };
+    use mockito::Matcher;
+    use std::{
+        io::Read,
+        net::{TcpListener, UdpSocket},
+        time::Duration,
+    };
+
+    #[test]
+    fn test_send_http_detailed() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .match_header("content-type", "application/json")
+            .match_header("user-agent", "soracom_harvest_api_client")
+            .match_body(r#"{"temperature":20}"#)
+            .with_status(201)
+            .create();
+
+        let sender = Sender::builder().http_endpoint(server.url()).build();
+        let outcome = sender.send_http_detailed(r#"{"temperature":20}"#).unwrap();
+
+        mock.assert();
+        assert_eq!(outcome.bytes, 18);
+        assert_eq!(outcome.status, 201);
+        assert!(outcome.elapsed > Duration::ZERO);
+        assert_eq!(outcome.endpoint, server.url());
+        assert_eq!(outcome.remote_addr, Some(server.socket_address()));
+    }
+
+    #[test]
+    fn test_send_http_raw() {
+        let mut server = mockito::Server::new();
+        let sender = Sender::builder().http_endpoint(server.url()).build();
+
+        for (body, content_type) in [
+            (br#"{"temperature":20}"#.to_vec(), "application/json"),
+            (b"temperature,humidity\n20,60\n".to_vec(), "text/csv"),
+            (vec![0x00, 0xff, 0x10, 0x80], "application/octet-stream"),
+        ] {
+            let mock = server
+                .mock("POST", "/")
+                .match_header("content-type", content_type)
+                .match_header("x-device-id", "device-1")
+                .match_body(body.clone())
+                .with_status(201)
+                .create();
+
+            let outcome = sender
+                .send_http_raw(&body, content_type, &[("x-device-id", "device-1")])
+                .unwrap();
+
+            mock.assert();
+            mock.remove();
+            assert_eq!(outcome.status, 201);
+            assert_eq!(outcome.bytes, body.len());
+        }
+    }
+
+    #[test]
+    fn test_headers() {
+        let mut server = mockito::Server::new();
+        let sender = Sender::builder()
+            .http_endpoint(server.url())
+            .headers(vec![
+                ("x-device-id".to_string(), "device-1".to_string()),
+                ("x-firmware".to_string(), "1.0.0".to_string()),
+            ])
+            .build();
+
+        // the default content type is replaced rather than added to, and so is the header of the sender
+        let mock = server
+            .mock("POST", "/")
+            .match_header("x-device-id", "device-1")
+            .match_request(|request| {
+                request.header("content-type") == ["text/csv"]
+                    && request.header("x-firmware") == ["2.0.0"]
+            })
+            .create();
+        sender
+            .send_http_raw(
+                b"20,60",
+                "application/json",
+                &[("Content-Type", "text/csv"), ("x-firmware", "2.0.0")],
+            )
+            .unwrap();
+        mock.assert();
+
+        assert!(matches!(
+            sender.send_http_raw(b"{}", "application/json", &[("x device", "1")]),
+            Err(SendError::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn test_send_http_with_content_type() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .match_header("content-type", "application/x-www-form-urlencoded")
+            .match_body("temperature=20&humidity=60")
+            .with_status(201)
+            .create();
+
+        let sender = Sender::builder().http_endpoint(server.url()).build();
+        sender
+            .send_http_with_content_type(
+                "temperature=20&humidity=60",
+                "application/x-www-form-urlencoded",
+            )
+            .unwrap();
+
+        mock.assert();
+    }
+
+    #[test]
+    fn test_send_http_to_path() {
+        let mut server = mockito::Server::new();
+        let root = server.mock("POST", "/").expect(0).create();
+        let ingest = server
+            .mock("POST", "/ingest")
+            .match_body(r#"{"temperature":20}"#)
+            .create();
+
+        let sender = Sender::builder()
+            .http_endpoint(format!("{}/ingest", server.url()))
+            .build();
+        sender.send_http(r#"{"temperature":20}"#).unwrap();
+
+        ingest.assert();
+        root.assert();
+    }
+
+    #[test]
+    fn test_invalid_http_endpoint() {
+        for endpoint in ["harvest.soracom.io", "ftp://harvest.soracom.io"] {
+            let sender = Sender::builder().http_endpoint(endpoint).build();
+            let err = sender.send_http("{}").unwrap_err();
+            assert!(matches!(err, SendError::InvalidEndpoint(_)));
+        }
+    }
+
+    #[test]
+    fn test_invalid_udp_tcp_endpoint() {
+        for endpoint in ["harvest.soracom.io", ":8514", "harvest.soracom.io:port"] {
+            let sender = Sender::builder()
+                .udp_endpoint(endpoint)
+                .tcp_endpoint(endpoint)
+                .build();
+            for err in [
+                sender.send_udp("hello").unwrap_err(),
+                sender.send_tcp("hello").unwrap_err(),
+            ] {
+                assert!(matches!(
+                    err,
+                    SendError::InvalidEndpoint(e) if e == format!("{endpoint}: must be host:port")
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_send_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let sender = Sender::builder()
+            .tcp_endpoint(listener.local_addr().unwrap().to_string())
+            .build();
+        sender.send_tcp("hello").unwrap();
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut received = String::new();
+        stream.read_to_string(&mut received).unwrap();
+        assert_eq!(received, "hello");
+    }
+
+    #[test]
+    fn test_send_with_fallback() {
+        // nothing listens on the port once the listener is dropped
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let sender = Sender::builder()
+            .http_endpoint(format!("http://{closed}"))
+            .udp_endpoint(receiver.local_addr().unwrap().to_string())
+            .build();
+
+        assert_eq!(
+            sender
+                .send_with_fallback("hello", Protocol::Http, Protocol::Udp)
+                .unwrap(),
+            Protocol::Udp
+        );
+        let mut buf = [0u8; 16];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"hello");
+
+        assert_eq!(
+            sender
+                .send_with_fallback("hello", Protocol::Udp, Protocol::Http)
+                .unwrap(),
+            Protocol::Udp
+        );
+        assert!(sender
+            .send_with_fallback("hello", Protocol::Http, Protocol::Http)
+            .is_err());
+    }
+
+    #[test]
+    fn test_send_with_fallback_duplicate() {
+        let mut server = mockito::Server::new();
+        let http = server.mock("POST", "/").expect(1).create();
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+
+        let sender = Sender::builder()
+            .http_endpoint(server.url())
+            .udp_endpoint(receiver.local_addr().unwrap().to_string())
+            .dedup_window(Duration::from_secs(60))
+            .build();
+
+        assert_eq!(
+            sender
+                .send_with_fallback("hello", Protocol::Http, Protocol::Udp)
+                .unwrap(),
+            Protocol::Http
+        );
+        // the duplicate isn't sent via the fallback either
+        assert!(matches!(
+            sender.send_with_fallback("hello", Protocol::Http, Protocol::Udp),
+            Err(SendError::Duplicate)
+        ));
+        http.assert();
+        let mut buf = [0u8; 16];
+        assert!(receiver.recv(&mut buf).is_err());
+
+        // nor a message with an invalid header
+        let sender = Sender::builder()
+            .http_endpoint(server.url())
+            .udp_endpoint(receiver.local_addr().unwrap().to_string())
+            .headers(vec![("invalid header".to_string(), "x".to_string())])
+            .build();
+        assert!(matches!(
+            sender.send_with_fallback("hello", Protocol::Http, Protocol::Udp),
+            Err(SendError::InvalidHeader(_))
+        ));
+        assert!(receiver.recv(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_http_timeout() {
+        // accepts connections, but never responds
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let sender = Sender::builder()
+            .http_endpoint(format!("http://{}", listener.local_addr().unwrap()))
+            .timeout(Duration::from_millis(200))
+            .build();
+
+        let start = std::time::Instant::now();
+        let err = sender.send_http("{}").unwrap_err();
+        assert!(matches!(err, SendError::Timeout));
+        assert_eq!(err.status(), None);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_tcp_timeout() {
+        // accepts connections, but never reads, so the write blocks once the buffers are full
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let sender = Sender::builder()
+            .tcp_endpoint(listener.local_addr().unwrap().to_string())
+            .timeout(Duration::from_millis(200))
+            .build();
+
+        let start = std::time::Instant::now();
+        let err = sender.send_tcp("x".repeat(64 << 20)).unwrap_err();
+        assert!(matches!(err, SendError::Timeout));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        drop(listener);
+    }
+
+    #[test]
+    fn test_unresolvable_endpoint() {
+        let retry = DnsRetry {
+            retries: 1,
+            backoff: Duration::from_millis(10),
+        };
+        let sender = Sender::builder()
+            .http_endpoint("http://harvest.invalid")
+            .udp_endpoint("harvest.invalid:8514")
+            .dns_retry(retry)
+            .build();
+
+        for err in [
+            sender.send_http("{}").unwrap_err(),
+            sender.send_udp("hello").unwrap_err(),
+        ] {
+            assert!(matches!(err, SendError::Resolve(_)));
+        }
+    }
+
+    #[test]
+    fn test_send_http_with_retry() {
+        let mut server = mockito::Server::new();
+        let sender = Sender::builder()
+            .http_endpoint(server.url())
+            .idempotency_header("Idempotency-Key")
+            .build();
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            jitter: false,
+            ..Default::default()
+        };
+
+        // fails twice, then succeeds on the last attempt, with the same key
+        let key = Matcher::Regex("^[0-9a-f-]{36}$".to_string());
+        let failure = server
+            .mock("POST", "/")
+            .match_header("idempotency-key", key.clone())
+            .with_status(503)
+            .expect(2)
+            .create();
+        let success = server
+            .mock("POST", "/")
+            .match_header("idempotency-key", key)
+            .with_status(201)
+            .expect(1)
+            .create();
+        assert_eq!(
+            sender.send_http_with_retry("{}", &policy).unwrap().status,
+            201
+        );
+        failure.assert();
+        success.assert();
+        failure.remove();
+        success.remove();
+
+        // 4xx isn't retried, nor is 5xx if disabled
+        for (status, policy) in [
+            (400, policy),
+            (
+                500,
+                RetryPolicy {
+                    retry_server_errors: false,
+                    ..policy
+                },
+            ),
+        ] {
+            let mock = server
+                .mock("POST", "/")
+                .with_status(status)
+                .expect(1)
+                .create();
+            assert!(sender.send_http_with_retry("{}", &policy).is_err());
+            mock.assert();
+            mock.remove();
+        }
+
+        // connection errors are retried until the attempts run out
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let sender = Sender::builder()
+            .http_endpoint(format!("http://{addr}"))
+            .build();
+        let start = std::time::Instant::now();
+        let err = sender.send_http_with_retry("{}", &policy).unwrap_err();
+        assert!(matches!(err, SendError::Http(e) if e.is_connect()));
+        assert!(start.elapsed() >= Duration::from_millis(3));
+    }
+
+    #[test]
+    fn test_retry_policy_delay() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+            jitter: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            (0..4).map(|n| policy.delay(n)).collect::<Vec<_>>(),
+            [100, 200, 300, 300].map(Duration::from_millis)
+        );
+        assert_eq!(policy.delay(u32::MAX), Duration::from_millis(300));
+
+        let policy = RetryPolicy {
+            jitter: true,
+            ..policy
+        };
+        for _ in 0..100 {
+            let delay = policy.delay(1);
+            assert!((Duration::from_millis(100)..=Duration::from_millis(200)).contains(&delay));
+        }
+    }
+
+    #[test]
+    fn test_send_udp_with_retry() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sender = Sender::builder()
+            .udp_endpoint(receiver.local_addr().unwrap().to_string())
+            .build();
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(10),
+            jitter: false,
+            ..Default::default()
+        };
+
+        sender.send_udp_with_retry(b"hello", &policy).unwrap();
+        let mut buf = [0; 16];
+        assert_eq!(receiver.recv(&mut buf).unwrap(), 5);
+
+        // a datagram larger than UDP allows fails on send, and is retried after 10ms + 20ms
+        let start = std::time::Instant::now();
+        let err = sender
+            .send_udp_with_retry(&[0; 70000], &policy)
+            .unwrap_err();
+        assert!(matches!(err, SendError::Io(_)));
+        assert!(start.elapsed() >= Duration::from_millis(30));
+
+        // an invalid endpoint isn't retried
+        let sender = Sender::builder().udp_endpoint("localhost").build();
+        let start = std::time::Instant::now();
+        assert!(sender.send_udp_with_retry(b"hello", &policy).is_err());
+        assert!(start.elapsed() < Duration::from_millis(10));
+    }
+
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn test_gzip_threshold() {
+        use std::io::Read;
+
+        let mut server = mockito::Server::new();
+        let sender = Sender::builder()
+            .http_endpoint(server.url())
+            .gzip_threshold(16)
+            .build();
+
+        // echoes the decompressed body back
+        let mock = server
+            .mock("POST", "/")
+            .match_header("content-encoding", "gzip")
+            .with_body_from_request(|request| {
+                let mut body = Vec::new();
+                flate2::read::GzDecoder::new(request.body().unwrap().as_slice())
+                    .read_to_end(&mut body)
+                    .unwrap();
+                body
+            })
+            .create();
+        let body = format!("[{}]", vec![r#"{"temperature":20}"#; 100].join(","));
+        assert_eq!(sender.send_http_with_response(&body).unwrap().body, body);
+        mock.assert();
+
+        let mock = server
+            .mock("POST", "/")
+            .match_header("content-encoding", Matcher::Missing)
+            .match_body("{}")
+            .create();
+        sender.send_http("{}").unwrap();
+        mock.assert();
+    }
+
+    #[test]
+    fn test_send_http_array() {
+        let mut server = mockito::Server::new();
+        let first = server
+            .mock("POST", "/")
+            .match_body(r#"{"temperature":20}"#)
+            .with_status(201)
+            .create();
+        let second = server
+            .mock("POST", "/")
+            .match_body(r#"{"temperature":21}"#)
+            .with_status(201)
+            .create();
+
+        let sender = Sender::builder().http_endpoint(server.url()).build();
+        let results = sender.send_http_array(&[
+            serde_json::json!({"temperature": 20}),
+            serde_json::json!({"temperature": 21}),
+        ]);
+
+        first.assert();
+        second.assert();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.as_ref().unwrap().status == 201));
+    }
+
+    #[test]
+    fn test_send_http_fanout() {
+        let mut servers = [mockito::Server::new(), mockito::Server::new()];
+        let mocks = servers
+            .iter_mut()
+            .map(|server| {
+                server
+                    .mock("POST", "/")
+                    .match_body(r#"{"temperature":20}"#)
+                    .with_status(201)
+                    .expect(1)
+                    .create()
+            })
+            .collect::<Vec<_>>();
+        let endpoints = [
+            servers[0].url(),
+            servers[1].url(),
+            "ftp://harvest.soracom.io".to_string(),
+        ];
+
+        // sent to each endpoint despite the dedup window, and the invalid endpoint doesn't fail the others
+        let sender = Sender::builder()
+            .dedup_window(Duration::from_secs(60))
+            .build();
+        let results = sender.send_http_fanout(
+            &endpoints.iter().map(|e| e.as_str()).collect::<Vec<_>>(),
+            r#"{"temperature":20}"#,
+        );
+
+        for mock in mocks {
+            mock.assert();
+        }
+        assert_eq!(results[0].as_ref().unwrap().endpoint, endpoints[0]);
+        assert_eq!(results[1].as_ref().unwrap().endpoint, endpoints[1]);
+        assert!(matches!(results[2], Err(SendError::InvalidEndpoint(_))));
+    }
+
+    #[test]
+    fn test_idempotency_key() {
+        let mut server = mockito::Server::new();
+        let sender = Sender::builder()
+            .http_endpoint(server.url())
+            .idempotency_header("x-idempotency-key")
+            .error_for_status(true)
+            .build();
+
+        // the same key is sent on the retry after the server error
+        let key = new_idempotency_key();
+        let failure = server
+            .mock("POST", "/")
+            .match_header("x-idempotency-key", key.as_str())
+            .with_status(503)
+            .expect(1)
+            .create();
+        assert!(sender.send_http_with_idempotency_key("{}", &key).is_err());
+        failure.assert();
+        failure.remove();
+
+        let success = server
+            .mock("POST", "/")
+            .match_header("x-idempotency-key", key.as_str())
+            .create();
+        sender.send_http_with_idempotency_key("{}", &key).unwrap();
+        success.assert();
+        success.remove();
+
+        // otherwise a new UUID is generated for each message
+        let generated = server
+            .mock("POST", "/")
+            .match_header(
+                "x-idempotency-key",
+                Matcher::Regex(
+                    "^[0-9a-f]{8}-[0-9a-f]{4}-4[0-9a-f]{3}-[89ab][0-9a-f]{3}-[0-9a-f]{12}$".into(),
+                ),
+            )
+            .expect(2)
+            .create();
+        sender.send_http("{}").unwrap();
+        sender.send_http("{}").unwrap();
+        generated.assert();
+        assert_ne!(new_idempotency_key(), new_idempotency_key());
+    }
+
+    #[test]
+    fn test_send_http_with_response() {
+        let mut server = mockito::Server::new();
+        let sender = Sender::builder().http_endpoint(server.url()).build();
+
+        let mock = server
+            .mock("POST", "/")
+            .with_status(201)
+            .with_body("created")
+            .create();
+        assert_eq!(
+            sender.send_http_with_response("{}").unwrap(),
+            HttpSendOutcome {
+                status: 201,
+                body: "created".to_string()
+            }
+        );
+        mock.remove();
+
+        // 400 is an error even without `error_for_status`
+        server.mock("POST", "/").with_status(400).create();
+        let err = sender.send_http_with_response("{").unwrap_err();
+        assert!(matches!(err, SendError::ClientError { status: 400 }));
+    }
+
+    #[test]
+    fn test_error_for_status() {
+        let mut server = mockito::Server::new();
+        let sender = Sender::builder()
+            .http_endpoint(server.url())
+            .error_for_status(true)
+            .build();
+
+        for (status, retry_after, expected) in [
+            (400, None, SendError::ClientError { status: 400 }),
+            (503, None, SendError::ServerError { status: 503 }),
+            (
+                429,
+                Some("30"),
+                SendError::RateLimited {
+                    retry_after: Some(Duration::from_secs(30)),
+                },
+            ),
+            (429, None, SendError::RateLimited { retry_after: None }),
+        ] {
+            let mut mock = server.mock("POST", "/").with_status(status);
+            if let Some(retry_after) = retry_after {
+                mock = mock.with_header("retry-after", retry_after);
+            }
+            let mock = mock.create();
+
+            let err = sender.send_http("{}").unwrap_err();
+            // `SendError` isn't `PartialEq` for its transparent errors
+            assert_eq!(format!("{err:?}"), format!("{expected:?}"));
+            assert_eq!(err.status(), Some(status as u16));
+            mock.remove();
+        }
+
+        // non-2xx is returned as is by default
+        server.mock("POST", "/").with_status(400).create();
+        let sender = Sender::builder().http_endpoint(server.url()).build();
+        assert_eq!(sender.send_http_detailed("{}").unwrap().status, 400);
+    }
+
+    #[test]
+    fn test_dedup_window() {
+        let mut server = mockito::Server::new();
+        let first = server
+            .mock("POST", "/")
+            .match_body(r#"{"temperature":20}"#)
+            .expect(1)
+            .create();
+        let second = server
+            .mock("POST", "/")
+            .match_body(r#"{"temperature":21}"#)
+            .expect(1)
+            .create();
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let sender = Sender::builder()
+            .http_endpoint(server.url())
+            .udp_endpoint(receiver.local_addr().unwrap().to_string())
+            .dedup_window(Duration::from_secs(60))
+            .build();
+
+        sender.send_http(r#"{"temperature":20}"#).unwrap();
+        let err = sender.send_http(r#"{"temperature":20}"#).unwrap_err();
+        assert!(matches!(err, SendError::Duplicate));
+        sender.send_http(r#"{"temperature":21}"#).unwrap();
+        // same content via another protocol isn't a duplicate
+        sender.send_udp(r#"{"temperature":20}"#).unwrap();
+
+        first.assert();
+        second.assert();
+
+        let sender = Sender::builder()
+            .http_endpoint(server.url())
+            .dedup_window(Duration::ZERO)
+            .build();
+        sender.send_http(r#"{"temperature":20}"#).unwrap();
+        sender.send_http(r#"{"temperature":20}"#).unwrap();
+    }
+
+    #[test]
+    fn test_send_udp_with_warmup() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let sender = Sender::builder()
+            .udp_endpoint(receiver.local_addr().unwrap().to_string())
+            .udp_warmup(Duration::from_millis(10))
+            .build();
+        sender.send_udp("hello").unwrap();
+
+        let mut buf = [0u8; 16];
+        assert_eq!(receiver.recv(&mut buf).unwrap(), 0);
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"hello");
+    }
+
+    #[test]
+    fn test_send_udp_bytes() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let sender = Sender::builder()
+            .udp_endpoint(receiver.local_addr().unwrap().to_string())
+            .build();
+        // not valid UTF-8
+        sender.send_udp_bytes(&[0xff, 0x00, 0xfe]).unwrap();
+
+        let mut buf = [0u8; 16];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &[0xff, 0x00, 0xfe]);
+    }
+
+    #[test]
+    fn test_with_overrides() {
+        let mut server = mockito::Server::new();
+        let mock = server.mock("POST", "/proxy").expect(2).create();
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let sender = Sender::new();
+        let to = sender.with_http_endpoint(format!("{}/proxy", server.url()));
+        to.send_http("hello").unwrap();
+        to.with_timeout(Duration::from_secs(1))
+            .send_http("hello")
+            .unwrap();
+        mock.assert();
+
+        // the UDP socket is shared across endpoints, but not with another timeout
+        let endpoint = receiver.local_addr().unwrap().to_string();
+        sender
+            .with_udp_endpoint(&endpoint)
+            .send_udp("first")
+            .unwrap();
+        sender
+            .with_udp_endpoint(&endpoint)
+            .send_udp("second")
+            .unwrap();
+        let timeout = sender.with_timeout(Duration::from_secs(1));
+        timeout
+            .with_udp_endpoint(&endpoint)
+            .send_udp("third")
+            .unwrap();
+        let mut buf = [0u8; 16];
+        let (_, first) = receiver.recv_from(&mut buf).unwrap();
+        let (_, second) = receiver.recv_from(&mut buf).unwrap();
+        let (_, third) = receiver.recv_from(&mut buf).unwrap();
+        assert_eq!(first, second);
+        assert_ne!(first, third);
+    }
+
+    #[test]
+    fn test_http_udp_shorthands() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .match_body(r#"{"temperature":20}"#)
+            .create();
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let sender = Sender::builder()
+            .http_endpoint(server.url())
+            .udp_endpoint(receiver.local_addr().unwrap().to_string())
+            .build();
+        sender.http(r#"{"temperature":20}"#).unwrap();
+        sender.udp("hello").unwrap();
+
+        mock.assert();
+        let mut buf = [0u8; 16];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"hello");
+    }
+
+    #[test]
+    fn test_send_udp_reuses_socket() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let sender = Sender::builder()
+            .udp_endpoint(receiver.local_addr().unwrap().to_string())
+            .build();
+        let clone = sender.clone();
+        sender.send_udp("first").unwrap();
+        clone.send_udp("second").unwrap();
+
+        let mut buf = [0u8; 16];
+        let (_, first) = receiver.recv_from(&mut buf).unwrap();
+        let (len, second) = receiver.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"second");
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_send_udp_ipv6() {
+        let receiver = UdpSocket::bind("[::1]:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let v4_receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        v4_receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        // one sender can send to both address families
+        let sender = Sender::builder()
+            .udp_endpoint(receiver.local_addr().unwrap().to_string())
+            .build();
+        sender.send_udp("hello").unwrap();
+        Sender {
+            udp_endpoint: v4_receiver.local_addr().unwrap().to_string(),
+            ..sender.clone()
+        }
+        .send_udp("hello")
+        .unwrap();
+
+        let mut buf = [0u8; 16];
+        let (len, source) = receiver.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"hello");
+        assert!(source.is_ipv6());
+        let (len, source) = v4_receiver.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"hello");
+        assert!(source.is_ipv4());
+    }
+
+    #[cfg(feature = "audit")]
+    #[test]
+    fn test_audit_db() {
+        let mut server = mockito::Server::new();
+        server.mock("POST", "/").with_status(201).create();
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.db");
+
+        let sender = Sender::builder()
+            .http_endpoint(server.url())
+            .udp_endpoint(receiver.local_addr().unwrap().to_string())
+            .audit_db(&path)
+            .build();
+        sender.send_http(r#"{"temperature":20}"#).unwrap();
+        sender.send_http("{}").unwrap();
+        sender.send_udp("hello").unwrap();
+
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        let mut stmt = conn
+            .prepare("SELECT protocol, bytes, outcome, detail FROM sent_messages ORDER BY rowid")
+            .unwrap();
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, usize>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            rows,
+            vec![
+                ("http".to_string(), 18, "ok".to_string(), "201".to_string()),
+                ("http".to_string(), 2, "ok".to_string(), "201".to_string()),
+                ("udp".to_string(), 5, "ok".to_string(), "".to_string()),
+            ]
+        );
+    }
+}
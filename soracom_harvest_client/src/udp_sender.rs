@@ -0,0 +1,158 @@
+//! Lightweight UDP sender which keeps the socket bound across sends.
+
+use crate::{sender::unspecified_addr, SORACOM_HARVEST_TCP_UDP_ENDPOINT};
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    time::Duration,
+};
+
+/// UDP sender for Soracom Harvest Data, which binds a socket and resolves the endpoint once, and reuses
+/// them for every send, unlike [`send_udp_message`](crate::send_udp_message) or
+/// [`Sender`](crate::sender::Sender) which resolve the endpoint for each message, in return for neither
+/// DNS retry nor warmup. Suitable for high-frequency senders.
+///
+/// As the address is cached, the sender keeps sending to the old address if the IP of the endpoint rotates,
+/// and datagrams are silently lost as UDP has no response. Call [`refresh_dns`](Self::refresh_dns)
+/// periodically e.g. hourly, or on reconnecting the network, to pick up a new address.
+///
+/// # Example
+///
+/// ```no_run
+/// use soracom_harvest_client::udp_sender::UdpSender;
+///
+/// let sender = UdpSender::new().unwrap();
+/// for i in 0..10 {
+///     sender.send(format!("reading {i}")).unwrap();
+/// }
+/// ```
+pub struct UdpSender {
+    socket: UdpSocket,
+    endpoint: String,
+}
+
+impl UdpSender {
+    /// Returns a new sender to `harvest.soracom.io:8514`.
+    pub fn new() -> io::Result<Self> {
+        Self::with_endpoint(SORACOM_HARVEST_TCP_UDP_ENDPOINT)
+    }
+
+    /// Returns a new sender to the endpoint (`host:port`). The endpoint is resolved only once here, and the
+    /// socket is bound for the address family of the first address which can be connected to, e.g. IPv6 on
+    /// an IPv6-only network.
+    pub fn with_endpoint(endpoint: impl AsRef<str>) -> io::Result<Self> {
+        let mut last_error = None;
+        for addr in endpoint.as_ref().to_socket_addrs()? {
+            match connect(addr) {
+                Ok(socket) => {
+                    return Ok(UdpSender {
+                        socket,
+                        endpoint: endpoint.as_ref().to_string(),
+                    })
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(last_error
+            .unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no addresses to send to")))
+    }
+
+    /// Resolves the endpoint again, and sends following messages to the new address. The cached address is
+    /// kept if it fails. The address family of the socket doesn't change.
+    pub fn refresh_dns(&self) -> io::Result<()> {
+        self.socket.connect(self.endpoint.as_str())
+    }
+
+    /// Returns the cached address of the endpoint which messages are sent to.
+    pub fn remote_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.peer_addr()
+    }
+
+    /// Send a message to Soracom Harvest Data via UDP, with the socket bound at creation.
+    pub fn send(&self, data: impl AsRef<[u8]>) -> io::Result<()> {
+        self.socket.send(data.as_ref())?;
+
+        Ok(())
+    }
+}
+
+/// Returns a socket bound for the address family of `addr`, and connected to it.
+fn connect(addr: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = UdpSocket::bind(unspecified_addr(addr))?;
+    socket.set_write_timeout(Some(Duration::from_secs(5)))?;
+    socket.connect(addr)?;
+
+    Ok(socket)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::udp_sender::UdpSender;
+    use std::{net::UdpSocket, time::Duration};
+
+    #[test]
+    fn test_send_multiple() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let sender = UdpSender::with_endpoint(receiver.local_addr().unwrap().to_string()).unwrap();
+        for message in ["first", "second", "third"] {
+            sender.send(message).unwrap();
+        }
+
+        let mut buf = [0u8; 16];
+        let mut sources = vec![];
+        for expected in ["first", "second", "third"] {
+            let (len, source) = receiver.recv_from(&mut buf).unwrap();
+            assert_eq!(&buf[..len], expected.as_bytes());
+            sources.push(source);
+        }
+        // all messages are sent from the same socket
+        assert!(sources.windows(2).all(|w| w[0] == w[1]));
+    }
+
+    #[test]
+    fn test_send_ipv6() {
+        let receiver = UdpSocket::bind("[::1]:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let sender = UdpSender::with_endpoint(receiver.local_addr().unwrap().to_string()).unwrap();
+        sender.send("hello").unwrap();
+
+        let mut buf = [0u8; 16];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"hello");
+    }
+
+    #[test]
+    fn test_refresh_dns() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let addr = receiver.local_addr().unwrap();
+
+        let sender = UdpSender::with_endpoint(format!("localhost:{}", addr.port())).unwrap();
+        let cached = sender.remote_addr().unwrap();
+        assert_eq!(cached.port(), addr.port());
+
+        // the cached address is reused across sends, and after refresh
+        let mut buf = [0u8; 16];
+        for message in ["first", "second"] {
+            sender.send(message).unwrap();
+            assert_eq!(sender.remote_addr().unwrap(), cached);
+        }
+        sender.refresh_dns().unwrap();
+        sender.send("third").unwrap();
+        assert_eq!(sender.remote_addr().unwrap().port(), addr.port());
+        for expected in ["first", "second", "third"] {
+            let len = receiver.recv(&mut buf).unwrap();
+            assert_eq!(&buf[..len], expected.as_bytes());
+        }
+    }
+}
@@ -1,6 +1,8 @@
 //! Error definition.
 
 use crate::sqlite3ext::sqlite3_api_routines;
+use soracom_harvest_api_client::error::SoracomHarvestClientError;
+use soracom_harvest_client::error::SendError;
 use std::{
     ffi::{c_char, c_int, CString},
     ptr::copy_nonoverlapping,
@@ -15,20 +17,90 @@ pub enum ArgumentError {
     NoImsi,
 
     /// Invalid `from` is provided.
-    #[error("Invalid 'from' is provided")]
+    #[error(
+        "Invalid 'from' is provided. It should be unix time in milliseconds, an RFC3339 datetime or a relative time e.g. '1d ago'"
+    )]
     InvalidFrom,
 
     /// Invalid `to` is provided.
-    #[error("Invalid 'to' is provided")]
+    #[error(
+        "Invalid 'to' is provided. It should be unix time in milliseconds, an RFC3339 datetime or a relative time e.g. '1d ago'"
+    )]
     InvalidTo,
 
     /// Invalid `limit` is provided. It should be from 1 to 1000.
     #[error("Invalid 'limit' is provided. It should be from 1 to 1000")]
     InvalidLimit,
 
-    /// Unknown option is provided.
-    #[error("Unknown option is provided")]
-    UnknownOption,
+    /// Invalid `require_data` is provided. It should be `true` or `false`.
+    #[error("Invalid 'require_data' is provided. It should be 'true' or 'false'")]
+    InvalidRequireData,
+
+    /// Invalid `decode` is provided. It should be a comma-separated list of decoders.
+    #[error("Invalid 'decode' is provided. It should be a comma-separated list of base64, hex, gzip or raw")]
+    InvalidDecode,
+
+    /// Invalid `binary` is provided. It should be `payload`, `hex` or `base64`.
+    #[error("Invalid 'binary' is provided. It should be 'payload', 'hex' or 'base64'")]
+    InvalidBinary,
+
+    /// Invalid `snapshot` is provided. It should be `true` or `false`.
+    #[error("Invalid 'snapshot' is provided. It should be 'true' or 'false'")]
+    InvalidSnapshot,
+
+    /// Invalid `unwrap_json` is provided. It should be `true` or `false`.
+    #[error("Invalid 'unwrap_json' is provided. It should be 'true' or 'false'")]
+    InvalidUnwrapJson,
+
+    /// Invalid `select_columns` is provided. It should be a comma-separated list of distinct columns.
+    #[error("Invalid 'select_columns' is provided. It should be a comma-separated list of distinct time, content_type or value")]
+    InvalidSelectColumns,
+
+    /// Unknown or malformed option is provided, e.g. a misspelled key.
+    #[error("Unknown option is provided: {0}")]
+    UnknownOption(String),
+}
+
+/// Possible errors while loading data for the virtual table
+#[derive(Debug, Error)]
+pub enum HarvestDataError {
+    /// Neither a client nor a fixture is provided.
+    #[error("No client or fixture is provided")]
+    NoSource,
+
+    /// The table is loaded from a fixture, so entries can't be deleted.
+    #[error("The table is read-only as it is loaded from a fixture")]
+    ReadOnly,
+
+    /// No data entry for the rowid.
+    #[error("No data entry for rowid {0}")]
+    NoSuchRow(i64),
+
+    /// Failed to read the fixture file.
+    #[error("Failed to read fixture: {0}")]
+    Fixture(#[from] std::io::Error),
+
+    /// Failed to parse the fixture file.
+    #[error("Failed to parse fixture: {0}")]
+    Json(#[from] serde_json::Error),
+
+    /// Transparent error from the API client.
+    #[error(transparent)]
+    Client(#[from] SoracomHarvestClientError),
+
+    /// The row to insert can't be sent to Harvest.
+    #[error("Invalid INSERT: {0}")]
+    InvalidInsert(&'static str),
+
+    /// Failed to send a message to Harvest.
+    #[error("Failed to send: {0}")]
+    Send(#[from] SendError),
+}
+
+impl From<HarvestDataError> for String {
+    fn from(e: HarvestDataError) -> Self {
+        e.to_string()
+    }
 }
 
 /// Convert error message to SQLite3 string.
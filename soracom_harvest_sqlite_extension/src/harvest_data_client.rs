@@ -1,72 +1,574 @@
 //! Represents Soracom Harvest Client and its associated data.
 
+use crate::error::HarvestDataError;
+use chrono::{DateTime, TimeDelta, Utc};
 use soracom_harvest_api_client::{
     client::{Data, SoracomHarvestClient},
+    decoder::{decode, DecodeOptions},
     error::SoracomHarvestClientError,
 };
+use soracom_harvest_client::sender::Sender;
+#[cfg(feature = "flate2")]
+use std::io::BufRead;
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    fs::File,
+    io::BufReader,
+    ops::RangeInclusive,
+    str::FromStr,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
 use typed_builder::TypedBuilder;
 
+/// Difference between the local and server time, beyond which an empty result is hinted as clock skew.
+const CLOCK_SKEW_THRESHOLD_MINUTES: i64 = 5;
+
+/// Minutes before the token expires, from which a cached client is authenticated again.
+const TOKEN_REFRESH_MARGIN_MINUTES: i64 = 5;
+
+/// Authenticated clients shared by tables created in the process, keyed by `auth_cache_key`.
+static AUTHENTICATED_CLIENTS: OnceLock<Mutex<HashMap<u64, SoracomHarvestClient>>> = OnceLock::new();
+
 /// Harvest Data
 #[derive(TypedBuilder)]
 pub struct HarvestDataClient {
-    #[builder]
-    client: SoracomHarvestClient,
+    #[builder(default, setter(strip_option))]
+    client: Option<SoracomHarvestClient>,
+    /// Path to a local JSON file of data entries, used instead of the API. For testing/demo.
+    #[builder(default, setter(strip_option))]
+    fixture: Option<String>,
     #[builder(default)]
     data: Vec<Data>,
+    /// IMSI of the target SIM, or a prefix followed by `%` e.g. `44120%` to target all SIMs of the
+    /// operator with the prefix.
     #[builder(default)]
     imsi: String,
+    /// IMSI of each data entry in `data`.
+    #[builder(default, setter(skip))]
+    imsis: Vec<String>,
     #[builder(default)]
     from: i64,
     #[builder(default)]
     to: i64,
     #[builder(default)]
     limit: u32,
+    /// Follow pagination to load all the data entries in the search range, instead of up to `limit` entries
+    /// of each SIM, e.g. not to miss any entry between polls.
+    #[builder(default)]
+    paginate: bool,
+    /// Return an error from `open()` when no data entries are loaded.
+    #[builder(default)]
+    require_data: bool,
+    /// Whether the search range is defaulted based on the local clock.
+    #[builder(default)]
+    default_window: bool,
+    /// Options to decode the content of data entries from the API. The client should keep the content
+    /// as is, so that the decode pass is timed separately.
+    #[builder(default)]
+    decode_options: DecodeOptions,
+    /// Key to share the authenticated client with other tables, e.g. a hash of the credentials and the
+    /// endpoint, to avoid authenticating for each table. Authenticates every time if `None`.
+    #[builder(default, setter(strip_option))]
+    auth_cache_key: Option<u64>,
+    /// Base columns of the table, followed by the hidden `imsi` column.
+    #[builder(default = Column::ALL.to_vec())]
+    columns: Vec<Column>,
+    /// Keep the data loaded by [`reload`](Self::reload) for later scans in the loaded range. Otherwise the
+    /// data is loaded for each scan, so that the limit of the scan can be pushed down too.
+    #[builder(default = true)]
+    snapshot: bool,
+    /// Search range of the data loaded for a snapshot, if any.
+    #[builder(default, setter(skip))]
+    loaded: Option<(i64, i64)>,
+    #[builder(default, setter(skip))]
+    warning: Option<String>,
+    #[builder(default, setter(skip))]
+    timings: Option<Timings>,
+}
+
+/// Breakdown of the time spent to load the data, for performance triage.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Timings {
+    /// Time spent to authenticate. Zero for a fixture.
+    pub auth: Duration,
+    /// Time spent to get data entries from the API, or to read the fixture.
+    pub fetch: Duration,
+    /// Time spent to decode the content of data entries. Zero for a fixture.
+    pub decode: Duration,
+}
+
+impl Display for Timings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "auth {}ms, fetch {}ms, decode {}ms",
+            self.auth.as_millis(),
+            self.fetch.as_millis(),
+            self.decode.as_millis()
+        )
+    }
 }
 
 impl HarvestDataClient {
-    /// Authenticate with provided credential and get data.
-    pub fn open(&mut self) -> Result<(), SoracomHarvestClientError> {
-        let client = self.client.auth()?;
-
-        self.data = client.get_data_entries(
-            &self.imsi,
-            Some(self.from),
-            Some(self.to),
-            Some(self.limit),
+    /// Authenticate with provided credential, or load data from the fixture if provided. The data from the
+    /// API is loaded by [`reload`](Self::reload) for the first scan, unless `require_data` is set, where the
+    /// whole search range is loaded to check it.
+    pub fn open(&mut self) -> Result<(), HarvestDataError> {
+        if let Some(fixture) = &self.fixture {
+            let start = Instant::now();
+            self.data = read_fixture(fixture)?;
+            self.imsis = vec![self.imsi.clone(); self.data.len()];
+            self.timings = Some(Timings {
+                fetch: start.elapsed(),
+                ..Default::default()
+            });
+        } else {
+            let start = Instant::now();
+            let client = authenticate(
+                self.client.as_ref().ok_or(HarvestDataError::NoSource)?,
+                self.auth_cache_key,
+            )?;
+            let auth = start.elapsed();
+            if self.require_data {
+                self.load(&client, &[(self.from, self.to)], self.limit, auth)?;
+                self.loaded = Some((self.from, self.to)).filter(|_| self.snapshot);
+            }
+            self.client = Some(client);
+        }
+
+        if self.require_data && self.data.is_empty() {
+            return Err(SoracomHarvestClientError::NoData.into());
+        }
+
+        Ok(())
+    }
+
+    /// Loads the data from the API for a scan, in the intersection of the search range of the table and
+    /// `from..=to`. For a snapshot, only the part of the range which isn't loaded yet, and the gap to the
+    /// loaded range, is loaded up to the limit of the table, and appended to the loaded data, so that the
+    /// entries seen by earlier scans and their rowids stay the same. Otherwise the data is loaded again for
+    /// each scan, up to `limit` entries if it is smaller than the limit of the table. Returns whether the
+    /// data is loaded.
+    pub fn reload(
+        &mut self,
+        from: i64,
+        to: i64,
+        limit: Option<u32>,
+    ) -> Result<bool, HarvestDataError> {
+        if self.fixture.is_some() {
+            return Ok(false);
+        }
+
+        let (from, to) = (self.from.max(from), self.to.min(to));
+        let (ranges, limit) = if !self.snapshot {
+            (self.imsis, self.data) = Default::default();
+            let limit = limit.map_or(self.limit, |limit| limit.min(self.limit));
+            (vec![(from, to)], limit)
+        } else if let Some((loaded_from, loaded_to)) = self.loaded {
+            let mut ranges = Vec::new();
+            if from <= to && from < loaded_from {
+                ranges.push((from, loaded_from - 1));
+            }
+            if from <= to && to > loaded_to {
+                ranges.push((loaded_to + 1, to));
+            }
+            if ranges.is_empty() {
+                return Ok(false);
+            }
+            (ranges, self.limit)
+        } else {
+            (vec![(from, to)], self.limit)
+        };
+
+        // authenticate again if the token is expired since the creation or the last scan
+        let start = Instant::now();
+        let client = authenticate(
+            self.client.as_ref().ok_or(HarvestDataError::NoSource)?,
+            self.auth_cache_key,
         )?;
+        let auth = start.elapsed();
+        self.load(&client, &ranges, limit, auth)?;
+        if self.snapshot && from <= to {
+            self.loaded = Some(match self.loaded {
+                Some((loaded_from, loaded_to)) => (loaded_from.min(from), loaded_to.max(to)),
+                None => (from, to),
+            });
+        }
+
+        Ok(true)
+    }
+
+    /// Fetches and decodes up to `limit` data entries of each SIM in each of the ranges, appends them to the
+    /// data, and records the timings, and a hint on clock skew for an empty result.
+    fn load(
+        &mut self,
+        client: &SoracomHarvestClient,
+        ranges: &[(i64, i64)],
+        limit: u32,
+        auth: Duration,
+    ) -> Result<(), HarvestDataError> {
+        self.warning = None;
+        let ranges = ranges
+            .iter()
+            .filter(|(from, to)| from <= to && (limit > 0 || self.paginate))
+            .collect::<Vec<_>>();
+        if ranges.is_empty() {
+            return Ok(());
+        }
+
+        let mut timings = Timings {
+            auth,
+            ..Default::default()
+        };
+        for &&(from, to) in &ranges {
+            let start = Instant::now();
+            let key = self.auth_cache_key;
+            let (imsis, mut data) = self
+                .fetch(client, from, to, limit)
+                .inspect_err(|e| forget_unauthorized(key, e))?;
+            timings.fetch += start.elapsed();
+
+            let start = Instant::now();
+            for d in &mut data {
+                d.content = decode(&self.decode_options, std::mem::take(&mut d.content));
+            }
+            timings.decode += start.elapsed();
+
+            self.imsis.extend(imsis);
+            self.data.extend(data);
+        }
+        self.timings = Some(timings);
+
+        if self.data.is_empty() && self.default_window {
+            self.warning = client
+                .server_time()
+                .ok()
+                .and_then(|server_time| clock_skew_hint(Utc::now(), server_time));
+        }
 
         Ok(())
     }
 
+    /// Returns up to `limit` data entries of each SIM in the range, and IMSI of each entry.
+    fn fetch(
+        &self,
+        client: &SoracomHarvestClient,
+        from: i64,
+        to: i64,
+        limit: u32,
+    ) -> Result<(Vec<String>, Vec<Data>), HarvestDataError> {
+        let imsis = match self.imsi.strip_suffix('%') {
+            Some(prefix) => client.list_imsis(prefix)?,
+            None => vec![self.imsi.clone()],
+        };
+        // union of the data entries of the SIMs, in descending order as with a single SIM
+        let mut entries = Vec::new();
+        for imsi in imsis {
+            let data = if self.paginate {
+                client
+                    .entries_iter(&imsi, from, to)
+                    .collect::<Result<Vec<_>, _>>()?
+            } else {
+                client.get_data_entries(&imsi, Some(from), Some(to), Some(limit))?
+            };
+            entries.extend(data.into_iter().map(|d| (imsi.clone(), d)));
+        }
+        entries.sort_by_key(|(_, d)| Reverse(d.time));
+
+        Ok(entries.into_iter().unzip())
+    }
+
+    /// Returns the index of the column in the table, or `None` if the column isn't declared.
+    pub fn column_index(&self, column: Column) -> Option<usize> {
+        self.columns.iter().position(|c| *c == column)
+    }
+
+    /// Deletes the data entry identified by the rowid from Soracom Harvest Data. The entry is kept in
+    /// the table until it is created again, so that rowids of the other entries stay the same.
+    pub fn delete(&mut self, rowid: i64) -> Result<(), HarvestDataError> {
+        let reader = self.get_reader();
+        let (imsi, time) = reader
+            .imsi_for_rowid(rowid)
+            .zip(reader.timestamp_for_rowid(rowid))
+            .ok_or(HarvestDataError::NoSuchRow(rowid))?;
+        let client = authenticate(
+            self.client.as_ref().ok_or(HarvestDataError::ReadOnly)?,
+            self.auth_cache_key,
+        )?;
+
+        client
+            .delete_data_entry(imsi, time)
+            .map_err(HarvestDataError::from)
+            .inspect_err(|e| forget_unauthorized(self.auth_cache_key, e))
+    }
+
+    /// Sends a data entry to Soracom Harvest Data via HTTP with `sender`, as the SIM of the device running
+    /// this. `values` are the columns of the row to insert, followed by `imsi`, where `value` is required
+    /// and `content_type` defaults to `application/json`. Harvest assigns `time` and `imsi`, so they
+    /// shouldn't be given. The entry isn't added to the table until it is created again, like
+    /// [`delete`](Self::delete). Returns the time of sending (unix time in milliseconds) as the rowid for the
+    /// entry, which is neither the `time` Harvest assigns nor unique, so it doesn't identify the entry.
+    pub fn insert(
+        &self,
+        sender: &Sender,
+        values: &[Option<String>],
+    ) -> Result<i64, HarvestDataError> {
+        if self.client.is_none() {
+            return Err(HarvestDataError::ReadOnly);
+        }
+        let value_of = |column: Option<usize>| column.and_then(|i| values.get(i)?.as_deref());
+        if value_of(self.column_index(Column::Time)).is_some()
+            || value_of(Some(self.columns.len())).is_some()
+        {
+            return Err(HarvestDataError::InvalidInsert(
+                "time and imsi are assigned by Harvest",
+            ));
+        }
+        let value = value_of(self.column_index(Column::Value))
+            .ok_or(HarvestDataError::InvalidInsert("value is required"))?;
+        let content_type =
+            value_of(self.column_index(Column::ContentType)).unwrap_or("application/json");
+
+        let sent_at = Utc::now().timestamp_millis();
+        sender.send_http_with_content_type(value, content_type)?;
+
+        Ok(sent_at)
+    }
+
+    /// Returns a warning found while loading the data, e.g. a hint on clock skew for an empty result.
+    pub fn warning(&self) -> Option<&str> {
+        self.warning.as_deref()
+    }
+
+    /// Returns the breakdown of the time spent to load the data the last time, if it succeeded.
+    pub fn timings(&self) -> Option<Timings> {
+        self.timings
+    }
+
     /// Get reader for the data.
     pub fn get_reader(&mut self) -> HarvestDataReader {
-        HarvestDataReader::new(self.data.clone()) // it should not be cloned, but for simplicity.
+        // it should not be cloned, but for simplicity.
+        HarvestDataReader::new(self.data.clone())
+            .with_imsis(self.imsis.clone())
+            .with_columns(self.columns.clone())
+    }
+}
+
+/// Returns the authenticated client from the cache if `cache_key` is given and cached, otherwise
+/// authenticates, and caches the result for the key. A client whose token expires within
+/// [`TOKEN_REFRESH_MARGIN_MINUTES`] is authenticated again, as well as `client` itself if it is the
+/// authenticated one without `cache_key`.
+pub(crate) fn authenticate(
+    client: &SoracomHarvestClient,
+    cache_key: Option<u64>,
+) -> Result<SoracomHarvestClient, SoracomHarvestClientError> {
+    let key = match cache_key {
+        Some(key) => key,
+        None if is_fresh(client) => return Ok(client.clone()),
+        None => return client.auth(),
+    };
+
+    let mut cache = AUTHENTICATED_CLIENTS
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap();
+    if let Some(authenticated) = cache.get(&key).filter(|c| is_fresh(c)) {
+        return Ok(authenticated.clone());
+    }
+    let authenticated = client.auth()?;
+    cache.insert(key, authenticated.clone());
+
+    Ok(authenticated)
+}
+
+/// Returns whether the client is authenticated, with the token valid for a while.
+fn is_fresh(client: &SoracomHarvestClient) -> bool {
+    client
+        .token_expires_at()
+        .is_some_and(|at| at - TimeDelta::minutes(TOKEN_REFRESH_MARGIN_MINUTES) > Utc::now())
+}
+
+/// Drops the authenticated client for `cache_key` from the cache if the API rejected it, e.g. as the token
+/// is revoked before it expires, so that the next request authenticates again.
+pub(crate) fn forget_unauthorized(cache_key: Option<u64>, err: &HarvestDataError) {
+    if let (Some(key), HarvestDataError::Client(SoracomHarvestClientError::Unauthorized)) =
+        (cache_key, err)
+    {
+        if let Some(cache) = AUTHENTICATED_CLIENTS.get() {
+            cache.lock().unwrap().remove(&key);
+        }
+    }
+}
+
+/// Reads data entries from the JSON file. With `flate2` feature, a gzip-compressed file is detected by its
+/// magic bytes and decompressed while reading.
+fn read_fixture(path: &str) -> Result<Vec<Data>, HarvestDataError> {
+    #[cfg_attr(not(feature = "flate2"), allow(unused_mut))]
+    let mut reader = BufReader::new(File::open(path)?);
+
+    #[cfg(feature = "flate2")]
+    if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        let decoder = flate2::bufread::GzDecoder::new(reader);
+        return Ok(serde_json::from_reader(decoder)?);
+    }
+
+    Ok(serde_json::from_reader(reader)?)
+}
+
+/// Returns a hint if the local time is off from the server time by more than the threshold, as the default
+/// search range computed from the local clock may miss all data in that case.
+fn clock_skew_hint(local_time: DateTime<Utc>, server_time: DateTime<Utc>) -> Option<String> {
+    let skew = local_time - server_time;
+    if skew.num_minutes().abs() <= CLOCK_SKEW_THRESHOLD_MINUTES {
+        return None;
+    }
+
+    Some(format!(
+        "Clock skew detected; local time is {}s off from server time, so data may be outside the default window",
+        skew.num_seconds()
+    ))
+}
+
+/// Base column of the table, which can be selected with `SELECT_COLUMNS`.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Column {
+    /// Timestamp of the data entry.
+    Time,
+
+    /// Content type of the data entry.
+    ContentType,
+
+    /// Content of the data entry.
+    Value,
+}
+
+impl Column {
+    /// All base columns, in the default order.
+    pub const ALL: [Column; 3] = [Column::Time, Column::ContentType, Column::Value];
+
+    /// Returns the column definition for the table declaration.
+    pub fn definition(&self) -> &'static str {
+        match self {
+            Column::Time => "time INTEGER",
+            Column::ContentType => "content_type TEXT",
+            Column::Value => "value TEXT",
+        }
+    }
+
+    /// Parses a comma-separated list of distinct columns, e.g. `time,value`.
+    pub fn parse_list(s: &str) -> Result<Vec<Column>, String> {
+        let columns = s
+            .split(',')
+            .map(|c| c.trim().parse())
+            .collect::<Result<Vec<Column>, _>>()?;
+        match columns
+            .iter()
+            .enumerate()
+            .find(|(i, c)| columns[..*i].contains(c))
+        {
+            Some((_, c)) => Err(format!("Duplicate column: {c:?}")),
+            None => Ok(columns),
+        }
+    }
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "time" => Ok(Column::Time),
+            "content_type" => Ok(Column::ContentType),
+            "value" => Ok(Column::Value),
+            _ => Err(format!("Unknown column: {s}")),
+        }
     }
 }
 
 /// Reader for given data.
 pub struct HarvestDataReader {
     data: Vec<Data>,
+    imsis: Vec<String>,
+    columns: Vec<Column>,
+    time_range: RangeInclusive<i64>,
     current_index: usize,
 }
 
 impl HarvestDataReader {
-    /// Returns a new reader for given data.
+    /// Returns a new reader for given data, with all base columns.
     pub fn new(data: Vec<Data>) -> Self {
         HarvestDataReader {
             data,
+            imsis: Vec::new(),
+            columns: Column::ALL.to_vec(),
+            time_range: i64::MIN..=i64::MAX,
             current_index: 0,
         }
     }
 
+    /// Sets IMSI of each data entry, for the `imsi` column.
+    pub fn with_imsis(mut self, imsis: Vec<String>) -> Self {
+        self.imsis = imsis;
+        self
+    }
+
+    /// Sets the base columns of the table, in the declared order, followed by the `imsi` column.
+    pub fn with_columns(mut self, columns: Vec<Column>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    /// Returns the index of the `imsi` column, which follows the base columns.
+    pub fn imsi_column(&self) -> usize {
+        self.columns.len()
+    }
+
     /// Get current index.
     pub fn get_index(&self) -> u32 {
         self.current_index as u32
     }
 
-    /// Increment index.
+    /// Returns the timestamp of the data entry identified by the rowid, which is the index of the entry.
+    /// Used to resolve a rowid to the original entry, e.g. to delete it with `delete_data_entry`.
+    pub fn timestamp_for_rowid(&self, rowid: i64) -> Option<i64> {
+        let index = usize::try_from(rowid).ok()?;
+        self.data.get(index).map(|d| d.time)
+    }
+
+    /// Returns IMSI of the SIM which sent the data entry identified by the rowid.
+    pub fn imsi_for_rowid(&self, rowid: i64) -> Option<&str> {
+        let index = usize::try_from(rowid).ok()?;
+        self.imsis.get(index).map(|s| s.as_str())
+    }
+
+    /// Limits the scan to the data with the time in the range, from the next [`rewind`](Self::rewind).
+    pub fn set_time_range(&mut self, time_range: RangeInclusive<i64>) {
+        self.time_range = time_range;
+    }
+
+    /// Moves back to the first data in the time range, to scan again.
+    pub fn rewind(&mut self) {
+        self.current_index = 0;
+        self.skip_out_of_range();
+    }
+
+    /// Moves to the next data in the time range.
     pub fn move_next(&mut self) {
         self.current_index += 1;
+        self.skip_out_of_range();
+    }
+
+    fn skip_out_of_range(&mut self) {
+        while self
+            .data
+            .get(self.current_index)
+            .is_some_and(|d| !self.time_range.contains(&d.time))
+        {
+            self.current_index += 1;
+        }
     }
 
     /// Returns if the current index has a data.
@@ -74,15 +576,389 @@ impl HarvestDataReader {
         self.data.get(self.current_index).is_some()
     }
 
-    /// Get value of the current index.
+    /// Get value of the `i`-th column of the current index.
     pub fn get_value(&self, i: usize) -> String {
         match self.data.get(self.current_index) {
             None => "".to_string(),
-            Some(d) => match i {
-                0 => d.time.to_string(),
-                1 => d.content_type.clone(),
-                _ => d.content.clone(),
+            Some(d) => match self.columns.get(i) {
+                Some(Column::Time) => d.time.to_string(),
+                Some(Column::ContentType) => d.content_type.clone(),
+                Some(Column::Value) => d.content.clone(),
+                None => self
+                    .imsis
+                    .get(self.current_index)
+                    .cloned()
+                    .unwrap_or_default(),
             },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::HarvestDataError,
+        harvest_data_client::{authenticate, Column, HarvestDataClient, HarvestDataReader},
+    };
+    use chrono::Utc;
+    use soracom_harvest_api_client::{
+        client::{Data, SoracomHarvestClient},
+        decoder::{DecodeOptions, Decoder},
+        error::SoracomHarvestClientError,
+    };
+    use soracom_harvest_client::sender::Sender;
+    use std::time::Duration;
+
+    #[test]
+    fn test_clock_skew_warning() {
+        let mut server = mockito::Server::new();
+        mock_auth(&mut server).create();
+        server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(mockito::Matcher::Any)
+            .with_body("[]")
+            .create();
+        server
+            .mock("HEAD", "/")
+            .with_header("date", "Wed, 21 Oct 2015 07:28:00 GMT")
+            .create();
+
+        let data_client = |default_window| {
+            let client = mock_client(&server);
+            let mut data_client = HarvestDataClient::builder()
+                .client(client)
+                .imsi("441200000050000".to_string())
+                .limit(100)
+                .default_window(default_window)
+                .build();
+            data_client.open().unwrap();
+            data_client.reload(i64::MIN, i64::MAX, None).unwrap();
+            data_client
+        };
+
+        assert!(data_client(true)
+            .warning()
+            .unwrap()
+            .starts_with("Clock skew detected"));
+        assert_eq!(data_client(false).warning(), None);
+    }
+
+    #[test]
+    fn test_timings() {
+        let mut server = mockito::Server::new();
+        mock_auth(&mut server).create();
+        server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(mockito::Matcher::Any)
+            .with_body(
+                r#"[{"time":1669024325202,"contentType":"application/json","content":"{\"payload\":\"aGVsbG8=\"}"}]"#,
+            )
+            .create();
+
+        let client = SoracomHarvestClient::builder()
+            .auth_key_id("keyId")
+            .auth_key_secret("secret")
+            .endpoint(server.url())
+            .decode_options(DecodeOptions {
+                decoders: vec![Decoder::Raw],
+                ..Default::default()
+            })
+            .build();
+        let mut data_client = HarvestDataClient::builder()
+            .client(client)
+            .imsi("441200000050000".to_string())
+            .limit(100)
+            .build();
+        data_client.open().unwrap();
+        assert_eq!(data_client.timings(), None);
+
+        data_client.reload(i64::MIN, i64::MAX, None).unwrap();
+
+        let timings = data_client.timings().unwrap();
+        assert!(timings.auth > Duration::ZERO);
+        assert!(timings.fetch > Duration::ZERO);
+        assert!(timings.to_string().starts_with("auth "));
+        // decoded in the decode pass
+        assert_eq!(
+            data_client.get_reader().get_value(2),
+            r#"{"value":"hello"}"#
+        );
+    }
+
+    #[test]
+    fn test_reload_snapshot() {
+        let mut server = mockito::Server::new();
+        mock_auth(&mut server).create();
+        let range = |from: &str, to: &str| {
+            mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("from".into(), from.into()),
+                mockito::Matcher::UrlEncoded("to".into(), to.into()),
+            ])
+        };
+        // the bounds of the first scan are pushed down
+        let narrowed = server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(range("1669024326000", "1669024328000"))
+            .with_body(
+                r#"[{"time":1669024327201,"contentType":"application/json","content":"{}"}]"#,
+            )
+            .expect(1)
+            .create();
+        // and a scan outside the loaded range loads only the part which isn't loaded yet
+        let widened = server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(range("1669024325000", "1669024325999"))
+            .with_body(
+                r#"[{"time":1669024325202,"contentType":"application/json","content":"{}"}]"#,
+            )
+            .expect(1)
+            .create();
+
+        let client = mock_client(&server);
+        let mut data_client = HarvestDataClient::builder()
+            .client(client)
+            .imsi("441200000050000".to_string())
+            .from(1669024325000)
+            .to(1669024328000)
+            .limit(100)
+            .build();
+        data_client.open().unwrap();
+
+        assert!(data_client.reload(1669024326000, i64::MAX, None).unwrap());
+        assert_eq!(
+            data_client.get_reader().timestamp_for_rowid(0),
+            Some(1669024327201)
+        );
+        // scans in the loaded range see the same data, whatever the limit
+        assert!(!data_client
+            .reload(1669024327000, i64::MAX, Some(1))
+            .unwrap());
+        assert!(!data_client.reload(1, 0, None).unwrap());
+        narrowed.assert();
+
+        assert!(data_client.reload(i64::MIN, i64::MAX, None).unwrap());
+        assert!(!data_client.reload(i64::MIN, i64::MAX, None).unwrap());
+        widened.assert();
+        // appended, so that the entries seen by the earlier scans keep their rowids
+        let reader = data_client.get_reader();
+        assert_eq!(reader.timestamp_for_rowid(0), Some(1669024327201));
+        assert_eq!(reader.timestamp_for_rowid(1), Some(1669024325202));
+    }
+
+    #[test]
+    fn test_authenticate_expired_or_unauthorized() {
+        let mut server = mockito::Server::new();
+        let client = mock_client(&server);
+
+        // a token expiring within the margin is authenticated again, instead of served from the cache
+        let auth = server
+            .mock("POST", "/v1/auth")
+            .with_body(r#"{"apiKey":"api-key","token":"token","tokenTimeoutSeconds":60}"#)
+            .expect(2)
+            .create();
+        let key = Some(0x1004_0001);
+        authenticate(&client, key).unwrap();
+        authenticate(&client, key).unwrap();
+        auth.assert();
+        auth.remove();
+
+        // a rejected client is dropped from the cache
+        let auth = mock_auth(&mut server).expect(2).create();
+        server
+            .mock("GET", "/v1/data/Subscriber/441200000050000")
+            .match_query(mockito::Matcher::Any)
+            .with_status(401)
+            .create();
+        let mut data_client = HarvestDataClient::builder()
+            .client(client)
+            .imsi("441200000050000".to_string())
+            .limit(100)
+            .auth_cache_key(0x1004_0002)
+            .build();
+        for _ in 0..2 {
+            data_client.open().unwrap();
+            assert!(matches!(
+                data_client.reload(i64::MIN, i64::MAX, None),
+                Err(HarvestDataError::Client(
+                    SoracomHarvestClientError::Unauthorized
+                ))
+            ));
+        }
+        auth.assert();
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut server = mockito::Server::new();
+        let send = server
+            .mock("POST", "/")
+            .match_header("content-type", "text/csv")
+            .match_body("20,60")
+            .create();
+        let sender = Sender::builder()
+            .http_endpoint(server.url())
+            .error_for_status(true)
+            .build();
+        let text = |s: &str| Some(s.to_string());
+
+        // a fixture isn't backed by Harvest
+        let fixture = HarvestDataClient::builder()
+            .fixture("fixture.json".to_string())
+            .build();
+        assert!(matches!(
+            fixture.insert(&sender, &[None, None, text("20,60"), None]),
+            Err(HarvestDataError::ReadOnly)
+        ));
+
+        let client = mock_client(&server);
+        let data_client = HarvestDataClient::builder()
+            .client(client)
+            .imsi("441200000050000".to_string())
+            .build();
+        for values in [
+            [text("1669024325202"), None, text("20,60"), None],
+            [None, None, text("20,60"), text("441200000050000")],
+            [None, text("text/csv"), None, None],
+        ] {
+            assert!(matches!(
+                data_client.insert(&sender, &values),
+                Err(HarvestDataError::InvalidInsert(_))
+            ));
+        }
+
+        let before = Utc::now().timestamp_millis();
+        let rowid = data_client
+            .insert(&sender, &[None, text("text/csv"), text("20,60"), None])
+            .unwrap();
+        assert!((before..=Utc::now().timestamp_millis()).contains(&rowid));
+        send.assert();
+        // rejected by Harvest
+        assert!(matches!(
+            data_client.insert(&sender, &[None, None, text("20,60"), None]),
+            Err(HarvestDataError::Send(_))
+        ));
+    }
+
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn test_read_gzip_fixture() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let json = r#"[{"time":1669024325202,"contentType":"application/json","content":"{}"}]"#;
+        let dir = tempfile::tempdir().unwrap();
+        let plain = dir.path().join("fixture.json");
+        let gzip = dir.path().join("fixture.json.gz");
+        std::fs::write(&plain, json).unwrap();
+        let mut encoder = GzEncoder::new(
+            std::fs::File::create(&gzip).unwrap(),
+            Compression::default(),
+        );
+        encoder.write_all(json.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        for path in [&plain, &gzip] {
+            let data = super::read_fixture(path.to_str().unwrap()).unwrap();
+            assert_eq!(data.len(), 1);
+            assert_eq!(data[0].time, 1669024325202);
+        }
+    }
+
+    #[test]
+    fn test_timestamp_for_rowid() {
+        let reader = HarvestDataReader::new(vec![
+            Data {
+                time: 1669024327201,
+                content_type: "application/json".to_string(),
+                content: r#"{"temperature":4096}"#.to_string(),
+            },
+            Data {
+                time: 1669024325202,
+                content_type: "application/json".to_string(),
+                content: r#"{"value":"hello"}"#.to_string(),
+            },
+        ]);
+
+        assert_eq!(reader.timestamp_for_rowid(0), Some(1669024327201));
+        assert_eq!(reader.timestamp_for_rowid(1), Some(1669024325202));
+        assert_eq!(reader.timestamp_for_rowid(2), None);
+        assert_eq!(reader.timestamp_for_rowid(-1), None);
+    }
+
+    #[test]
+    fn test_time_range() {
+        let mut reader = HarvestDataReader::new(
+            [1669024327201, 1669024326000, 1669024325202]
+                .into_iter()
+                .map(|time| Data {
+                    time,
+                    content_type: "application/json".to_string(),
+                    content: "{}".to_string(),
+                })
+                .collect(),
+        );
+
+        let scan = |reader: &mut HarvestDataReader| {
+            let mut rows = Vec::new();
+            reader.rewind();
+            while reader.has_value() {
+                rows.push((reader.get_index(), reader.get_value(0)));
+                reader.move_next();
+            }
+            rows
+        };
+        assert_eq!(scan(&mut reader).len(), 3);
+
+        // rowids stay the same, to delete the entries
+        reader.set_time_range(1669024325202..=1669024326000);
+        assert_eq!(
+            scan(&mut reader),
+            vec![
+                (1, "1669024326000".to_string()),
+                (2, "1669024325202".to_string())
+            ]
+        );
+        reader.set_time_range(1669024327202..=i64::MAX);
+        assert!(scan(&mut reader).is_empty());
+    }
+
+    #[test]
+    fn test_get_value_with_columns() {
+        let reader = HarvestDataReader::new(vec![Data {
+            time: 1669024325202,
+            content_type: "application/json".to_string(),
+            content: r#"{"value":"hello"}"#.to_string(),
+        }])
+        .with_imsis(vec!["441200000050000".to_string()])
+        .with_columns(vec![Column::Value, Column::Time]);
+
+        assert_eq!(reader.get_value(0), r#"{"value":"hello"}"#);
+        assert_eq!(reader.get_value(1), "1669024325202");
+        assert_eq!(reader.imsi_column(), 2);
+        assert_eq!(reader.get_value(2), "441200000050000");
+
+        assert_eq!(
+            Column::parse_list("time, VALUE"),
+            Ok(vec![Column::Time, Column::Value])
+        );
+        assert!(Column::parse_list("time,payload").is_err());
+        assert!(Column::parse_list("time,time").is_err());
+        assert!(Column::parse_list("").is_err());
+    }
+
+    /// Returns a mock of the authentication API, to be created.
+    fn mock_auth(server: &mut mockito::Server) -> mockito::Mock {
+        server
+            .mock("POST", "/v1/auth")
+            .with_body(r#"{"apiKey":"api-key","token":"token"}"#)
+    }
+
+    /// Returns a client with dummy credentials for the mock server.
+    fn mock_client(server: &mockito::Server) -> SoracomHarvestClient {
+        SoracomHarvestClient::builder()
+            .auth_key_id("keyId")
+            .auth_key_secret("secret")
+            .endpoint(server.url())
+            .build()
+    }
+}
@@ -23,13 +23,20 @@
 //!
 //! # SQLite3 virtual table arguments
 //!
-//! | Argument   | Description                                                               | Default             | Required |
-//! |------------|---------------------------------------------------------------------------|---------------------|:--------:|
-//! | `IMSI`     | Your IMSI                                                                 | None                |    x     |
-//! | `FROM`     | Start time for the data entries search range (unix time in milliseconds). | 1 days ago from now |          |
-//! | `TO`       | End time for the data entries search range (unix time in milliseconds).   | now                 |          |
-//! | `COVERAGE` | Your SIM's coverage (`global` or `japan`)                                 | `global`            |          |
-//! | `LIMIT`    | Maximum number of data entries to retrieve. Should be between 1 and 1000. | 100                 |          |
+//! | Argument         | Description                                                                        | Default                   | Required |
+//! |------------------|------------------------------------------------------------------------------------|---------------------------|:--------:|
+//! | `IMSI`           | Your IMSI, or a prefix followed by `%` to query all SIMs with the prefix.          | None                      |    x     |
+//! | `FROM`           | Start time for the data entries search range (unix ms, RFC3339 or e.g. `1d ago`).  | 1 days ago from now       |          |
+//! | `TO`             | End time for the data entries search range (unix ms, RFC3339 or e.g. `1h ago`).    | now                       |          |
+//! | `COVERAGE`       | Your SIM's coverage (`global` or `japan`)                                          | `global`                  |          |
+//! | `LIMIT`          | Maximum number of data entries to retrieve. Should be between 1 and 1000.          | 100                       |          |
+//! | `FIXTURE`        | Local JSON file of data entries to load instead of the API. For testing.           | None                      |          |
+//! | `REQUIRE_DATA`   | Return an error if no data entries are found (`true` or `false`).                  | `false`                   |          |
+//! | `DECODE`         | Comma-separated decoders to try on `{"payload": ...}` content, in order.           | `base64,raw`              |          |
+//! | `BINARY`         | Representation of undecodable binary payloads; `payload`, `hex` or `base64`.       | `payload`                 |          |
+//! | `UNWRAP_JSON`    | Keep a decoded JSON object or array as the content itself (`true` or `false`).     | `false`                   |          |
+//! | `SELECT_COLUMNS` | Comma-separated columns to declare; `time`, `content_type` or `value`.             | `time,content_type,value` |          |
+//! | `SNAPSHOT`       | Keep the data loaded by a query for later queries (`true`), or not (`false`).      | `true`                    |          |
 //!
 //! ## Example
 //!
@@ -42,9 +49,24 @@
 //!     LIMIT '...',
 //! );
 //! ```
+//!
+//! `FROM` and `TO` also accept an RFC3339 datetime, e.g. `FROM '2023-01-01T00:00:00Z'` or
+//! `TO '2023-01-01T09:00:00+09:00'`, or a time relative to now, as a number followed by `d`, `h`, `m` or
+//! `s`, optionally prefixed with `-` or followed by ` ago`, e.g. `FROM '1d ago'` (the default), `FROM '24h'`
+//! or `FROM '-30m'`.
+//!
+//! For testing/demo without credentials or network, `FIXTURE` loads data entries from a local JSON file
+//! instead of calling the API. `IMSI` is not required in that case. With `flate2` feature, the file may be
+//! gzip-compressed.
+//!
+//! ```sql
+//! CREATE VIRTUAL TABLE harvest_data USING shsqlite(FIXTURE '/path/to/entries.json');
+//! ```
 
 pub mod error;
 mod harvest_data_client;
 mod module; // SQLite extension entry point
 mod module_arguments_parser;
+mod send_function; // `shsqlite_send` scalar function
 mod sqlite3ext;
+mod tail_module; // `shsqlite_tail` table-valued function
@@ -2,34 +2,73 @@
 
 use crate::{
     error::error_to_sqlite3_string,
-    harvest_data_client::{HarvestDataClient, HarvestDataReader},
+    harvest_data_client::{Column, HarvestDataClient, HarvestDataReader},
     module_arguments_parser::collect_options_from_args,
+    send_function::{shsqlite_send, SHSQLITE_SEND_FUNCTION},
     sqlite3ext::{
         sqlite3, sqlite3_api_routines, sqlite3_context, sqlite3_index_info, sqlite3_int64,
-        sqlite3_module, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor, SQLITE_ERROR, SQLITE_OK,
-        SQLITE_OK_LOAD_PERMANENTLY,
+        sqlite3_module, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor, SQLITE_ERROR,
+        SQLITE_FLOAT, SQLITE_INDEX_CONSTRAINT_EQ, SQLITE_INDEX_CONSTRAINT_GE,
+        SQLITE_INDEX_CONSTRAINT_GT, SQLITE_INDEX_CONSTRAINT_LE, SQLITE_INDEX_CONSTRAINT_LT,
+        SQLITE_INTEGER, SQLITE_NOTICE, SQLITE_NULL, SQLITE_OK, SQLITE_OK_LOAD_PERMANENTLY,
+        SQLITE_READONLY, SQLITE_UTF8, SQLITE_WARNING,
     },
+    tail_module::SHSQLITE_TAIL_MODULE,
 };
 use serde::Deserialize;
-use soracom_harvest_api_client::client::SoracomHarvestClient;
+use soracom_harvest_api_client::{
+    client::SoracomHarvestClient,
+    decoder::{DecodeOptions, Decoder},
+    endpoint::Endpoint,
+};
+use soracom_harvest_client::sender::Sender;
 use std::{
-    ffi::{c_char, c_int, c_longlong, c_void, CString},
-    sync::{Arc, Mutex},
+    collections::{hash_map::DefaultHasher, HashMap},
+    env,
+    ffi::{c_char, c_int, c_longlong, c_void, CStr, CString},
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
 };
 
+/// Bits of `idxNum` for the bounds of the `time` column, and the `LIMIT` and `OFFSET` of the query, passed to
+/// `shsqlite_filter`.
+const LOWER_TIME_BOUND: c_int = 1;
+const UPPER_TIME_BOUND: c_int = 2;
+const EQUAL_TIME_BOUNDS: c_int = 4;
+const ROW_LIMIT: c_int = 8;
+const ROW_OFFSET: c_int = 16;
+
+/// Constraints for `LIMIT` and `OFFSET`, which aren't in the bindings. Passed since SQLite 3.38.0.
+const SQLITE_INDEX_CONSTRAINT_LIMIT: c_int = 73;
+const SQLITE_INDEX_CONSTRAINT_OFFSET: c_int = 74;
+
+/// Number of the leading `xCreate` arguments before the module arguments, i.e. module, database and table names.
+const MODULE_ARGUMENTS_OFFSET: c_int = 3;
+
 #[derive(Deserialize, Debug)]
 struct Config {
     auth_key_id: String,
     auth_key_secret: String,
 }
 
+/// Environment variable of the URL to send messages to instead of Harvest, e.g. a mock receiver for testing.
+const HARVEST_ENDPOINT_VARIABLE: &str = "LIBSHSQLITE_HARVEST_ENDPOINT";
+
+/// Timeout of sending a message, so that a stalled connection can't hang the query.
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Senders shared by tables and functions in the process, keyed by the value of `LIBSHSQLITE_HARVEST_ENDPOINT`,
+/// so that sends reuse the connections.
+static HARVEST_SENDERS: OnceLock<Mutex<HashMap<Option<String>, Sender>>> = OnceLock::new();
+
 #[no_mangle]
-static mut SQLITE3_API: *mut sqlite3_api_routines = std::ptr::null_mut();
+pub(crate) static mut SQLITE3_API: *mut sqlite3_api_routines = std::ptr::null_mut();
 
 #[repr(C)]
-struct Module {
-    base: sqlite3_module,
-    name: &'static [u8],
+pub(crate) struct Module {
+    pub base: sqlite3_module,
+    pub name: &'static [u8],
 }
 
 const SHSQLITE_MODULE: Module = Module {
@@ -47,7 +86,7 @@ const SHSQLITE_MODULE: Module = Module {
         xEof: Some(shsqlite_eof),
         xColumn: Some(shsqlite_column),
         xRowid: Some(shsqlite_rowid),
-        xUpdate: None,
+        xUpdate: Some(shsqlite_update),
         xBegin: None,
         xSync: None,
         xCommit: None,
@@ -80,23 +119,47 @@ unsafe extern "C" fn register_module(
     pz_err_msg: *mut *mut c_char,
     p_api: *mut sqlite3_api_routines,
 ) -> c_int {
-    let result = ((*p_api).create_module.unwrap())(
-        db,
-        SHSQLITE_MODULE.name.as_ptr() as *const c_char,
-        &SHSQLITE_MODULE as *const Module as *const sqlite3_module,
-        std::ptr::null_mut(),
-    );
-
-    match result {
-        SQLITE_OK => SQLITE_OK_LOAD_PERMANENTLY,
-        _ => {
+    for module in [&SHSQLITE_MODULE, &SHSQLITE_TAIL_MODULE] {
+        let result = ((*p_api).create_module.unwrap())(
+            db,
+            module.name.as_ptr() as *const c_char,
+            module as *const Module as *const sqlite3_module,
+            std::ptr::null_mut(),
+        );
+
+        if result != SQLITE_OK {
             let err = format!("Failed to create module, status: {}", result);
             if let Some(ptr) = error_to_sqlite3_string(SQLITE3_API, err) {
                 *pz_err_msg = ptr;
             }
-            SQLITE_ERROR
+            return SQLITE_ERROR;
+        }
+    }
+
+    // `shsqlite_send(value)`, `shsqlite_send(value, imsi)` or `shsqlite_send(value, imsi, coverage)`
+    for argc in 1..=3 {
+        let result = ((*p_api).create_function_v2.unwrap())(
+            db,
+            SHSQLITE_SEND_FUNCTION.as_ptr() as *const c_char,
+            argc,
+            SQLITE_UTF8 as c_int,
+            std::ptr::null_mut(),
+            Some(shsqlite_send),
+            None,
+            None,
+            None,
+        );
+
+        if result != SQLITE_OK {
+            let err = format!("Failed to create function, status: {}", result);
+            if let Some(ptr) = error_to_sqlite3_string(SQLITE3_API, err) {
+                *pz_err_msg = ptr;
+            }
+            return SQLITE_ERROR;
         }
     }
+
+    SQLITE_OK_LOAD_PERMANENTLY
 }
 
 #[no_mangle]
@@ -110,9 +173,10 @@ unsafe extern "C" fn sqlite3_shsqlite_init(
     let result = register_module(db, pz_err_msg, p_api);
     match result {
         SQLITE_OK => {
-            let result = ((*p_api).auto_extension.unwrap())(Some(std::mem::transmute(
+            let entry_point = std::mem::transmute::<*const (), unsafe extern "C" fn()>(
                 register_module as *const (),
-            )));
+            );
+            let result = ((*p_api).auto_extension.unwrap())(Some(entry_point));
             if result != SQLITE_OK {
                 return result;
             }
@@ -132,37 +196,67 @@ unsafe extern "C" fn shsqlite_create(
     pp_vtab: *mut *mut sqlite3_vtab,
     pz_err: *mut *mut c_char,
 ) -> c_int {
-    let config = match envy::prefixed("LIBSHSQLITE_").from_env::<Config>() {
-        Ok(c) => c,
-        Err(why) => panic!("{why}"),
+    // skip the module, database and table names
+    let options = match argc {
+        ..=MODULE_ARGUMENTS_OFFSET => collect_options_from_args(0, argv),
+        _ => collect_options_from_args(
+            argc - MODULE_ARGUMENTS_OFFSET,
+            argv.add(MODULE_ARGUMENTS_OFFSET as usize),
+        ),
     };
-
-    match collect_options_from_args(argc, argv) {
-        Ok((imsi, endpoint, from, to, limit)) => {
-            let client = SoracomHarvestClient::builder()
-                .auth_key_id(config.auth_key_id)
-                .auth_key_secret(config.auth_key_secret)
-                .endpoint(endpoint)
-                .build();
-
-            let mut harvest_data = HarvestDataClient::builder()
-                .client(client)
-                .imsi(imsi)
-                .from(from)
-                .to(to)
-                .limit(limit)
-                .build();
+    match options {
+        Ok(args) => {
+            let mut harvest_data = match args.fixture {
+                Some(fixture) => HarvestDataClient::builder()
+                    .fixture(fixture)
+                    .require_data(args.require_data)
+                    .columns(args.columns.clone())
+                    .build(),
+                None => {
+                    let (client, auth_cache_key) = match api_client(args.endpoint) {
+                        Ok(c) => c,
+                        Err(why) => {
+                            if let Some(ptr) = error_to_sqlite3_string(SQLITE3_API, why.to_string())
+                            {
+                                *pz_err = ptr;
+                            }
+                            return SQLITE_ERROR;
+                        }
+                    };
+
+                    HarvestDataClient::builder()
+                        .client(client)
+                        .imsi(args.imsi)
+                        .from(args.from)
+                        .to(args.to)
+                        .limit(args.limit)
+                        .require_data(args.require_data)
+                        .default_window(args.default_window)
+                        .auth_cache_key(auth_cache_key)
+                        .columns(args.columns.clone())
+                        .snapshot(args.snapshot)
+                        .decode_options(DecodeOptions {
+                            decoders: args.decoders,
+                            binary_format: args.binary_format,
+                            unwrap_json: args.unwrap_json,
+                            ..Default::default()
+                        })
+                        .build()
+                }
+            };
 
             match harvest_data.open() {
                 Ok(_) => {
+                    log_load(&harvest_data);
                     let result = declare_table(
                         db,
                         SQLITE3_API,
-                        vec![
-                            "time INTEGER".to_string(),
-                            "content_type TEXT".to_string(),
-                            "value TEXT".to_string(),
-                        ],
+                        args.columns
+                            .iter()
+                            .map(|c| c.definition())
+                            .chain(["imsi TEXT HIDDEN"])
+                            .map(String::from)
+                            .collect(),
                     );
                     let p_new = Box::new(VirtualTable {
                         base: sqlite3_vtab {
@@ -183,7 +277,22 @@ unsafe extern "C" fn shsqlite_create(
                 }
             }
         }
-        Err(_) => SQLITE_ERROR,
+        Err(err) => {
+            if let Some(ptr) = error_to_sqlite3_string(SQLITE3_API, err.to_string()) {
+                *pz_err = ptr;
+            }
+            SQLITE_ERROR
+        }
+    }
+}
+
+/// Writes the warning and the timings of loading the data of the table to the SQLite error log.
+unsafe fn log_load(harvest_data: &HarvestDataClient) {
+    if let Some(warning) = harvest_data.warning() {
+        write_log(SQLITE3_API, SQLITE_WARNING, warning);
+    }
+    if let Some(timings) = harvest_data.timings() {
+        write_log(SQLITE3_API, SQLITE_NOTICE, &format!("shsqlite: {timings}"));
     }
 }
 
@@ -199,11 +308,95 @@ unsafe extern "C" fn shsqlite_connect(
     shsqlite_create(db, p_aux, argc, argv, pp_vtab, pz_err)
 }
 
+/// Passes the lower and upper bounds of the `time` column to `shsqlite_filter`, in this order. `idxNum` is the
+/// bitmask of the given bounds, with [`EQUAL_TIME_BOUNDS`] if a single equality constraint gives both of
+/// them. The bounds are applied inclusively, so SQLite still checks the constraints.
+///
+/// `LIMIT` and `OFFSET` of the query follow, but only if there are no other constraints or `ORDER BY`, as
+/// rows filtered out or sorted by SQLite after the scan would make the limit wrong.
 #[no_mangle]
 unsafe extern "C" fn shsqlite_best_index(
-    _p_vtab: *mut sqlite3_vtab,
-    _arg1: *mut sqlite3_index_info,
+    p_vtab: *mut sqlite3_vtab,
+    p_info: *mut sqlite3_index_info,
 ) -> c_int {
+    let table = &*(p_vtab as *mut VirtualTable);
+    let info = &mut *p_info;
+    info.idxNum = 0;
+    info.estimatedCost = 1_000_000.0;
+
+    let time_column = match table.data.lock().unwrap().column_index(Column::Time) {
+        Some(i) => i as c_int,
+        None => return SQLITE_OK,
+    };
+    if info.nConstraint == 0 {
+        return SQLITE_OK;
+    }
+    let constraints = std::slice::from_raw_parts(info.aConstraint, info.nConstraint as usize);
+    let usages = std::slice::from_raw_parts_mut(info.aConstraintUsage, info.nConstraint as usize);
+
+    let (mut lower, mut upper) = (None, None);
+    for (i, constraint) in constraints.iter().enumerate() {
+        if constraint.iColumn != time_column || constraint.usable == 0 {
+            continue;
+        }
+        match constraint.op as c_int {
+            SQLITE_INDEX_CONSTRAINT_EQ if lower.is_none() && upper.is_none() => {
+                (lower, upper) = (Some(i), Some(i));
+            }
+            SQLITE_INDEX_CONSTRAINT_GT | SQLITE_INDEX_CONSTRAINT_GE => {
+                lower.get_or_insert(i);
+            }
+            SQLITE_INDEX_CONSTRAINT_LT | SQLITE_INDEX_CONSTRAINT_LE => {
+                upper.get_or_insert(i);
+            }
+            _ => {}
+        }
+    }
+
+    let (mut limit, mut offset) = (None, None);
+    if info.nOrderBy == 0 {
+        for (i, constraint) in constraints.iter().enumerate() {
+            match constraint.op as c_int {
+                SQLITE_INDEX_CONSTRAINT_LIMIT if constraint.usable != 0 => limit = Some(i),
+                SQLITE_INDEX_CONSTRAINT_OFFSET if constraint.usable != 0 => offset = Some(i),
+                _ => {
+                    (limit, offset) = (None, None);
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut argv_index = 0;
+    if let Some(i) = lower {
+        argv_index += 1;
+        usages[i].argvIndex = argv_index;
+        info.idxNum |= LOWER_TIME_BOUND;
+        info.estimatedCost /= 10.0;
+    }
+    if let Some(i) = upper {
+        if lower == upper {
+            info.idxNum |= EQUAL_TIME_BOUNDS;
+        } else {
+            argv_index += 1;
+            usages[i].argvIndex = argv_index;
+        }
+        info.idxNum |= UPPER_TIME_BOUND;
+        info.estimatedCost /= 10.0;
+    }
+    if let Some(i) = limit {
+        argv_index += 1;
+        usages[i].argvIndex = argv_index;
+        info.idxNum |= ROW_LIMIT;
+        info.estimatedCost /= 10.0;
+
+        if let Some(i) = offset {
+            argv_index += 1;
+            usages[i].argvIndex = argv_index;
+            info.idxNum |= ROW_OFFSET;
+        }
+    }
+
     SQLITE_OK
 }
 
@@ -253,15 +446,90 @@ unsafe extern "C" fn shsqlite_close(p_cursor: *mut sqlite3_vtab_cursor) -> c_int
 
 #[no_mangle]
 unsafe extern "C" fn shsqlite_filter(
-    _arg1: *mut sqlite3_vtab_cursor,
-    _idx_num: c_int,
+    p_cursor: *mut sqlite3_vtab_cursor,
+    idx_num: c_int,
     _idx_str: *const c_char,
-    _argc: c_int,
-    _argv: *mut *mut sqlite3_value,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
 ) -> c_int {
+    let cursor = &mut *(p_cursor as *mut VirtualCursor);
+
+    let values = match argc {
+        0 => &[][..],
+        _ => std::slice::from_raw_parts(argv, argc as usize),
+    };
+    let mut values = values.iter();
+    let mut lower = None;
+    if idx_num & LOWER_TIME_BOUND != 0 {
+        lower = values.next().and_then(|v| time_bound(*v, f64::floor));
+    }
+    let mut upper = None;
+    if idx_num & EQUAL_TIME_BOUNDS != 0 {
+        upper = lower;
+    } else if idx_num & UPPER_TIME_BOUND != 0 {
+        upper = values.next().and_then(|v| time_bound(*v, f64::ceil));
+    }
+    let (from, to) = (lower.unwrap_or(i64::MIN), upper.unwrap_or(i64::MAX));
+    // rows to skip with OFFSET are scanned too, and a negative LIMIT means no limit
+    let mut limit = None;
+    if idx_num & ROW_LIMIT != 0 {
+        limit = values
+            .next()
+            .map(|v| ((*SQLITE3_API).value_int64.unwrap())(*v));
+    }
+    if idx_num & ROW_OFFSET != 0 {
+        let offset = values
+            .next()
+            .map_or(0, |v| ((*SQLITE3_API).value_int64.unwrap())(*v));
+        limit = limit.map(|limit| limit.saturating_add(offset.max(0)));
+    }
+    let limit = limit
+        .filter(|limit| *limit >= 0)
+        .map(|limit| u32::try_from(limit).unwrap_or(u32::MAX));
+
+    // The data of a snapshot is loaded for the first scan in the bounds, and kept for later scans in the
+    // loaded range, so that they see the same data. Otherwise load the data in the bounds, and up to the
+    // limit, for each scan.
+    let table = &mut *(cursor.base.pVtab as *mut VirtualTable);
+    let data = Arc::clone(&table.data);
+    let mut data = data.lock().unwrap();
+    let lock = Arc::clone(&cursor.reader);
+    let mut reader = lock.lock().unwrap();
+    match data.reload(from, to, limit) {
+        Ok(true) => {
+            log_load(&data);
+            *reader = data.get_reader();
+        }
+        Ok(false) => {}
+        Err(err) => {
+            if let Some(ptr) = error_to_sqlite3_string(SQLITE3_API, err) {
+                table.base.zErrMsg = ptr;
+            }
+            return SQLITE_ERROR;
+        }
+    }
+
+    // A cursor may be scanned multiple times e.g. as the inner loop of a join, so start over from the first
+    // data in the bounds.
+    reader.set_time_range(from..=to);
+    reader.rewind();
+
     SQLITE_OK
 }
 
+/// Returns the value of a constraint on the `time` column as an integer, rounded with `round` if it is real,
+/// or `None` if it isn't a number.
+unsafe fn time_bound(value: *mut sqlite3_value, round: fn(f64) -> f64) -> Option<i64> {
+    match ((*SQLITE3_API).value_type.unwrap())(value) {
+        SQLITE_INTEGER => Some(((*SQLITE3_API).value_int64.unwrap())(value)),
+        SQLITE_FLOAT => {
+            let value = round(((*SQLITE3_API).value_double.unwrap())(value));
+            value.is_finite().then_some(value as i64)
+        }
+        _ => None,
+    }
+}
+
 #[no_mangle]
 unsafe extern "C" fn shsqlite_next(p_cursor: *mut sqlite3_vtab_cursor) -> c_int {
     let cursor = &mut *(p_cursor as *mut VirtualCursor);
@@ -296,7 +564,12 @@ unsafe extern "C" fn shsqlite_column(
     let lock = Arc::clone(&cursor.reader);
     let reader = lock.lock().unwrap();
 
-    yield_cell_value(p_context, SQLITE3_API, reader.get_value(column as usize));
+    let value = reader.get_value(column as usize);
+    match column as usize {
+        // IMSI is digits, but text as declared, so that leading zeros and its length are kept
+        i if i == reader.imsi_column() => yield_text(p_context, SQLITE3_API, value),
+        _ => yield_cell_value(p_context, SQLITE3_API, value),
+    }
 
     SQLITE_OK
 }
@@ -315,7 +588,100 @@ unsafe extern "C" fn shsqlite_rowid(
     SQLITE_OK
 }
 
-unsafe fn declare_table(
+#[no_mangle]
+unsafe extern "C" fn shsqlite_update(
+    p_vtab: *mut sqlite3_vtab,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
+    p_rowid: *mut sqlite3_int64,
+) -> c_int {
+    let table = &mut *(p_vtab as *mut VirtualTable);
+    let args = std::slice::from_raw_parts(argv, argc as usize);
+    let data = Arc::clone(&table.data);
+    let mut lock = data.lock().unwrap();
+
+    let result = match args {
+        // DELETE is called with a single argument: the rowid to delete
+        [rowid] => lock.delete(((*SQLITE3_API).value_int64.unwrap())(*rowid)),
+        // INSERT is called with NULL, the new rowid and the columns, and UPDATE with the old rowid instead
+        [old_rowid, _, columns @ ..]
+            if ((*SQLITE3_API).value_type.unwrap())(*old_rowid) == SQLITE_NULL =>
+        {
+            let values = columns.iter().map(|v| value_text(*v)).collect::<Vec<_>>();
+            lock.insert(&harvest_sender(), &values)
+                .map(|rowid| *p_rowid = rowid)
+        }
+        _ => return SQLITE_READONLY,
+    };
+
+    match result {
+        Ok(_) => SQLITE_OK,
+        Err(err) => {
+            if let Some(ptr) = error_to_sqlite3_string(SQLITE3_API, err) {
+                table.base.zErrMsg = ptr;
+            }
+            SQLITE_ERROR
+        }
+    }
+}
+
+/// Returns a client for the endpoint with the credentials from the environment variables, and the key to
+/// share its authentication among tables with the same credentials and endpoint.
+pub(crate) fn api_client(endpoint: Endpoint) -> Result<(SoracomHarvestClient, u64), envy::Error> {
+    let config = envy::prefixed("LIBSHSQLITE_").from_env::<Config>()?;
+
+    let harvest_endpoint = env::var(HARVEST_ENDPOINT_VARIABLE).ok();
+
+    let mut hasher = DefaultHasher::new();
+    (
+        &config.auth_key_id,
+        &config.auth_key_secret,
+        endpoint.as_str(),
+        &harvest_endpoint,
+    )
+        .hash(&mut hasher);
+    let auth_cache_key = hasher.finish();
+
+    let client = SoracomHarvestClient::builder()
+        .auth_key_id(config.auth_key_id)
+        .auth_key_secret(config.auth_key_secret)
+        .endpoint(endpoint)
+        // keep the content as is, to decode in `HarvestDataClient::open`
+        .decode_options(DecodeOptions {
+            decoders: vec![Decoder::Raw],
+            ..Default::default()
+        })
+        .sender(harvest_sender())
+        .build();
+
+    Ok((client, auth_cache_key))
+}
+
+/// Returns a sender to Harvest, or the URL of `LIBSHSQLITE_HARVEST_ENDPOINT` if set, which treats a non-2xx
+/// response as an error. The sender is shared by the calls with the same URL.
+pub(crate) fn harvest_sender() -> Sender {
+    let url = env::var(HARVEST_ENDPOINT_VARIABLE).ok();
+
+    HARVEST_SENDERS
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap()
+        .entry(url)
+        .or_insert_with_key(|url| match url {
+            Some(url) => Sender::builder()
+                .http_endpoint(url)
+                .error_for_status(true)
+                .timeout(SEND_TIMEOUT)
+                .build(),
+            None => Sender::builder()
+                .error_for_status(true)
+                .timeout(SEND_TIMEOUT)
+                .build(),
+        })
+        .clone()
+}
+
+pub(crate) unsafe fn declare_table(
     db: *mut sqlite3,
     api: *mut sqlite3_api_routines,
     columns: Vec<String>,
@@ -331,22 +697,52 @@ fn create_declare_table_statement(columns: Vec<String>) -> CString {
     .unwrap()
 }
 
-unsafe fn yield_cell_value(
+/// Returns the text of the value, or `None` if it is `NULL`.
+pub(crate) unsafe fn value_text(value: *mut sqlite3_value) -> Option<String> {
+    if ((*SQLITE3_API).value_type.unwrap())(value) == SQLITE_NULL {
+        return None;
+    }
+    let text = ((*SQLITE3_API).value_text.unwrap())(value);
+    if text.is_null() {
+        return None;
+    }
+
+    Some(
+        CStr::from_ptr(text as *const c_char)
+            .to_string_lossy()
+            .to_string(),
+    )
+}
+
+pub(crate) unsafe fn yield_cell_value(
     p_context: *mut sqlite3_context,
     api: *mut sqlite3_api_routines,
     value: String,
 ) {
     match value.parse::<i64>() {
         Ok(i) => ((*api).result_int64.unwrap())(p_context, i),
-        Err(_) => {
-            let (len, raw) = to_raw_string(value);
-            ((*api).result_text.unwrap())(p_context, raw, len as c_int, Some(destructor))
-        }
+        Err(_) => yield_text(p_context, api, value),
+    }
+}
+
+pub(crate) unsafe fn yield_text(
+    p_context: *mut sqlite3_context,
+    api: *mut sqlite3_api_routines,
+    value: String,
+) {
+    let (len, raw) = to_raw_string(value);
+    ((*api).result_text.unwrap())(p_context, raw, len as c_int, Some(destructor))
+}
+
+/// Writes the message to the SQLite error log, see <https://www.sqlite.org/errlog.html>.
+unsafe fn write_log(api: *mut sqlite3_api_routines, code: c_int, message: &str) {
+    if let (Some(log), Ok(message)) = ((*api).log, CString::new(message)) {
+        log(code, c"%s".as_ptr(), message.as_ptr());
     }
 }
 
 fn to_raw_string(s: String) -> (usize, *mut c_char) {
-    let cstr = CString::new(s.as_str().as_bytes()).unwrap();
+    let cstr = CString::new(s.as_bytes()).unwrap();
     let len = cstr.as_bytes().len();
     let raw = cstr.into_raw();
 
@@ -1,48 +1,102 @@
 //! SQLite option parser
 
-use crate::error::{
-    ArgumentError,
-    ArgumentError::{InvalidFrom, InvalidLimit, InvalidTo, NoImsi, UnknownOption},
+use crate::{
+    error::{
+        ArgumentError,
+        ArgumentError::{
+            InvalidBinary, InvalidDecode, InvalidFrom, InvalidLimit, InvalidRequireData,
+            InvalidSelectColumns, InvalidSnapshot, InvalidTo, InvalidUnwrapJson, NoImsi,
+            UnknownOption,
+        },
+    },
+    harvest_data_client::Column,
 };
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
 use regex::Regex;
-use soracom_harvest_api_client::endpoint::Endpoint;
+use soracom_harvest_api_client::{
+    decoder::{BinaryFormat, Decoder},
+    endpoint::Endpoint,
+};
 use std::ffi::{c_char, c_int, CStr};
 
 enum ModuleArgument {
-    Imsi(String),       // required
-    Coverage(Endpoint), // optional
-    From(i64),          // optional
-    To(i64),            // optional
-    Limit(u32),         // optional, and should be between 1 to 1000
+    Imsi(String),               // required, unless fixture is provided
+    Coverage(Endpoint),         // optional
+    From(i64),                  // optional
+    To(i64),                    // optional
+    Limit(u32),                 // optional, and should be between 1 to 1000
+    Fixture(String),            // optional, for testing/demo
+    RequireData(bool),          // optional
+    Decode(Vec<Decoder>),       // optional
+    Binary(BinaryFormat),       // optional
+    UnwrapJson(bool),           // optional
+    Snapshot(bool),             // optional
+    SelectColumns(Vec<Column>), // optional
+}
+
+/// Options collected from the module arguments.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ModuleArguments {
+    pub imsi: String,
+    pub endpoint: Endpoint,
+    pub from: i64,
+    pub to: i64,
+    pub limit: u32,
+    pub fixture: Option<String>,
+    pub require_data: bool,
+    pub decoders: Vec<Decoder>,
+    pub binary_format: BinaryFormat,
+    pub unwrap_json: bool,
+    pub columns: Vec<Column>,
+    /// Whether the data loaded by a scan is kept for later scans in the loaded range, rather than loaded for
+    /// each scan.
+    pub snapshot: bool,
+    /// Whether `from` or `to` is omitted, and defaulted based on the local clock.
+    pub default_window: bool,
 }
 
+/// Collects options from the module arguments, i.e. `argv` of `xCreate` without the module, database and
+/// table names. Any unknown or invalid option is an error, so that a typo won't be ignored silently.
 pub(crate) unsafe fn collect_options_from_args(
     argc: c_int,
     argv: *const *const c_char,
-) -> Result<(String, Endpoint, i64, i64, u32), ArgumentError> {
+) -> Result<ModuleArguments, ArgumentError> {
     let mut imsi = "".to_string();
     let mut endpoint = Endpoint::default();
     let mut from = 0i64;
     let mut to = 0i64;
     let mut limit = 100u32;
+    let mut fixture = None;
+    let mut require_data = false;
+    let mut decoders = Decoder::default_order();
+    let mut binary_format = BinaryFormat::default();
+    let mut unwrap_json = false;
+    let mut snapshot = true;
+    let mut columns = Column::ALL.to_vec();
 
     for arg in collect_strings_from_raw(argc as usize, argv) {
-        if let Ok(option) = parse_option(arg.as_str()) {
-            match option {
-                ModuleArgument::Imsi(s) => imsi = s.to_string(),
-                ModuleArgument::Coverage(e) => endpoint = e,
-                ModuleArgument::From(i) => from = i,
-                ModuleArgument::To(i) => to = i,
-                ModuleArgument::Limit(u) => limit = u,
-            }
+        match parse_option(arg.as_str())? {
+            ModuleArgument::Imsi(s) => imsi = s.to_string(),
+            ModuleArgument::Coverage(e) => endpoint = e,
+            ModuleArgument::From(i) => from = i,
+            ModuleArgument::To(i) => to = i,
+            ModuleArgument::Limit(u) => limit = u,
+            ModuleArgument::Fixture(s) => fixture = Some(s),
+            ModuleArgument::RequireData(b) => require_data = b,
+            ModuleArgument::Decode(d) => decoders = d,
+            ModuleArgument::Binary(b) => binary_format = b,
+            ModuleArgument::UnwrapJson(b) => unwrap_json = b,
+            ModuleArgument::Snapshot(b) => snapshot = b,
+            ModuleArgument::SelectColumns(c) => columns = c,
         }
     }
 
-    if imsi.is_empty() {
+    if imsi.is_empty() && fixture.is_none() {
         return Err(NoImsi);
     }
 
+    let default_window = from == 0 || to == 0;
+
     if from == 0 {
         from = (Utc::now() - Duration::days(1)).timestamp_millis();
     }
@@ -51,11 +105,25 @@ pub(crate) unsafe fn collect_options_from_args(
         to = Utc::now().timestamp_millis();
     }
 
-    if limit < 1 && limit > 1000 {
+    if !(1..=1000).contains(&limit) {
         return Err(InvalidLimit);
     }
 
-    Ok((imsi, endpoint, from, to, limit))
+    Ok(ModuleArguments {
+        imsi,
+        endpoint,
+        from,
+        to,
+        limit,
+        fixture,
+        require_data,
+        decoders,
+        binary_format,
+        unwrap_json,
+        columns,
+        snapshot,
+        default_window,
+    })
 }
 
 unsafe fn collect_strings_from_raw(n: usize, args: *const *const c_char) -> Vec<String> {
@@ -77,45 +145,117 @@ unsafe fn read_string_from_raw(raw: *const c_char) -> String {
 }
 
 fn parse_option(input: &str) -> Result<ModuleArgument, ArgumentError> {
-    if let Ok(re) = Regex::new(r#"(?i)^(IMSI|COVERAGE|FROM|TO|LIMIT)\s+['"]([^'"]+)['"]$"#) {
+    if let Ok(re) = Regex::new(
+        r#"(?i)^(IMSI|COVERAGE|FROM|TO|LIMIT|FIXTURE|REQUIRE_DATA|DECODE|BINARY|UNWRAP_JSON|SELECT_COLUMNS|SNAPSHOT)\s+['"]([^'"]+)['"]$"#,
+    ) {
         if let Some(cap) = re.captures(input) {
             return match cap[1].to_lowercase().as_str() {
                 "imsi" => Ok(ModuleArgument::Imsi(cap[2].into())),
                 "coverage" => Ok(ModuleArgument::Coverage(cap[2].into())),
-                "from" => match cap[2].parse::<i64>() {
-                    Ok(i) => Ok(ModuleArgument::From(i)),
-                    Err(_) => Err(InvalidFrom),
+                "from" => match parse_time(&cap[2]) {
+                    Some(i) => Ok(ModuleArgument::From(i)),
+                    None => Err(InvalidFrom),
                 },
-                "to" => match cap[2].parse::<i64>() {
-                    Ok(i) => Ok(ModuleArgument::To(i)),
-                    Err(_) => Err(InvalidTo),
+                "to" => match parse_time(&cap[2]) {
+                    Some(i) => Ok(ModuleArgument::To(i)),
+                    None => Err(InvalidTo),
                 },
                 "limit" => match cap[2].parse::<u32>() {
                     Ok(u) => Ok(ModuleArgument::Limit(u)),
                     Err(_) => Err(InvalidLimit),
                 },
-                _ => Err(UnknownOption),
+                "fixture" => Ok(ModuleArgument::Fixture(cap[2].into())),
+                "require_data" => match cap[2].to_lowercase().parse::<bool>() {
+                    Ok(b) => Ok(ModuleArgument::RequireData(b)),
+                    Err(_) => Err(InvalidRequireData),
+                },
+                "decode" => match Decoder::parse_list(&cap[2]) {
+                    Ok(d) => Ok(ModuleArgument::Decode(d)),
+                    Err(_) => Err(InvalidDecode),
+                },
+                "binary" => match cap[2].parse::<BinaryFormat>() {
+                    Ok(b) => Ok(ModuleArgument::Binary(b)),
+                    Err(_) => Err(InvalidBinary),
+                },
+                "unwrap_json" => match cap[2].to_lowercase().parse::<bool>() {
+                    Ok(b) => Ok(ModuleArgument::UnwrapJson(b)),
+                    Err(_) => Err(InvalidUnwrapJson),
+                },
+                "snapshot" => match cap[2].to_lowercase().parse::<bool>() {
+                    Ok(b) => Ok(ModuleArgument::Snapshot(b)),
+                    Err(_) => Err(InvalidSnapshot),
+                },
+                "select_columns" => match Column::parse_list(&cap[2]) {
+                    Ok(c) => Ok(ModuleArgument::SelectColumns(c)),
+                    Err(_) => Err(InvalidSelectColumns),
+                },
+                _ => Err(UnknownOption(input.to_string())),
             };
         }
     }
 
-    Err(UnknownOption)
+    Err(UnknownOption(input.to_string()))
+}
+
+/// Parses the time as unix time in milliseconds, as an RFC3339 datetime e.g. `2023-01-01T00:00:00Z`, or as
+/// a time relative to now e.g. `1d ago`. See [`parse_relative_time`] for the relative expressions.
+fn parse_time(input: &str) -> Option<i64> {
+    input
+        .parse::<i64>()
+        .ok()
+        .or_else(|| {
+            DateTime::parse_from_rfc3339(input)
+                .ok()
+                .map(|t| t.timestamp_millis())
+        })
+        .or_else(|| parse_relative_time(input, Utc::now()))
+}
+
+/// Parses a time before `now` in unix time in milliseconds, given as a number followed by a unit `d`, `h`,
+/// `m` or `s`, optionally prefixed with `-` or followed by ` ago`, e.g. `1d ago`, `24h` or `-30m`.
+fn parse_relative_time(input: &str, now: DateTime<Utc>) -> Option<i64> {
+    let re = Regex::new(r"(?i)^(-)?(\d+)\s*([dhms])(\s+ago)?$").ok()?;
+    let cap = re.captures(input.trim())?;
+    if cap.get(1).is_some() && cap.get(4).is_some() {
+        return None;
+    }
+
+    let n = cap[2].parse::<i64>().ok()?;
+    let seconds = match cap[3].to_lowercase().as_str() {
+        "d" => n.checked_mul(24 * 60 * 60)?,
+        "h" => n.checked_mul(60 * 60)?,
+        "m" => n.checked_mul(60)?,
+        _ => n,
+    };
+
+    now.checked_sub_signed(Duration::try_seconds(seconds)?)
+        .map(|t| t.timestamp_millis())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::module_arguments_parser::collect_options_from_args;
-    use soracom_harvest_api_client::endpoint::Endpoint;
-    use std::{error::Error, ffi::CStr};
+    use crate::{
+        error::ArgumentError,
+        harvest_data_client::Column,
+        module_arguments_parser::{
+            collect_options_from_args, parse_relative_time, ModuleArguments,
+        },
+    };
+    use chrono::{TimeZone, Utc};
+    use soracom_harvest_api_client::{
+        decoder::{BinaryFormat, Decoder},
+        endpoint::Endpoint,
+    };
+    use std::error::Error;
 
     #[test]
     fn test_collect_options_from_args() -> Result<(), Box<dyn Error>> {
         let out = vec![
-            CStr::from_bytes_with_nul(b"IMSI '441200000050000'\0").unwrap(),
-            CStr::from_bytes_with_nul(b"COVERAGE 'japan'\0").unwrap(),
-            CStr::from_bytes_with_nul(b"FROM '1668003111681'\0").unwrap(),
-            CStr::from_bytes_with_nul(b"TO '1668604289406'\0").unwrap(),
-            CStr::from_bytes_with_nul(b"LIMIT '1000'\0").unwrap(),
+            c"IMSI '441200000050000'",
+            c"COVERAGE 'japan'",
+            c"FROM '1668003111681'",
+            c"TO '1668604289406'",
+            c"LIMIT '1000'",
         ]
         .into_iter()
         .map(|s| s.as_ptr())
@@ -123,13 +263,21 @@ mod tests {
 
         unsafe {
             assert_eq!(
-                (
-                    "441200000050000".to_string(),
-                    Endpoint::Japan,
-                    1668003111681,
-                    1668604289406,
-                    1000
-                ),
+                ModuleArguments {
+                    imsi: "441200000050000".to_string(),
+                    endpoint: Endpoint::Japan,
+                    from: 1668003111681,
+                    to: 1668604289406,
+                    limit: 1000,
+                    fixture: None,
+                    require_data: false,
+                    decoders: Decoder::default_order(),
+                    binary_format: BinaryFormat::Payload,
+                    unwrap_json: false,
+                    columns: Column::ALL.to_vec(),
+                    snapshot: true,
+                    default_window: false,
+                },
                 collect_options_from_args(5, out.as_ptr())?
             )
         }
@@ -140,9 +288,9 @@ mod tests {
     #[test]
     fn test_collect_options_from_args_with_optional() {
         let out = vec![
-            CStr::from_bytes_with_nul(b"IMSI '441200000050000'\0").unwrap(),
-            CStr::from_bytes_with_nul(b"FROM '1668003111681'\0").unwrap(),
-            CStr::from_bytes_with_nul(b"TO '1668604289406'\0").unwrap(),
+            c"IMSI '441200000050000'",
+            c"FROM '1668003111681'",
+            c"TO '1668604289406'",
         ]
         .into_iter()
         .map(|s| s.as_ptr())
@@ -150,15 +298,219 @@ mod tests {
 
         unsafe {
             assert_eq!(
-                (
-                    "441200000050000".to_string(),
-                    Endpoint::Global,
-                    1668003111681,
-                    1668604289406,
-                    100
-                ),
+                ModuleArguments {
+                    imsi: "441200000050000".to_string(),
+                    endpoint: Endpoint::Global,
+                    from: 1668003111681,
+                    to: 1668604289406,
+                    limit: 100,
+                    fixture: None,
+                    require_data: false,
+                    decoders: Decoder::default_order(),
+                    binary_format: BinaryFormat::Payload,
+                    unwrap_json: false,
+                    columns: Column::ALL.to_vec(),
+                    snapshot: true,
+                    default_window: false,
+                },
                 collect_options_from_args(3, out.as_ptr()).unwrap()
             )
         }
     }
+
+    #[test]
+    fn test_collect_options_from_args_with_limit() {
+        for (limit, expected) in [
+            (c"LIMIT '0'", None),
+            (c"LIMIT '1'", Some(1)),
+            (c"LIMIT '1000'", Some(1000)),
+            (c"LIMIT '1001'", None),
+        ] {
+            let out = [c"IMSI '441200000050000'", limit].map(|s| s.as_ptr());
+
+            unsafe {
+                match (collect_options_from_args(2, out.as_ptr()), expected) {
+                    (Ok(args), Some(expected)) => assert_eq!(args.limit, expected),
+                    (Err(ArgumentError::InvalidLimit), None) => {}
+                    (result, _) => panic!("unexpected result for {limit:?}: {result:?}"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_collect_options_from_args_with_unknown_option() {
+        let out = [c"IMSI '441200000050000'", c"COVRAGE 'japan'"].map(|s| s.as_ptr());
+
+        unsafe {
+            match collect_options_from_args(2, out.as_ptr()) {
+                Err(ArgumentError::UnknownOption(option)) => assert_eq!(option, "COVRAGE 'japan'"),
+                result => panic!("unexpected result: {result:?}"),
+            }
+            assert!(matches!(
+                collect_options_from_args(1, [c"FROM 'yesterday'"].map(|s| s.as_ptr()).as_ptr()),
+                Err(ArgumentError::InvalidFrom)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_collect_options_from_args_with_rfc3339() {
+        let out = [
+            c"IMSI '441200000050000'",
+            c"FROM '2023-01-01T00:00:00Z'",
+            c"TO '2023-01-01T09:00:00.5+09:00'",
+        ]
+        .map(|s| s.as_ptr());
+
+        unsafe {
+            let args = collect_options_from_args(3, out.as_ptr()).unwrap();
+            assert_eq!(args.from, 1672531200000);
+            assert_eq!(args.to, 1672531200500);
+
+            let out = [c"IMSI '441200000050000'", c"FROM '1672531200000'"].map(|s| s.as_ptr());
+            assert_eq!(
+                collect_options_from_args(2, out.as_ptr()).unwrap().from,
+                1672531200000
+            );
+
+            let out = [c"IMSI '441200000050000'", c"TO '2023-13-01'"].map(|s| s.as_ptr());
+            assert!(matches!(
+                collect_options_from_args(2, out.as_ptr()),
+                Err(ArgumentError::InvalidTo)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_relative_time() {
+        let now = Utc.timestamp_millis_opt(1672531200000).unwrap();
+        for (input, expected) in [
+            ("1d ago", Some(1672531200000 - 24 * 60 * 60 * 1000)),
+            ("24h ago", Some(1672531200000 - 24 * 60 * 60 * 1000)),
+            ("-2d", Some(1672531200000 - 2 * 24 * 60 * 60 * 1000)),
+            ("30m", Some(1672531200000 - 30 * 60 * 1000)),
+            ("10S AGO", Some(1672531200000 - 10 * 1000)),
+            ("-1d ago", None),
+            ("1w ago", None),
+            ("d ago", None),
+            ("99999999999999999d", None),
+        ] {
+            assert_eq!(parse_relative_time(input, now), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn test_collect_options_from_args_with_relative_time() {
+        let out = [c"IMSI '441200000050000'", c"FROM '1d ago'", c"TO '0s ago'"].map(|s| s.as_ptr());
+
+        unsafe {
+            let before = Utc::now().timestamp_millis();
+            let args = collect_options_from_args(3, out.as_ptr()).unwrap();
+            let after = Utc::now().timestamp_millis();
+            assert!((before - 24 * 60 * 60 * 1000..=after).contains(&args.from));
+            assert!(args.from <= after - 24 * 60 * 60 * 1000);
+            assert!((before..=after).contains(&args.to));
+            assert!(!args.default_window);
+
+            let out = [c"IMSI '441200000050000'", c"FROM '1 fortnight ago'"].map(|s| s.as_ptr());
+            assert!(matches!(
+                collect_options_from_args(2, out.as_ptr()),
+                Err(ArgumentError::InvalidFrom)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_collect_options_from_args_with_select_columns() {
+        let out = [c"IMSI '441200000050000'", c"SELECT_COLUMNS 'time,value'"].map(|s| s.as_ptr());
+
+        unsafe {
+            assert_eq!(
+                collect_options_from_args(2, out.as_ptr()).unwrap().columns,
+                vec![Column::Time, Column::Value]
+            );
+
+            let out =
+                [c"IMSI '441200000050000'", c"SELECT_COLUMNS 'time,imsi'"].map(|s| s.as_ptr());
+            assert!(matches!(
+                collect_options_from_args(2, out.as_ptr()),
+                Err(ArgumentError::InvalidSelectColumns)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_collect_options_from_args_with_snapshot() {
+        let out = [c"IMSI '441200000050000'", c"SNAPSHOT 'false'"].map(|s| s.as_ptr());
+
+        unsafe {
+            assert!(!collect_options_from_args(2, out.as_ptr()).unwrap().snapshot);
+            assert!(collect_options_from_args(1, out.as_ptr()).unwrap().snapshot);
+        }
+    }
+
+    #[test]
+    fn test_collect_options_from_args_with_fixture() {
+        let out = vec![
+            c"FIXTURE '/path/to/entries.json'",
+            c"FROM '1668003111681'",
+            c"TO '1668604289406'",
+        ]
+        .into_iter()
+        .map(|s| s.as_ptr())
+        .collect::<Vec<_>>();
+
+        unsafe {
+            let args = collect_options_from_args(3, out.as_ptr()).unwrap();
+            assert_eq!(args.imsi, "");
+            assert_eq!(args.fixture, Some("/path/to/entries.json".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_collect_options_from_args_with_require_data() {
+        let out = vec![c"IMSI '441200000050000'", c"REQUIRE_DATA 'True'"]
+            .into_iter()
+            .map(|s| s.as_ptr())
+            .collect::<Vec<_>>();
+
+        unsafe {
+            assert!(
+                collect_options_from_args(2, out.as_ptr())
+                    .unwrap()
+                    .require_data
+            );
+            assert!(
+                !collect_options_from_args(1, out.as_ptr())
+                    .unwrap()
+                    .require_data
+            );
+            assert!(
+                collect_options_from_args(1, out.as_ptr())
+                    .unwrap()
+                    .default_window
+            );
+        }
+    }
+
+    #[test]
+    fn test_collect_options_from_args_with_decode() {
+        let out = vec![
+            c"IMSI '441200000050000'",
+            c"DECODE 'hex,base64'",
+            c"BINARY 'hex'",
+            c"UNWRAP_JSON 'true'",
+        ]
+        .into_iter()
+        .map(|s| s.as_ptr())
+        .collect::<Vec<_>>();
+
+        unsafe {
+            let args = collect_options_from_args(4, out.as_ptr()).unwrap();
+            assert_eq!(args.decoders, vec![Decoder::Hex, Decoder::Base64]);
+            assert_eq!(args.binary_format, BinaryFormat::Hex);
+            assert!(args.unwrap_json);
+        }
+    }
 }
@@ -0,0 +1,77 @@
+//! `shsqlite_send(value, imsi, coverage)` scalar function, which sends a message to Harvest from within a
+//! query, e.g. `SELECT shsqlite_send('{"temperature":21}');` or `SELECT shsqlite_send(value) FROM buffer;`.
+//!
+//! The message is sent via HTTP from the device running SQLite, as `application/json`. With `imsi` of the
+//! SIM of the device, the function polls the data entries with the authenticated client shared with the
+//! tables, and returns the timestamp Harvest stored the message with. Otherwise it returns the time of
+//! sending, as there is no way to look up the entry without the IMSI. `NULL` isn't sent, and yields `NULL`.
+
+use crate::{
+    error::HarvestDataError,
+    harvest_data_client::{authenticate, forget_unauthorized},
+    module::{api_client, harvest_sender, value_text, SQLITE3_API},
+    sqlite3ext::{sqlite3_context, sqlite3_value},
+};
+use chrono::Utc;
+use soracom_harvest_api_client::endpoint::Endpoint;
+use std::{
+    ffi::{c_int, CString},
+    time::Duration,
+};
+
+/// Name of the function.
+pub(crate) const SHSQLITE_SEND_FUNCTION: &[u8] = b"shsqlite_send\0";
+
+/// Maximum duration to wait for the message to show up in the data entries, with `imsi`.
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[no_mangle]
+pub(crate) unsafe extern "C" fn shsqlite_send(
+    p_context: *mut sqlite3_context,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) {
+    let values = match argc {
+        0 => &[][..],
+        _ => std::slice::from_raw_parts(argv, argc as usize),
+    };
+    let mut values = values.iter().map(|v| value_text(*v));
+
+    let value = match values.next().flatten() {
+        Some(value) => value,
+        None => return ((*SQLITE3_API).result_null.unwrap())(p_context),
+    };
+    let imsi = values.next().flatten();
+    let coverage = values.next().flatten();
+
+    match send(value, imsi, coverage) {
+        Ok(time) => ((*SQLITE3_API).result_int64.unwrap())(p_context, time),
+        Err(err) => {
+            let message = CString::new(err).unwrap_or_default();
+            ((*SQLITE3_API).result_error.unwrap())(p_context, message.as_ptr(), -1)
+        }
+    }
+}
+
+/// Sends the message, and returns the timestamp of the data entry if `imsi` is given, or the time of sending.
+fn send(value: String, imsi: Option<String>, coverage: Option<String>) -> Result<i64, String> {
+    let imsi = match imsi {
+        Some(imsi) => imsi,
+        None => {
+            let time = Utc::now().timestamp_millis();
+            harvest_sender()
+                .send_http(value)
+                .map_err(HarvestDataError::from)?;
+            return Ok(time);
+        }
+    };
+
+    let endpoint = coverage.map_or_else(Endpoint::default, Endpoint::from);
+    let (client, auth_cache_key) = api_client(endpoint).map_err(|e| e.to_string())?;
+    let client = authenticate(&client, Some(auth_cache_key)).map_err(HarvestDataError::from)?;
+
+    Ok(client
+        .send_and_verify(imsi, value, VERIFY_TIMEOUT)
+        .map_err(HarvestDataError::from)
+        .inspect_err(|e| forget_unauthorized(Some(auth_cache_key), e))?)
+}
@@ -0,0 +1,309 @@
+//! `shsqlite_tail` table-valued function, which returns data entries newer than the given timestamp for
+//! incremental polling, e.g. `SELECT * FROM shsqlite_tail('44120xxxxxxxxxx', 1669024325202);`.
+//!
+//! Unlike the `shsqlite` virtual table which keeps the data loaded by the first query, the function
+//! fetches the data each time it is queried, following pagination so that no entry newer than `since` is
+//! missed, however many arrived since the last poll.
+
+use crate::{
+    error::error_to_sqlite3_string,
+    harvest_data_client::{HarvestDataClient, HarvestDataReader},
+    module::{
+        api_client, declare_table, value_text, yield_cell_value, yield_text, Module, SQLITE3_API,
+    },
+    sqlite3ext::{
+        sqlite3, sqlite3_context, sqlite3_index_info, sqlite3_int64, sqlite3_module, sqlite3_value,
+        sqlite3_vtab, sqlite3_vtab_cursor, SQLITE_CONSTRAINT, SQLITE_ERROR,
+        SQLITE_INDEX_CONSTRAINT_EQ, SQLITE_OK,
+    },
+};
+use chrono::Utc;
+use soracom_harvest_api_client::endpoint::Endpoint;
+use std::ffi::{c_char, c_int, c_longlong, c_void};
+
+/// Index of the first argument column, `imsi`, followed by `since` and `coverage`.
+const FIRST_ARGUMENT_COLUMN: c_int = 3;
+
+/// Number of the argument columns.
+const ARGUMENTS: usize = 3;
+
+pub(crate) const SHSQLITE_TAIL_MODULE: Module = Module {
+    base: sqlite3_module {
+        iVersion: 0,
+        // eponymous-only, i.e. can't be created with `CREATE VIRTUAL TABLE`
+        xCreate: None,
+        xConnect: Some(shsqlite_tail_connect),
+        xBestIndex: Some(shsqlite_tail_best_index),
+        xDisconnect: Some(shsqlite_tail_disconnect),
+        xDestroy: Some(shsqlite_tail_disconnect),
+        xOpen: Some(shsqlite_tail_open),
+        xClose: Some(shsqlite_tail_close),
+        xFilter: Some(shsqlite_tail_filter),
+        xNext: Some(shsqlite_tail_next),
+        xEof: Some(shsqlite_tail_eof),
+        xColumn: Some(shsqlite_tail_column),
+        xRowid: Some(shsqlite_tail_rowid),
+        xUpdate: None,
+        xBegin: None,
+        xSync: None,
+        xCommit: None,
+        xRollback: None,
+        xFindFunction: None,
+        xRename: None,
+        xSavepoint: None,
+        xRelease: None,
+        xRollbackTo: None,
+        xShadowName: None,
+    },
+    name: b"shsqlite_tail\0",
+};
+
+#[repr(C)]
+struct TailCursor {
+    pub base: sqlite3_vtab_cursor,
+    pub reader: HarvestDataReader,
+    pub since: i64,
+    pub coverage: String,
+}
+
+#[no_mangle]
+unsafe extern "C" fn shsqlite_tail_connect(
+    db: *mut sqlite3,
+    _p_aux: *mut c_void,
+    _argc: c_int,
+    _argv: *const *const c_char,
+    pp_vtab: *mut *mut sqlite3_vtab,
+    _pz_err: *mut *mut c_char,
+) -> c_int {
+    let result = declare_table(
+        db,
+        SQLITE3_API,
+        vec![
+            "time INTEGER".to_string(),
+            "content_type TEXT".to_string(),
+            "value TEXT".to_string(),
+            "imsi TEXT HIDDEN".to_string(),
+            "since INTEGER HIDDEN".to_string(),
+            "coverage TEXT HIDDEN".to_string(),
+        ],
+    );
+    let p_new = Box::new(sqlite3_vtab {
+        pModule: std::ptr::null_mut(),
+        nRef: 0,
+        zErrMsg: std::ptr::null_mut(),
+    });
+    *pp_vtab = Box::into_raw(p_new);
+
+    result
+}
+
+/// Passes `imsi`, `since` and `coverage` given as the arguments to `shsqlite_tail_filter`, in this order.
+/// `idxNum` is the bitmask of the given arguments.
+#[no_mangle]
+unsafe extern "C" fn shsqlite_tail_best_index(
+    _p_vtab: *mut sqlite3_vtab,
+    p_info: *mut sqlite3_index_info,
+) -> c_int {
+    let info = &mut *p_info;
+    if info.nConstraint == 0 {
+        info.estimatedCost = f64::MAX;
+        return SQLITE_OK;
+    }
+    let constraints = std::slice::from_raw_parts(info.aConstraint, info.nConstraint as usize);
+    let usages = std::slice::from_raw_parts_mut(info.aConstraintUsage, info.nConstraint as usize);
+
+    let mut arguments = [None; ARGUMENTS];
+    for (i, constraint) in constraints.iter().enumerate() {
+        let column = constraint.iColumn - FIRST_ARGUMENT_COLUMN;
+        if !(0..ARGUMENTS as c_int).contains(&column)
+            || constraint.op as c_int != SQLITE_INDEX_CONSTRAINT_EQ
+        {
+            continue;
+        }
+        if constraint.usable == 0 {
+            // a plan without IMSI can't be used, so make SQLite try another one
+            if column == 0 {
+                return SQLITE_CONSTRAINT;
+            }
+            continue;
+        }
+        arguments[column as usize] = Some(i);
+    }
+
+    let mut argv_index = 0;
+    info.idxNum = 0;
+    for (column, constraint) in arguments.iter().enumerate() {
+        if let Some(i) = constraint {
+            argv_index += 1;
+            usages[*i].argvIndex = argv_index;
+            usages[*i].omit = 1;
+            info.idxNum |= 1 << column;
+        }
+    }
+    info.estimatedCost = if arguments[0].is_some() {
+        1.0
+    } else {
+        f64::MAX
+    };
+
+    SQLITE_OK
+}
+
+#[no_mangle]
+unsafe extern "C" fn shsqlite_tail_disconnect(p_vtab: *mut sqlite3_vtab) -> c_int {
+    if !p_vtab.is_null() {
+        drop(Box::from_raw(p_vtab));
+    }
+
+    SQLITE_OK
+}
+
+#[no_mangle]
+unsafe extern "C" fn shsqlite_tail_open(
+    p_vtab: *mut sqlite3_vtab,
+    pp_cursor: *mut *mut sqlite3_vtab_cursor,
+) -> c_int {
+    let cursor = Box::new(TailCursor {
+        base: sqlite3_vtab_cursor { pVtab: p_vtab },
+        reader: HarvestDataReader::new(Vec::new()),
+        since: 0,
+        coverage: String::new(),
+    });
+    *pp_cursor = Box::into_raw(cursor) as _;
+
+    SQLITE_OK
+}
+
+#[no_mangle]
+unsafe extern "C" fn shsqlite_tail_close(p_cursor: *mut sqlite3_vtab_cursor) -> c_int {
+    if !p_cursor.is_null() {
+        drop(Box::from_raw(p_cursor as *mut TailCursor));
+    }
+
+    SQLITE_OK
+}
+
+#[no_mangle]
+unsafe extern "C" fn shsqlite_tail_filter(
+    p_cursor: *mut sqlite3_vtab_cursor,
+    idx_num: c_int,
+    _idx_str: *const c_char,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) -> c_int {
+    let cursor = &mut *(p_cursor as *mut TailCursor);
+
+    let values = match argc {
+        0 => &[][..],
+        _ => std::slice::from_raw_parts(argv, argc as usize),
+    };
+    let mut values = values.iter();
+    let mut arguments: [Option<*mut sqlite3_value>; ARGUMENTS] = [None; ARGUMENTS];
+    for (column, argument) in arguments.iter_mut().enumerate() {
+        if idx_num & (1 << column) != 0 {
+            *argument = values.next().copied();
+        }
+    }
+
+    let imsi = match arguments[0].and_then(|v| value_text(v)) {
+        Some(imsi) => imsi,
+        None => return tail_error(cursor, "shsqlite_tail requires IMSI as the first argument"),
+    };
+    cursor.since = arguments[1].map_or(0, |v| ((*SQLITE3_API).value_int64.unwrap())(v));
+    // no entry is newer than the latest timestamp
+    let Some(from) = cursor.since.checked_add(1) else {
+        cursor.reader = HarvestDataReader::new(Vec::new());
+        return SQLITE_OK;
+    };
+    cursor.coverage = arguments[2].and_then(|v| value_text(v)).unwrap_or_default();
+    let endpoint = match cursor.coverage.as_str() {
+        "" => Endpoint::default(),
+        coverage => Endpoint::from(coverage),
+    };
+
+    let (client, auth_cache_key) = match api_client(endpoint) {
+        Ok(c) => c,
+        Err(why) => return tail_error(cursor, why.to_string()),
+    };
+    let mut harvest_data = HarvestDataClient::builder()
+        .client(client)
+        .imsi(imsi)
+        .from(from)
+        .to(Utc::now().timestamp_millis())
+        // all entries since the last poll, as the latest page alone would miss older ones
+        .paginate(true)
+        .auth_cache_key(auth_cache_key)
+        .build();
+
+    match harvest_data
+        .open()
+        .and_then(|_| harvest_data.reload(i64::MIN, i64::MAX, None))
+    {
+        Ok(_) => {
+            cursor.reader = harvest_data.get_reader();
+            SQLITE_OK
+        }
+        Err(err) => tail_error(cursor, err),
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn shsqlite_tail_next(p_cursor: *mut sqlite3_vtab_cursor) -> c_int {
+    let cursor = &mut *(p_cursor as *mut TailCursor);
+    cursor.reader.move_next();
+
+    SQLITE_OK
+}
+
+#[no_mangle]
+unsafe extern "C" fn shsqlite_tail_eof(p_cursor: *mut sqlite3_vtab_cursor) -> c_int {
+    let cursor = &*(p_cursor as *mut TailCursor);
+
+    if cursor.reader.has_value() {
+        0
+    } else {
+        1
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn shsqlite_tail_column(
+    p_cursor: *mut sqlite3_vtab_cursor,
+    p_context: *mut sqlite3_context,
+    column: c_int,
+) -> c_int {
+    let cursor = &*(p_cursor as *mut TailCursor);
+
+    match column - FIRST_ARGUMENT_COLUMN {
+        0 => yield_text(p_context, SQLITE3_API, cursor.reader.get_value(3)),
+        1 => ((*SQLITE3_API).result_int64.unwrap())(p_context, cursor.since),
+        2 => yield_text(p_context, SQLITE3_API, cursor.coverage.clone()),
+        _ => yield_cell_value(
+            p_context,
+            SQLITE3_API,
+            cursor.reader.get_value(column as usize),
+        ),
+    }
+
+    SQLITE_OK
+}
+
+#[no_mangle]
+unsafe extern "C" fn shsqlite_tail_rowid(
+    p_cursor: *mut sqlite3_vtab_cursor,
+    p_rowid: *mut sqlite3_int64,
+) -> c_int {
+    let cursor = &*(p_cursor as *mut TailCursor);
+    *p_rowid = cursor.reader.get_index() as c_longlong;
+
+    SQLITE_OK
+}
+
+/// Sets the error message to the table of the cursor, and returns `SQLITE_ERROR`.
+unsafe fn tail_error(cursor: &mut TailCursor, err: impl Into<String>) -> c_int {
+    if let Some(ptr) = error_to_sqlite3_string(SQLITE3_API, err) {
+        (*cursor.base.pVtab).zErrMsg = ptr;
+    }
+
+    SQLITE_ERROR
+}
@@ -2,9 +2,13 @@
 use chrono::Utc;
 use rusqlite::{Connection, LoadExtensionGuard};
 use serde::Deserialize;
-use soracom_harvest_api_client::client::{Data, SoracomHarvestClient};
+use soracom_harvest_api_client::client::{find_duplicate_timestamps, Data, SoracomHarvestClient};
 use soracom_harvest_client::{send_http_message, send_udp_message};
-use std::{env, error::Error, path::PathBuf, thread, time::Duration};
+use std::{env, error::Error, fs, path::PathBuf, sync::Mutex, thread, time::Duration};
+
+/// Held by tests which reach the API client of the extension, as some point `LIBSHSQLITE_HARVEST_ENDPOINT`,
+/// which keys the shared authentication, to their mock server.
+static HARVEST_ENDPOINT: Mutex<()> = Mutex::new(());
 
 #[derive(Deserialize, Debug)]
 struct Config {
@@ -22,6 +26,7 @@ fn e2e() -> Result<(), Box<dyn Error>> {
         Ok(c) => c,
         Err(why) => panic!("{why}"),
     };
+    let _lock = HARVEST_ENDPOINT.lock().unwrap_or_else(|e| e.into_inner());
 
     let coverage = config.endpoint.unwrap_or_else(|| "global".to_string());
     let client: SoracomHarvestClient = SoracomHarvestClient::builder()
@@ -70,6 +75,7 @@ fn e2e() -> Result<(), Box<dyn Error>> {
         r#"{"value":"hello from extension_test.rs"}"#
     );
 
+    assert!(find_duplicate_timestamps(&harvest_data).is_empty());
     client
         .delete_data_entry(&config.imsi, harvest_data[0].time)
         .expect("Failed to delete test entry");
@@ -80,6 +86,814 @@ fn e2e() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[test]
+fn fixture() -> Result<(), Box<dyn Error>> {
+    let entries = vec![
+        Data {
+            time: 1669024327201,
+            content_type: "application/json".to_string(),
+            content: r#"{"temperature":4096}"#.to_string(),
+        },
+        Data {
+            time: 1669024325202,
+            content_type: "application/json".to_string(),
+            content: r#"{"value":"hello from extension_test.rs"}"#.to_string(),
+        },
+    ];
+    let path = env::temp_dir().join("libshsqlite_fixture_test.json");
+    fs::write(&path, serde_json::to_string(&entries)?)?;
+
+    let conn = Connection::open_in_memory()?;
+    load_extension(&conn)?;
+
+    conn.execute(
+        format!(
+            r#"CREATE VIRTUAL TABLE harvest_data USING shsqlite(FIXTURE '{}');"#,
+            path.display()
+        )
+        .as_str(),
+        (),
+    )?;
+
+    let mut stmt =
+        conn.prepare("SELECT * FROM harvest_data WHERE value ->> '$.temperature' > 10;")?;
+    let result = stmt.query_map([], |row| {
+        Ok(Data {
+            time: row.get::<_, i64>(0)?,
+            content_type: row.get(1)?,
+            content: row.get(2)?,
+        })
+    })?;
+
+    let harvest_data = result.collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(harvest_data, entries[..1]);
+
+    // a table loaded from a fixture can't delete entries
+    let err = conn
+        .execute("DELETE FROM harvest_data WHERE rowid = 0;", ())
+        .unwrap_err();
+    assert!(err.to_string().contains("read-only"));
+    // nor send new entries
+    let err = conn
+        .execute("INSERT INTO harvest_data (value) VALUES ('{}');", ())
+        .unwrap_err();
+    assert!(err.to_string().contains("read-only"));
+    // and UPDATE isn't supported
+    let err = conn
+        .execute("UPDATE harvest_data SET value = '{}';", ())
+        .unwrap_err();
+    assert!(err.to_string().contains("readonly"), "{err}");
+
+    fs::remove_file(path)?;
+
+    Ok(())
+}
+
+#[test]
+fn fixture_require_data() -> Result<(), Box<dyn Error>> {
+    let path = env::temp_dir().join("libshsqlite_fixture_require_data_test.json");
+    fs::write(&path, "[]")?;
+
+    let conn = Connection::open_in_memory()?;
+    load_extension(&conn)?;
+
+    let create = |name: &str, require_data: &str| {
+        conn.execute(
+            format!(
+                r#"CREATE VIRTUAL TABLE {name} USING shsqlite(FIXTURE '{}', REQUIRE_DATA '{require_data}');"#,
+                path.display()
+            )
+            .as_str(),
+            (),
+        )
+    };
+
+    // zero rows is a valid result by default
+    create("harvest_data", "false")?;
+    // but an error when data is required
+    let err = create("harvest_data_required", "true").unwrap_err();
+    assert!(err.to_string().contains("No data entries found"));
+
+    // a misspelled option is an error rather than ignored
+    let err = conn
+        .execute(
+            "CREATE VIRTUAL TABLE harvest_data_typo USING shsqlite(IMSI '441200000050000', COVRAGE 'japan');",
+            (),
+        )
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("Unknown option is provided: COVRAGE 'japan'"));
+
+    fs::remove_file(path)?;
+
+    Ok(())
+}
+
+#[test]
+fn select_columns() -> Result<(), Box<dyn Error>> {
+    let entries = vec![Data {
+        time: 1669024325202,
+        content_type: "application/json".to_string(),
+        content: r#"{"value":"hello"}"#.to_string(),
+    }];
+    let path = env::temp_dir().join("libshsqlite_select_columns_test.json");
+    fs::write(&path, serde_json::to_string(&entries)?)?;
+
+    let conn = Connection::open_in_memory()?;
+    load_extension(&conn)?;
+
+    conn.execute(
+        format!(
+            r#"CREATE VIRTUAL TABLE harvest_data USING shsqlite(FIXTURE '{}', SELECT_COLUMNS 'value,time');"#,
+            path.display()
+        )
+        .as_str(),
+        (),
+    )?;
+
+    let mut stmt = conn.prepare("SELECT * FROM harvest_data;")?;
+    assert_eq!(stmt.column_names(), vec!["value", "time"]);
+    let row = stmt.query_row([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+    })?;
+    assert_eq!(row, (entries[0].content.clone(), entries[0].time));
+
+    // the hidden imsi column follows the selected columns
+    let imsi = conn.query_row("SELECT imsi FROM harvest_data;", [], |row| {
+        row.get::<_, String>(0)
+    })?;
+    assert_eq!(imsi, "");
+
+    fs::remove_file(path)?;
+
+    Ok(())
+}
+
+#[test]
+fn time_constraints() -> Result<(), Box<dyn Error>> {
+    let entries = [1669024327201, 1669024326000, 1669024325202]
+        .into_iter()
+        .map(|time| Data {
+            time,
+            content_type: "application/json".to_string(),
+            content: "{}".to_string(),
+        })
+        .collect::<Vec<_>>();
+    let path = env::temp_dir().join("libshsqlite_time_constraints_test.json");
+    fs::write(&path, serde_json::to_string(&entries)?)?;
+
+    let conn = Connection::open_in_memory()?;
+    load_extension(&conn)?;
+    conn.execute(
+        format!(
+            r#"CREATE VIRTUAL TABLE harvest_data USING shsqlite(FIXTURE '{}');"#,
+            path.display()
+        )
+        .as_str(),
+        (),
+    )?;
+
+    let times = |condition: &str| -> rusqlite::Result<Vec<i64>> {
+        conn.prepare(&format!("SELECT time FROM harvest_data WHERE {condition};"))?
+            .query_map([], |row| row.get(0))?
+            .collect()
+    };
+    assert_eq!(times("time > 1669024326000")?, vec![1669024327201]);
+    assert_eq!(
+        times("time >= 1669024326000")?,
+        vec![1669024327201, 1669024326000]
+    );
+    assert_eq!(
+        times("time BETWEEN 1669024325202 AND 1669024326000")?,
+        vec![1669024326000, 1669024325202]
+    );
+    assert_eq!(
+        times("time < 1669024326000.5")?,
+        vec![1669024326000, 1669024325202]
+    );
+    assert_eq!(times("time = 1669024326000")?, vec![1669024326000]);
+    assert_eq!(
+        times("time > 1669024326000 AND time < 1669024326000")?,
+        Vec::<i64>::new()
+    );
+    assert_eq!(times("time > '1669024326000'")?, vec![1669024327201]);
+
+    fs::remove_file(path)?;
+
+    Ok(())
+}
+
+#[test]
+fn time_constraints_with_snapshot() -> Result<(), Box<dyn Error>> {
+    let mut server = mockito::Server::new();
+    mock_auth(&mut server, "").create();
+    // the bounds of the first query narrow the search range of the table
+    let fetch = server
+        .mock("GET", "/v1/data/Subscriber/441200000050000")
+        .match_query(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::UrlEncoded("from".into(), "1669024327000".into()),
+            mockito::Matcher::UrlEncoded("to".into(), "1669024328000".into()),
+        ]))
+        .with_body(
+            r#"[{"time":1669024327201,"contentType":"application/json","content":"{\"temperature\":4096}"}]"#,
+        )
+        .expect(1)
+        .create();
+    set_dummy_credentials();
+    let _lock = HARVEST_ENDPOINT.lock().unwrap_or_else(|e| e.into_inner());
+
+    let conn = Connection::open_in_memory()?;
+    load_extension(&conn)?;
+
+    conn.execute(
+        format!(
+            r#"CREATE VIRTUAL TABLE harvest_data USING shsqlite(IMSI '441200000050000', FROM '1669024325000', TO '1669024328000', COVERAGE '{}');"#,
+            server.url()
+        )
+        .as_str(),
+        (),
+    )?;
+
+    // and later queries in the loaded range read the data loaded for the first one
+    for condition in ["time >= 1669024327000", "time > 1669024327100"] {
+        let temperature: i64 = conn.query_row(
+            &format!("SELECT value ->> '$.temperature' FROM harvest_data WHERE {condition};"),
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(temperature, 4096);
+    }
+
+    fetch.assert();
+
+    Ok(())
+}
+
+#[test]
+fn time_constraints_without_snapshot() -> Result<(), Box<dyn Error>> {
+    let mut server = mockito::Server::new();
+    mock_auth(&mut server, "").create();
+    // the bounds narrow the search range of the table
+    let fetch = server
+        .mock("GET", "/v1/data/Subscriber/441200000050000")
+        .match_query(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::UrlEncoded("from".into(), "1669024326000".into()),
+            mockito::Matcher::UrlEncoded("to".into(), "1669024328000".into()),
+        ]))
+        .with_body(
+            r#"[{"time":1669024327201,"contentType":"application/json","content":"{\"temperature\":4096}"}]"#,
+        )
+        .expect(2)
+        .create();
+    set_dummy_credentials();
+    let _lock = HARVEST_ENDPOINT.lock().unwrap_or_else(|e| e.into_inner());
+
+    let conn = Connection::open_in_memory()?;
+    load_extension(&conn)?;
+
+    conn.execute(
+        format!(
+            r#"CREATE VIRTUAL TABLE harvest_data USING shsqlite(IMSI '441200000050000', FROM '1669024325000', TO '1669024328000', COVERAGE '{}', SNAPSHOT 'false');"#,
+            server.url()
+        )
+        .as_str(),
+        (),
+    )?;
+
+    // each scan loads the data again
+    for _ in 0..2 {
+        let temperature: i64 = conn.query_row(
+            "SELECT value ->> '$.temperature' FROM harvest_data WHERE time >= 1669024326000;",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(temperature, 4096);
+    }
+
+    fetch.assert();
+
+    Ok(())
+}
+
+#[test]
+fn limit_without_snapshot() -> Result<(), Box<dyn Error>> {
+    let mut server = mockito::Server::new();
+    mock_auth(&mut server, "").create();
+    // LIMIT and OFFSET of the query are pushed down to the request
+    let limited = server
+        .mock("GET", "/v1/data/Subscriber/441200000050000")
+        .match_query(mockito::Matcher::UrlEncoded("limit".into(), "3".into()))
+        .with_body(
+            r#"[
+                {"time":1669024327201,"contentType":"application/json","content":"{\"temperature\":4096}"},
+                {"time":1669024326202,"contentType":"application/json","content":"{\"temperature\":2048}"},
+                {"time":1669024325202,"contentType":"application/json","content":"{\"temperature\":1024}"}
+            ]"#,
+        )
+        .expect(1)
+        .create();
+    // but not beyond the limit of the table, nor with other constraints which SQLite checks after the scan
+    let unlimited = server
+        .mock("GET", "/v1/data/Subscriber/441200000050000")
+        .match_query(mockito::Matcher::UrlEncoded("limit".into(), "100".into()))
+        .with_body("[]")
+        .expect(2)
+        .create();
+    set_dummy_credentials();
+    let _lock = HARVEST_ENDPOINT.lock().unwrap_or_else(|e| e.into_inner());
+
+    let conn = Connection::open_in_memory()?;
+    load_extension(&conn)?;
+
+    conn.execute(
+        format!(
+            r#"CREATE VIRTUAL TABLE harvest_data USING shsqlite(IMSI '441200000050000', COVERAGE '{}', SNAPSHOT 'false');"#,
+            server.url()
+        )
+        .as_str(),
+        (),
+    )?;
+
+    let temperature: i64 = conn.query_row(
+        "SELECT value ->> '$.temperature' FROM harvest_data LIMIT 1 OFFSET 2;",
+        [],
+        |row| row.get(0),
+    )?;
+    assert_eq!(temperature, 1024);
+    limited.assert();
+
+    let mut stmt = conn.prepare("SELECT * FROM harvest_data LIMIT 1000;")?;
+    assert_eq!(stmt.query_map([], |_| Ok(()))?.count(), 0);
+    let mut stmt =
+        conn.prepare("SELECT * FROM harvest_data WHERE value ->> '$.temperature' > 2048 LIMIT 1;")?;
+    assert_eq!(stmt.query_map([], |_| Ok(()))?.count(), 0);
+    unlimited.assert();
+
+    Ok(())
+}
+
+#[test]
+fn insert_sends_data() -> Result<(), Box<dyn Error>> {
+    let mut server = mockito::Server::new();
+    mock_auth(&mut server, "").create();
+    let fetch = server
+        .mock("GET", "/v1/data/Subscriber/441200000050000")
+        .match_query(mockito::Matcher::Any)
+        .with_body("[]")
+        .expect(1)
+        .create();
+    let json = server
+        .mock("POST", "/")
+        .match_header("content-type", "application/json")
+        .match_body(r#"{"t":20}"#)
+        .expect(2)
+        .create();
+    let csv = server
+        .mock("POST", "/")
+        .match_header("content-type", "text/csv")
+        .match_body("20,60")
+        .create();
+    set_dummy_credentials();
+    let _lock = HARVEST_ENDPOINT.lock().unwrap_or_else(|e| e.into_inner());
+    env::set_var("LIBSHSQLITE_HARVEST_ENDPOINT", server.url());
+
+    let conn = Connection::open_in_memory()?;
+    load_extension(&conn)?;
+
+    conn.execute(
+        format!(
+            r#"CREATE VIRTUAL TABLE harvest_data USING shsqlite(IMSI '441200000050000', COVERAGE '{}');"#,
+            server.url()
+        )
+        .as_str(),
+        (),
+    )?;
+    let count: i64 = conn.query_row("SELECT count(*) FROM harvest_data;", [], |row| row.get(0))?;
+    assert_eq!(count, 0);
+
+    conn.execute(
+        r#"INSERT INTO harvest_data (content_type, value) VALUES ('application/json', '{"t":20}'), ('text/csv', '20,60');"#,
+        (),
+    )?;
+    conn.execute(
+        r#"INSERT INTO harvest_data (value) VALUES ('{"t":20}');"#,
+        (),
+    )?;
+    // the time of sending, rather than the time Harvest assigns
+    assert!(conn.last_insert_rowid() > 1669024325202);
+    json.assert();
+    csv.assert();
+
+    // Harvest assigns the timestamp
+    let err = conn
+        .execute(
+            r#"INSERT INTO harvest_data (time, value) VALUES (1669024325202, '{"t":20}');"#,
+            (),
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("Invalid INSERT"), "{err}");
+
+    // the table is still the snapshot loaded before the INSERT
+    let count: i64 = conn.query_row("SELECT count(*) FROM harvest_data;", [], |row| row.get(0))?;
+    assert_eq!(count, 0);
+    fetch.assert();
+
+    Ok(())
+}
+
+#[test]
+fn send_function() -> Result<(), Box<dyn Error>> {
+    let mut server = mockito::Server::new();
+    mock_auth(&mut server, "").create();
+    let send = server
+        .mock("POST", "/")
+        .match_body(r#"{"temperature":21}"#)
+        .expect(2)
+        .create();
+    server
+        .mock("POST", "/")
+        .match_body("rejected")
+        .with_status(400)
+        .create();
+    server
+        .mock("GET", "/v1/data/Subscriber/441200000050000")
+        .match_query(mockito::Matcher::Any)
+        .with_body(
+            r#"[{"time":1669024327201,"contentType":"application/json","content":"{\"temperature\":21}"}]"#,
+        )
+        .create();
+    set_dummy_credentials();
+    let _lock = HARVEST_ENDPOINT.lock().unwrap_or_else(|e| e.into_inner());
+    env::set_var("LIBSHSQLITE_HARVEST_ENDPOINT", server.url());
+
+    let conn = Connection::open_in_memory()?;
+    load_extension(&conn)?;
+
+    // NULL isn't sent
+    let time: Option<i64> = conn.query_row("SELECT shsqlite_send(NULL);", [], |row| row.get(0))?;
+    assert_eq!(time, None);
+
+    // without IMSI, the time of sending
+    let before = Utc::now().timestamp_millis();
+    let time: i64 = conn.query_row(
+        r#"SELECT shsqlite_send('{"temperature":21}');"#,
+        [],
+        |row| row.get(0),
+    )?;
+    assert!((before..=Utc::now().timestamp_millis()).contains(&time));
+
+    // with IMSI, the timestamp of the stored entry
+    let time: i64 = conn.query_row(
+        r#"SELECT shsqlite_send('{"temperature":21}', '441200000050000', ?1);"#,
+        [server.url()],
+        |row| row.get(0),
+    )?;
+    assert_eq!(time, 1669024327201);
+    send.assert();
+
+    let err = conn
+        .query_row("SELECT shsqlite_send('rejected');", [], |row| {
+            row.get::<_, i64>(0)
+        })
+        .unwrap_err();
+    assert!(err.to_string().starts_with("Failed to send"), "{err}");
+
+    Ok(())
+}
+
+#[test]
+fn snapshot_fetches_once() -> Result<(), Box<dyn Error>> {
+    let mut server = mockito::Server::new();
+    mock_auth(&mut server, "").create();
+    let fetch = server
+        .mock("GET", "/v1/data/Subscriber/441200000050000")
+        .match_query(mockito::Matcher::Any)
+        .with_body(
+            r#"[
+                {"time":1669024327201,"contentType":"application/json","content":"{\"temperature\":4096}"},
+                {"time":1669024325202,"contentType":"application/json","content":"{\"temperature\":2048}"}
+            ]"#,
+        )
+        .expect(1)
+        .create();
+    set_dummy_credentials();
+    let _lock = HARVEST_ENDPOINT.lock().unwrap_or_else(|e| e.into_inner());
+
+    let conn = Connection::open_in_memory()?;
+    load_extension(&conn)?;
+
+    conn.execute(
+        format!(
+            r#"CREATE VIRTUAL TABLE harvest_data USING shsqlite(IMSI '441200000050000', FROM '1669024325000', TO '1669024328000', COVERAGE '{}');"#,
+            server.url()
+        )
+        .as_str(),
+        (),
+    )?;
+    conn.execute("CREATE TABLE snapshot AS SELECT * FROM harvest_data;", ())?;
+
+    // multiple cursors over the virtual table read the same data loaded at creation
+    let pairs: i64 = conn.query_row(
+        "SELECT count(*) FROM harvest_data a, harvest_data b;",
+        [],
+        |row| row.get(0),
+    )?;
+    assert_eq!(pairs, 4);
+    let diff: i64 = conn.query_row(
+        "SELECT count(*) FROM (SELECT * FROM harvest_data EXCEPT SELECT * FROM snapshot);",
+        [],
+        |row| row.get(0),
+    )?;
+    assert_eq!(diff, 0);
+
+    fetch.assert();
+
+    Ok(())
+}
+
+#[test]
+fn tables_share_auth() -> Result<(), Box<dyn Error>> {
+    // with a path in the endpoint, so that it isn't shared with other tests using the same mock server
+    let mut server = mockito::Server::new();
+    let auth = mock_auth(&mut server, "/tables_share_auth")
+        .expect(1)
+        .create();
+    let fetch = server
+        .mock(
+            "GET",
+            "/tables_share_auth/v1/data/Subscriber/441200000050000",
+        )
+        .match_query(mockito::Matcher::Any)
+        .match_header("x-soracom-token", "token")
+        .with_body("[]")
+        .expect(3)
+        .create();
+    set_dummy_credentials();
+    let _lock = HARVEST_ENDPOINT.lock().unwrap_or_else(|e| e.into_inner());
+
+    let conn = Connection::open_in_memory()?;
+    load_extension(&conn)?;
+
+    for (name, from) in [
+        ("a", 1669024325000i64),
+        ("b", 1669024326000),
+        ("c", 1669024327000),
+    ] {
+        conn.execute(
+            format!(
+                r#"CREATE VIRTUAL TABLE {name} USING shsqlite(IMSI '441200000050000', FROM '{from}', TO '1669024328000', COVERAGE '{}/tables_share_auth');"#,
+                server.url()
+            )
+            .as_str(),
+            (),
+        )?;
+        conn.query_row(&format!("SELECT count(*) FROM {name};"), [], |_| Ok(()))?;
+    }
+
+    auth.assert();
+    fetch.assert();
+
+    Ok(())
+}
+
+#[test]
+fn imsi_prefix() -> Result<(), Box<dyn Error>> {
+    let mut server = mockito::Server::new();
+    mock_auth(&mut server, "/imsi_prefix").create();
+    server
+        .mock("GET", "/imsi_prefix/v1/subscribers")
+        .match_query(mockito::Matcher::Any)
+        .with_body(
+            r#"[{"imsi":"441200000050000"},{"imsi":"441200000050001"},{"imsi":"440100000000001"}]"#,
+        )
+        .create();
+    for (imsi, time) in [
+        ("441200000050000", 1669024325202i64),
+        ("441200000050001", 1669024327201),
+    ] {
+        server
+            .mock(
+                "GET",
+                format!("/imsi_prefix/v1/data/Subscriber/{imsi}").as_str(),
+            )
+            .match_query(mockito::Matcher::Any)
+            .with_body(format!(
+                r#"[{{"time":{time},"contentType":"application/json","content":"{{}}"}}]"#
+            ))
+            .expect(1)
+            .create();
+    }
+    set_dummy_credentials();
+    let _lock = HARVEST_ENDPOINT.lock().unwrap_or_else(|e| e.into_inner());
+
+    let conn = Connection::open_in_memory()?;
+    load_extension(&conn)?;
+
+    conn.execute(
+        format!(
+            r#"CREATE VIRTUAL TABLE harvest_data USING shsqlite(IMSI '44120%', FROM '1669024325000', TO '1669024328000', COVERAGE '{}/imsi_prefix');"#,
+            server.url()
+        )
+        .as_str(),
+        (),
+    )?;
+
+    let mut stmt = conn.prepare("SELECT time, imsi FROM harvest_data;")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(
+        rows,
+        vec![
+            (1669024327201, "441200000050001".to_string()),
+            (1669024325202, "441200000050000".to_string()),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn tail() -> Result<(), Box<dyn Error>> {
+    let mut server = mockito::Server::new();
+    mock_auth(&mut server, "/tail").create();
+    let fetch = server
+        .mock("GET", "/tail/v1/data/Subscriber/441200000050000")
+        .match_query(mockito::Matcher::UrlEncoded(
+            "from".into(),
+            "1669024325203".into(),
+        ))
+        .with_body(
+            r#"[{"time":1669024327201,"contentType":"application/json","content":"{\"temperature\":4096}"}]"#,
+        )
+        .expect(2)
+        .create();
+    set_dummy_credentials();
+    let _lock = HARVEST_ENDPOINT.lock().unwrap_or_else(|e| e.into_inner());
+
+    let conn = Connection::open_in_memory()?;
+    load_extension(&conn)?;
+
+    // fetched on each query
+    for _ in 0..2 {
+        let (time, value, imsi): (i64, String, String) = conn.query_row(
+            &format!(
+                "SELECT time, value, imsi FROM shsqlite_tail('441200000050000', 1669024325202, '{}/tail');",
+                server.url()
+            ),
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        assert_eq!(time, 1669024327201);
+        assert_eq!(value, r#"{"temperature":4096}"#);
+        assert_eq!(imsi, "441200000050000");
+    }
+
+    // nothing is newer than the latest timestamp, so not fetched
+    let count: i64 = conn.query_row(
+        &format!(
+            "SELECT count(*) FROM shsqlite_tail('441200000050000', {}, '{}/tail');",
+            i64::MAX,
+            server.url()
+        ),
+        [],
+        |row| row.get(0),
+    )?;
+    assert_eq!(count, 0);
+    fetch.assert();
+
+    let err = conn
+        .query_row("SELECT * FROM shsqlite_tail();", [], |_| Ok(()))
+        .unwrap_err();
+    assert!(err.to_string().contains("requires IMSI"));
+
+    Ok(())
+}
+
+#[test]
+fn delete_entries() -> Result<(), Box<dyn Error>> {
+    let mut server = mockito::Server::new();
+    mock_auth(&mut server, "").create();
+    server
+        .mock("GET", "/v1/data/Subscriber/441200000050002")
+        .match_query(mockito::Matcher::Any)
+        .with_body(
+            r#"[
+                {"time":1669024327201,"contentType":"application/json","content":"{}"},
+                {"time":1669024325202,"contentType":"application/json","content":"{}"}
+            ]"#,
+        )
+        .create();
+    // the rowid is mapped to the timestamp of the entry
+    let delete = server
+        .mock(
+            "DELETE",
+            "/v1/data/Subscriber/441200000050002/1669024325202",
+        )
+        .with_status(204)
+        .expect(1)
+        .create();
+    let forbidden = server
+        .mock(
+            "DELETE",
+            "/v1/data/Subscriber/441200000050002/1669024327201",
+        )
+        .with_status(403)
+        .expect(1)
+        .create();
+    set_dummy_credentials();
+    let _lock = HARVEST_ENDPOINT.lock().unwrap_or_else(|e| e.into_inner());
+
+    let conn = Connection::open_in_memory()?;
+    load_extension(&conn)?;
+
+    conn.execute(
+        format!(
+            r#"CREATE VIRTUAL TABLE harvest_data USING shsqlite(IMSI '441200000050002', COVERAGE '{}');"#,
+            server.url()
+        )
+        .as_str(),
+        (),
+    )?;
+
+    conn.execute("DELETE FROM harvest_data WHERE rowid = 1;", ())?;
+    delete.assert();
+
+    // a rejected delete fails the statement
+    let err = conn
+        .execute("DELETE FROM harvest_data WHERE rowid = 0;", ())
+        .unwrap_err();
+    assert!(err.to_string().contains("status 403"), "{err}");
+    forbidden.assert();
+
+    Ok(())
+}
+
+#[test]
+fn tail_paginates() -> Result<(), Box<dyn Error>> {
+    let mut server = mockito::Server::new();
+    mock_auth(&mut server, "").create();
+    // more entries than a page since the last poll, the oldest of which are on the second page
+    let first = server
+        .mock("GET", "/v1/data/Subscriber/441200000050001")
+        .match_query(mockito::Matcher::Any)
+        .with_header("x-soracom-next-key", "1669024325204")
+        .with_body(
+            r#"[
+                {"time":1669024325205,"contentType":"application/json","content":"{}"},
+                {"time":1669024325204,"contentType":"application/json","content":"{}"}
+            ]"#,
+        )
+        .expect(1)
+        .create();
+    let second = server
+        .mock("GET", "/v1/data/Subscriber/441200000050001")
+        .match_query(mockito::Matcher::UrlEncoded(
+            "last_evaluated_key".into(),
+            "1669024325204".into(),
+        ))
+        .with_body(r#"[{"time":1669024325203,"contentType":"application/json","content":"{}"}]"#)
+        .expect(1)
+        .create();
+    set_dummy_credentials();
+    let _lock = HARVEST_ENDPOINT.lock().unwrap_or_else(|e| e.into_inner());
+
+    let conn = Connection::open_in_memory()?;
+    load_extension(&conn)?;
+
+    let mut stmt =
+        conn.prepare("SELECT time FROM shsqlite_tail('441200000050001', 1669024325202, ?1);")?;
+    let times = stmt
+        .query_map([server.url()], |row| row.get::<_, i64>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(times, [1669024325205, 1669024325204, 1669024325203]);
+    first.assert();
+    second.assert();
+
+    Ok(())
+}
+
+/// Returns a mock of the authentication API under the path e.g. `/tail`, or the root if empty, to be created.
+#[cfg(test)]
+fn mock_auth(server: &mut mockito::Server, path: &str) -> mockito::Mock {
+    server
+        .mock("POST", format!("{path}/v1/auth").as_str())
+        .with_body(r#"{"apiKey":"api-key","token":"token"}"#)
+}
+
+/// Sets dummy credentials for the extension to authenticate with a mock server, unless they are given.
+#[cfg(test)]
+fn set_dummy_credentials() {
+    for key in ["LIBSHSQLITE_AUTH_KEY_ID", "LIBSHSQLITE_AUTH_KEY_SECRET"] {
+        if env::var(key).is_err() {
+            env::set_var(key, "dummy");
+        }
+    }
+}
+
 #[cfg(test)]
 fn load_extension(conn: &Connection) -> rusqlite::Result<()> {
     let path_buf: PathBuf = [